@@ -1,6 +1,5 @@
-use lambda_http::{self, http::StatusCode, service_fn, Error, IntoResponse, Request, RequestExt};
-use serde_json::json;
-use shared::utils::{api_helper::ApiHelper};
+use api_one::execute;
+use lambda_http::{self, service_fn, Error, Request};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -17,19 +16,3 @@ async fn main() -> Result<(), Error> {
     lambda_http::run(service_fn(|event: Request| execute(event))).await?;
     Ok(())
 }
-
-pub async fn execute(
-    event: Request,
-) -> Result<impl IntoResponse, Error> {
-    println!("{:?}", event);
-
-    // read the payload
-    // let request = event.payload::<MyStruct>()?.unwrap();
-    // do something with the payload
-
-    Ok(ApiHelper::response(
-        StatusCode::OK,
-        json!({ "message": "authorized" }).to_string(),
-        "application/json".to_string(),
-    ))
-}
@@ -0,0 +1,17 @@
+use lambda_http::{self, http::StatusCode, Error, Request, Response};
+use serde_json::json;
+use shared::utils::api_helper::ApiHelper;
+
+pub async fn execute(event: Request) -> Result<Response<String>, Error> {
+    println!("{:?}", event);
+
+    // read the payload
+    // let request = event.payload::<MyStruct>()?.unwrap();
+    // do something with the payload
+
+    Ok(ApiHelper::response(
+        StatusCode::OK,
+        json!({ "message": "authorized" }).to_string(),
+        "application/json".to_string(),
+    ))
+}
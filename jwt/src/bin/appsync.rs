@@ -0,0 +1,513 @@
+use aws_lambda_events::event::appsync::{
+    AppSyncLambdaAuthorizerRequest, AppSyncLambdaAuthorizerResponse,
+};
+use lambda_request_authorizer::{
+    queries::get_operation_rule::OperationRule,
+    utils::{
+        decision::{DecisionEngine, DecisionRequest},
+        graphql::GraphQL,
+        injections::appsync_di::{AppSyncAppClient, AppSyncInitialisation, OperationRules},
+    },
+};
+use lambda_runtime::{self, service_fn, Error, LambdaEvent};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_ansi(false)
+        .without_time()
+        .with_max_level(tracing_subscriber::filter::LevelFilter::INFO)
+        .init();
+
+    let config = aws_config::load_from_env().await;
+    let app_client = AppSyncAppClient::from_env(&config)
+        .await
+        .expect("the authorizer must be configured");
+
+    lambda_runtime::run(service_fn(
+        |event: LambdaEvent<AppSyncLambdaAuthorizerRequest>| execute(&app_client, event),
+    ))
+    .await?;
+    Ok(())
+}
+
+pub async fn execute(
+    app_client: &dyn AppSyncInitialisation,
+    event: LambdaEvent<AppSyncLambdaAuthorizerRequest>,
+) -> Result<AppSyncLambdaAuthorizerResponse, Error> {
+    println!("event {:?}", redacted(&event.payload));
+    let request_context = event.payload.request_context;
+    let operation = GraphQL::find_operation(
+        &request_context.query_string.unwrap_or_default(),
+        request_context.operation_name.as_deref(),
+    )
+    .ok()
+    .flatten();
+    let operation = match operation {
+        Some(operation) => operation,
+        None => return Ok(unauthorized()),
+    };
+    let rules = app_client.get_operation_rules(&operation).await?;
+    if rules.is_empty() {
+        return Ok(unauthorized());
+    }
+
+    // every matching rule must grant, a type-wide rule never widens a stricter operation rule
+    let operation_rules = OperationRules {
+        app_client,
+        rules: &rules,
+    };
+    for rule in &rules {
+        let request = to_decision_request(rule, event.payload.authorization_token.as_deref());
+        let decision = DecisionEngine::decide(&operation_rules, &request).await?;
+        println!("decision {} {:?}", rule.pk, decision);
+        if !decision.is_allowed() {
+            return Ok(unauthorized());
+        }
+    }
+
+    let mut denied_fields: Vec<String> = Vec::new();
+    let mut resolver_context: HashMap<String, Value> = HashMap::new();
+    for rule in rules {
+        for field in rule.denied_fields {
+            if !denied_fields.contains(&field) {
+                denied_fields.push(field);
+            }
+        }
+        resolver_context.extend(
+            rule.resolver_context
+                .into_iter()
+                .map(|(key, value)| (key, Value::String(value))),
+        );
+    }
+
+    Ok(AppSyncLambdaAuthorizerResponse {
+        is_authorized: true,
+        resolver_context,
+        denied_fields: Some(denied_fields).filter(|x| !x.is_empty()),
+        ttl_override: None,
+    })
+}
+
+/// The rule as a route of the `DecisionEngine`, `QUERY/getPost` being `QUERY` on `/getPost`.
+fn to_decision_request(rule: &OperationRule, authorization_token: Option<&str>) -> DecisionRequest {
+    let (method, path) = rule.pk.split_once('/').unwrap_or((rule.pk.as_str(), ""));
+    let mut headers = HashMap::new();
+    if let Some(token) = authorization_token {
+        headers.insert("authorization".to_string(), token.to_string());
+    }
+
+    DecisionRequest::builder()
+        .method(method)
+        .path(format!("/{}", path))
+        .headers(headers)
+        .build()
+}
+
+/// The request to log, without the token.
+fn redacted(request: &AppSyncLambdaAuthorizerRequest) -> AppSyncLambdaAuthorizerRequest {
+    AppSyncLambdaAuthorizerRequest {
+        authorization_token: request
+            .authorization_token
+            .as_ref()
+            .map(|_| "***".to_string()),
+        ..request.clone()
+    }
+}
+
+fn unauthorized() -> AppSyncLambdaAuthorizerResponse {
+    AppSyncLambdaAuthorizerResponse {
+        is_authorized: false,
+        resolver_context: HashMap::new(),
+        denied_fields: None,
+        ttl_override: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use aws_lambda_events::apigw::ApiGatewayCustomAuthorizerResponse;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Context;
+    use lambda_http::Response;
+    use lambda_request_authorizer::queries::get_operation_rule::GetOperationRule;
+    use lambda_request_authorizer::{
+        queries::{
+            cached_batch_get::CachedBatchGet, get_operation_rule::OperationRule,
+            principal_status::PrincipalStatus, revocation_list::RevocationList,
+        },
+        utils::{
+            authorizer::{Authorizer, Claims},
+            graphql::Operation,
+            injections::jwt_di::JWTInitialisation,
+            introspection::Introspection,
+            route_rule::RoutePolicy,
+            token_source::{TokenSource, TokenSources},
+        },
+    };
+    use mockall::mock;
+    use shared::{error::ApplicationError, utils::unit_tests_helper::UnitTestHelper};
+
+    mock! {
+        pub AppSyncAppClient {}
+        #[async_trait]
+        impl JWTInitialisation for AppSyncAppClient {
+            fn token_sources(&self) -> TokenSources;
+            async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError>;
+            fn to_response(&self, effect: String, principal: Option<String>, method_arn: String) -> ApiGatewayCustomAuthorizerResponse;
+            async fn get_scope_query(&self, method: &str, path: &str) -> Result<Option<RoutePolicy>, ApplicationError>;
+            async fn get_principal_status(&self, claims: &Claims) -> Result<Vec<PrincipalStatus>, ApplicationError>;
+        }
+        #[async_trait]
+        impl AppSyncInitialisation for AppSyncAppClient {
+            async fn get_operation_rules(&self, operation: &Operation) -> Result<Vec<OperationRule>, ApplicationError>;
+        }
+    }
+
+    fn get_lambda_request() -> LambdaEvent<AppSyncLambdaAuthorizerRequest> {
+        let json = r#"{
+  "authorizationToken": "token",
+  "requestContext": {
+    "apiId": "aaaaaa123123123example123",
+    "accountId": "111122223333",
+    "requestId": "f4081827-1111-4444-5555-5cf4695f339f",
+    "queryString": "mutation CreateEvent { createEvent(name: \"demo\", when: \"Next Friday!\", where: \"Here!\") { id name when where description } }",
+    "operationName": "CreateEvent",
+    "variables": {}
+  }
+}"#;
+
+        let request: AppSyncLambdaAuthorizerRequest = serde_json::from_str(json).unwrap();
+
+        LambdaEvent::new(request, Context::default())
+    }
+
+    /// The answers of a plain request, the token read like `AppSyncAppClient` and the principal having no status.
+    fn get_mock() -> MockAppSyncAppClient {
+        let mut mock = MockAppSyncAppClient::default();
+        mock.expect_token_sources().returning(get_token_sources);
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_get_scope_query().times(0);
+
+        mock
+    }
+
+    fn get_token_sources() -> TokenSources {
+        TokenSources(vec![TokenSource::Header {
+            name: "authorization".to_string(),
+            scheme: None,
+        }])
+    }
+
+    fn get_rule(pk: &str, scope: &str) -> OperationRule {
+        OperationRule {
+            pk: pk.to_string(),
+            scopes: vec![scope.to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn get_claims() -> Result<Option<Claims>, ApplicationError> {
+        let data = r#"
+                {
+          "exp": 1654242297,
+          "iss": "https://somedomain.com",
+          "aud": "my-audience",
+          "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+          "scope": "events.write",
+          "email": "a@a.com"
+        }"#;
+        let v: Value = serde_json::from_str(data)?;
+        let response: Claims = serde_json::from_value(v)?;
+        Ok(Some(response))
+    }
+
+    #[test]
+    fn redact_the_token_of_the_logged_event() {
+        // ARRANGE
+        let mut request = get_lambda_request().payload;
+        request.authorization_token = Some("Bearer secret-token".to_string());
+
+        // ACT
+        let logged = format!("{:?}", redacted(&request));
+
+        // ASSERT
+        assert!(!logged.contains("secret-token"));
+        assert!(logged.contains("CreateEvent"));
+    }
+
+    #[tokio::test]
+    async fn will_allow_with_denied_fields_and_resolver_context() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = get_mock();
+        mock.expect_validate_token()
+            .withf(|token| token == "token")
+            .times(2)
+            .returning(|_| get_claims());
+        mock.expect_get_operation_rules()
+            .withf(|operation| operation.name == "CreateEvent")
+            .times(1)
+            .returning(|_| {
+                Ok(vec![
+                    OperationRule {
+                        pk: "MUTATION/*".to_string(),
+                        scopes: vec!["events.write".to_string()],
+                        denied_fields: vec!["Event.description".to_string()],
+                        resolver_context: HashMap::from([
+                            ("tenant".to_string(), "blue".to_string()),
+                            ("tier".to_string(), "basic".to_string()),
+                        ]),
+                    },
+                    OperationRule {
+                        pk: "MUTATION/CreateEvent".to_string(),
+                        scopes: vec!["events.write".to_string()],
+                        denied_fields: vec![
+                            "Event.description".to_string(),
+                            "Event.where".to_string(),
+                        ],
+                        resolver_context: HashMap::from([(
+                            "tier".to_string(),
+                            "premium".to_string(),
+                        )]),
+                    },
+                ])
+            });
+
+        // ACT
+        let result = execute(&mock, get_lambda_request()).await?;
+
+        // ASSERT
+        assert!(result.is_authorized);
+        assert_eq!(
+            result.denied_fields,
+            Some(vec![
+                "Event.description".to_string(),
+                "Event.where".to_string()
+            ])
+        );
+        assert_eq!(
+            result.resolver_context.get("tenant"),
+            Some(&Value::from("blue"))
+        );
+        assert_eq!(
+            result.resolver_context.get("tier"),
+            Some(&Value::from("premium"))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn will_deny_when_no_rule_grants_the_token_scope() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = get_mock();
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims());
+        mock.expect_get_operation_rules().times(1).returning(|_| {
+            Ok(vec![OperationRule {
+                pk: "MUTATION/CreateEvent".to_string(),
+                scopes: vec!["events.admin".to_string()],
+                ..Default::default()
+            }])
+        });
+
+        // ACT
+        let result = execute(&mock, get_lambda_request()).await?;
+
+        // ASSERT
+        assert!(!result.is_authorized);
+        assert!(result.resolver_context.is_empty());
+        assert!(result.denied_fields.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn will_deny_when_the_type_wide_rule_grants_and_the_operation_rule_does_not(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = get_mock();
+        mock.expect_validate_token()
+            .times(2)
+            .returning(|_| get_claims());
+        mock.expect_get_operation_rules().times(1).returning(|_| {
+            Ok(vec![
+                OperationRule {
+                    pk: "MUTATION/*".to_string(),
+                    scopes: vec!["events.write".to_string()],
+                    ..Default::default()
+                },
+                OperationRule {
+                    pk: "MUTATION/CreateEvent".to_string(),
+                    scopes: vec!["events.admin".to_string()],
+                    denied_fields: vec!["Event.where".to_string()],
+                    ..Default::default()
+                },
+            ])
+        });
+
+        // ACT
+        let result = execute(&mock, get_lambda_request()).await?;
+
+        // ASSERT
+        assert!(!result.is_authorized);
+        assert!(result.denied_fields.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn will_deny_when_operation_is_not_found() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = get_mock();
+        mock.expect_validate_token().times(0);
+        mock.expect_get_operation_rules().times(0);
+
+        let mut request = get_lambda_request();
+        request.payload.request_context.operation_name = Some("DeleteEvent".to_string());
+
+        // ACT
+        let result = execute(&mock, request).await?;
+
+        // ASSERT
+        assert!(!result.is_authorized);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn will_deny_when_authorization_token_is_not_passed() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = get_mock();
+        mock.expect_validate_token().times(0);
+        mock.expect_get_operation_rules()
+            .times(1)
+            .returning(|_| Ok(vec![get_rule("MUTATION/CreateEvent", "events.write")]));
+
+        let mut request = get_lambda_request();
+        request.payload.authorization_token = None;
+
+        // ACT
+        let result = execute(&mock, request).await?;
+
+        // ASSERT
+        assert!(!result.is_authorized);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn will_deny_when_the_principal_is_suspended() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockAppSyncAppClient::default();
+        mock.expect_get_principal_status().times(1).returning(|_| {
+            Ok(vec![PrincipalStatus {
+                pk: "sub#12408bde-207d-45a5-a143-6aa02f049df7".to_string(),
+                suspended: true,
+                not_before: None,
+            }])
+        });
+        mock.expect_token_sources().returning(get_token_sources);
+        mock.expect_get_scope_query().times(0);
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims());
+        mock.expect_get_operation_rules()
+            .times(1)
+            .returning(|_| Ok(vec![get_rule("MUTATION/CreateEvent", "events.write")]));
+
+        // ACT
+        let result = execute(&mock, get_lambda_request()).await?;
+
+        // ASSERT
+        assert!(!result.is_authorized);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn will_deny_a_revoked_token() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let _m = mockito::mock("POST", "/introspect-appsync")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"active": true, "sub": "12408bde-207d-45a5-a143-6aa02f049df7", "jti": "abc", "scope": "events.write", "exp": 4102444800}"#,
+            )
+            .create();
+        let dynamodb_event = |target: &str, body: &'static str| {
+            let request = UnitTestHelper::dynamodb_request_builder()
+                .header("x-amz-target", target)
+                .body(SdkBody::empty())
+                .unwrap();
+            let response = Response::builder()
+                .status(200)
+                .body(SdkBody::from(body))
+                .unwrap();
+            (request, response)
+        };
+        let conn = TestConnection::new(vec![
+            dynamodb_event("DynamoDB_20120810.GetItem", "{}"),
+            dynamodb_event(
+                "DynamoDB_20120810.GetItem",
+                r#"{"Item": {"pk": {"S": "MUTATION/CreateEvent"}, "scopes": {"L": [{"S": "events.write"}]}}}"#,
+            ),
+            dynamodb_event(
+                "DynamoDB_20120810.BatchGetItem",
+                r#"{"Responses": {"revocations": [{"pk": {"S": "jti#abc"}, "expires_at": {"N": "4102444800"}}]}, "UnprocessedKeys": {}}"#,
+            ),
+        ]);
+        let dynamodb_client = UnitTestHelper::dynamo_fake_client(&conn).await;
+        let app_client = AppSyncAppClient::builder()
+            .authorizer(
+                Authorizer::builder()
+                    .json_key_set_url(mockito::server_url())
+                    .audience("my-audience")
+                    .issuer("https://somedomain.com")
+                    .reqwest_client(reqwest::Client::new())
+                    .introspection(Some(
+                        Introspection::builder()
+                            .endpoint(format!("{}/introspect-appsync", mockito::server_url()))
+                            .client_id("authorizer")
+                            .client_secret("secret")
+                            .audience("my-audience")
+                            .issuer("https://somedomain.com")
+                            .reqwest_client(reqwest::Client::new())
+                            .build(),
+                    ))
+                    .revocation_list(Some(
+                        RevocationList::builder()
+                            .lookup(
+                                CachedBatchGet::builder()
+                                    .table_name("revocations")
+                                    .dynamo_db_client(dynamodb_client.clone())
+                                    .build(),
+                            )
+                            .build(),
+                    ))
+                    .build(),
+            )
+            .get_operation_rule_query(
+                GetOperationRule::builder()
+                    .table_name("operations")
+                    .dynamo_db_client(dynamodb_client)
+                    .build(),
+            )
+            .build();
+
+        // ACT
+        let result = execute(&app_client, get_lambda_request()).await?;
+
+        // ASSERT
+        assert!(!result.is_authorized);
+        assert_eq!(conn.requests().len(), 3);
+
+        Ok(())
+    }
+}
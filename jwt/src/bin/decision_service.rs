@@ -0,0 +1,44 @@
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Server,
+};
+use lambda_request_authorizer::utils::{
+    decision_service::DecisionService, identity_headers::IdentityHeaders,
+    injections::jwt_di::JWTAppClient,
+};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tracing_subscriber::fmt()
+        .with_ansi(false)
+        .without_time()
+        .with_max_level(tracing_subscriber::filter::LevelFilter::INFO)
+        .init();
+
+    let address: SocketAddr = std::env::var("LISTEN_ADDRESS")
+        .unwrap_or_else(|_| "0.0.0.0:8080".to_string())
+        .parse()
+        .expect("LISTEN_ADDRESS must be a socket address");
+    let identity_headers = IdentityHeaders::from_env().expect("IDENTITY_HEADERS must be valid");
+
+    let config = aws_config::load_from_env().await;
+    let app_client = JWTAppClient::from_env(&config)
+        .await
+        .expect("the authorizer must be configured");
+
+    let decision_service = DecisionService::new(Arc::new(app_client), identity_headers);
+    let make_service = make_service_fn(move |_| {
+        let decision_service = decision_service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let decision_service = decision_service.clone();
+                async move { decision_service.handle(request).await }
+            }))
+        }
+    });
+
+    println!("decision service listening on {}", address);
+    Server::bind(&address).serve(make_service).await?;
+    Ok(())
+}
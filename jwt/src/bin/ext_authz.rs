@@ -0,0 +1,35 @@
+use envoy_types::ext_authz::v3::pb::AuthorizationServer;
+use lambda_request_authorizer::utils::{
+    ext_authz::ExtAuthzService, identity_headers::IdentityHeaders, injections::jwt_di::JWTAppClient,
+};
+use std::{net::SocketAddr, sync::Arc};
+use tonic::transport::Server;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tracing_subscriber::fmt()
+        .with_ansi(false)
+        .without_time()
+        .with_max_level(tracing_subscriber::filter::LevelFilter::INFO)
+        .init();
+
+    let address: SocketAddr = std::env::var("LISTEN_ADDRESS")
+        .unwrap_or_else(|_| "0.0.0.0:50051".to_string())
+        .parse()
+        .expect("LISTEN_ADDRESS must be a socket address");
+    let identity_headers = IdentityHeaders::from_env().expect("IDENTITY_HEADERS must be valid");
+
+    let config = aws_config::load_from_env().await;
+    let app_client = JWTAppClient::from_env(&config)
+        .await
+        .expect("the authorizer must be configured");
+
+    let service = ExtAuthzService::new(Arc::new(app_client), identity_headers);
+
+    println!("ext_authz listening on {}", address);
+    Server::builder()
+        .add_service(AuthorizationServer::new(service))
+        .serve(address)
+        .await?;
+    Ok(())
+}
@@ -1,12 +1,6 @@
-use aws_lambda_events::apigw::{
-    ApiGatewayCustomAuthorizerRequestTypeRequest, ApiGatewayCustomAuthorizerResponse,
-};
 use lambda_request_authorizer::{
-    queries::get_scope::GetScope,
-    utils::{
-        authorizer::Authorizer,
-        injections::jwt_di::{JWTAppClient, JWTInitialisation},
-    },
+    handler::{execute, AuthorizerEvent},
+    utils::injections::jwt_di::JWTAppClient,
 };
 use lambda_runtime::{self, service_fn, Error, LambdaEvent};
 
@@ -18,87 +12,35 @@ async fn main() -> Result<(), Error> {
         .with_max_level(tracing_subscriber::filter::LevelFilter::INFO)
         .init();
 
-    let audience = std::env::var("AUDIENCE").expect("AUDIENCE must be set");
-    let token_issuer = std::env::var("TOKEN_ISSUER").expect("TOKEN_ISSUER must be set");
-    let json_key_set_url = std::env::var("JSKS_URI").expect("JSKS_URI must be set");
-
     let config = aws_config::load_from_env().await;
-    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
-    let table_name = std::env::var("SCOPE_TABLE_NAME").expect("SCOPE_TABLE_NAME must be set");
-    let query = GetScope::builder()
-        .table_name(table_name)
-        .dynamo_db_client(dynamodb_client.clone())
-        .build();
-
-    let app_client = JWTAppClient::builder()
-        .authorizer(
-            Authorizer::builder()
-                .json_key_set_url(json_key_set_url)
-                .audience(audience)
-                .issuer(token_issuer)
-                .reqwest_client(reqwest::Client::new())
-                .build(),
-        )
-        .get_scope_query(query)
-        .build();
+    let app_client = JWTAppClient::from_env(&config)
+        .await
+        .expect("the authorizer must be configured");
 
-    lambda_runtime::run(service_fn(
-        |event: LambdaEvent<ApiGatewayCustomAuthorizerRequestTypeRequest>| {
-            execute(&app_client, event)
-        },
-    ))
+    lambda_runtime::run(service_fn(|event: LambdaEvent<AuthorizerEvent>| {
+        execute(&app_client, event)
+    }))
     .await?;
     Ok(())
 }
 
-pub async fn execute(
-    app_client: &dyn JWTInitialisation,
-    event: LambdaEvent<ApiGatewayCustomAuthorizerRequestTypeRequest>,
-) -> Result<ApiGatewayCustomAuthorizerResponse, Error> {
-    println!("event {:?}", event);
-    let method_arn = event.payload.method_arn.unwrap();
-    let path = event.payload.path.unwrap();
-    let method = event.payload.http_method.unwrap_or_default().to_string();
-    if let Some(token) = event.payload.headers.get("authorization") {
-        let claims = app_client
-            .validate_token(token.to_str().unwrap().to_string())
-            .await?;
-        if let Some(claims) = claims {
-            if let Some(token_scope) = claims.scope {
-                let api_scopes = app_client.get_scope_query(&method, &path).await?;
-                if let Some(api_scopes) = api_scopes {
-                    for api_scope in api_scopes {
-                        if token_scope.split(' ').any(|x| x == api_scope.as_str()) {
-                            return Ok(app_client.to_response(
-                                "ALLOW".to_string(),
-                                Some(claims.email),
-                                method_arn,
-                            ));
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(app_client.to_response("DENY".to_string(), None, method_arn))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use async_trait::async_trait;
     use aws_lambda_events::apigw::{
-        ApiGatewayCustomAuthorizerPolicy, ApiGatewayCustomAuthorizerRequestTypeRequest,
-        ApiGatewayCustomAuthorizerResponse, IamPolicyStatement,
+        ApiGatewayCustomAuthorizerPolicy, ApiGatewayCustomAuthorizerResponse, IamPolicyStatement,
     };
     use lambda_http::Context;
-    use lambda_request_authorizer::utils::authorizer::Claims;
+    use lambda_request_authorizer::utils::{
+        authorizer::Claims, decision::DecisionRequest, injections::jwt_di::JWTInitialisation,
+        route_rule::RoutePolicy,
+    };
     use mockall::mock;
     use serde_json::{self, Value};
     use shared::error::ApplicationError;
 
-    fn get_lambda_request() -> LambdaEvent<ApiGatewayCustomAuthorizerRequestTypeRequest> {
+    fn get_lambda_request() -> LambdaEvent<AuthorizerEvent> {
         let json = r#"{
   "type": "REQUEST",
   "methodArn": "arn:aws:execute-api:us-east-1:123456789012:abcdef123/one/GET",
@@ -132,6 +74,7 @@ mod tests {
     "identity": {
       "apiKey": "...",
       "sourceIp": "...",
+      "userArn": "arn:aws:sts::123456789012:assumed-role/billing-api/session-1",
       "clientCert": {
         "clientCertPem": "CERT_CONTENT",
         "subjectDN": "www.example.com",
@@ -149,13 +92,23 @@ mod tests {
   }
 }"#;
 
-        let request: ApiGatewayCustomAuthorizerRequestTypeRequest =
-            serde_json::from_str(json).unwrap();
+        let request: AuthorizerEvent = serde_json::from_str(json).unwrap();
 
         let context = Context::default();
-        let event = LambdaEvent::new(request, context);
+        LambdaEvent::new(request, context)
+    }
 
-        event
+    #[test]
+    fn will_read_the_iam_caller() {
+        // ACT
+        let request = DecisionRequest::from(&get_lambda_request().payload);
+
+        // ASSERT
+        assert_eq!(
+            request.caller_arn.as_deref(),
+            Some("arn:aws:sts::123456789012:assumed-role/billing-api/session-1")
+        );
+        assert_eq!(request.path, "/one/");
     }
 
     #[tokio::test]
@@ -167,7 +120,7 @@ mod tests {
             impl JWTInitialisation for JWTAppClient {
                 async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError>;
                 fn to_response(&self, effect: String, principal: Option<String>, method_arn: String) -> ApiGatewayCustomAuthorizerResponse;
-                async fn get_scope_query(&self, method: &str, path: &str) -> Result<Option<Vec<String>>, ApplicationError>;
+                async fn get_scope_query(&self, method: &str, path: &str) -> Result<Option<RoutePolicy>, ApplicationError>;
             }
         }
 
@@ -187,10 +140,13 @@ mod tests {
             Ok(Some(response))
         });
         mock.expect_get_scope_query().times(1).returning(|_, _| {
-            Ok(Some(vec![
-                "my-audience.my-custom-scope".to_string(),
-                "something".to_string(),
-            ]))
+            Ok(Some(
+                vec![
+                    "my-audience.my-custom-scope".to_string(),
+                    "something".to_string(),
+                ]
+                .into(),
+            ))
         });
         mock.expect_to_response().times(1).returning(|_, _, _| {
             let stmt = IamPolicyStatement {
@@ -203,14 +159,12 @@ mod tests {
                 statement: vec![stmt],
             };
 
-            let response = ApiGatewayCustomAuthorizerResponse {
+            ApiGatewayCustomAuthorizerResponse {
                 principal_id: Some("something".to_string()),
                 policy_document: policy,
                 context: Value::Null,
                 usage_identifier_key: None,
-            };
-
-            response
+            }
         });
 
         // ACT
@@ -236,7 +190,7 @@ mod tests {
             impl JWTInitialisation for JWTAppClient {
                 async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError>;
                 fn to_response(&self, effect: String, principal: Option<String>, method_arn: String) -> ApiGatewayCustomAuthorizerResponse;
-                async fn get_scope_query(&self, method: &str, path: &str) -> Result<Option<Vec<String>>, ApplicationError>;
+                async fn get_scope_query(&self, method: &str, path: &str) -> Result<Option<RoutePolicy>, ApplicationError>;
             }
         }
 
@@ -253,18 +207,16 @@ mod tests {
                 statement: vec![stmt],
             };
 
-            let response = ApiGatewayCustomAuthorizerResponse {
+            ApiGatewayCustomAuthorizerResponse {
                 principal_id: None,
                 policy_document: policy,
                 context: Value::Null,
                 usage_identifier_key: None,
-            };
-
-            response
+            }
         });
 
         let mut request = get_lambda_request();
-        request.payload.headers.clear();
+        request.payload.request.headers.clear();
 
         // ACT
         let result = execute(&mock, request).await?;
@@ -290,7 +242,7 @@ mod tests {
             impl JWTInitialisation for JWTAppClient {
                 async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError>;
                 fn to_response(&self, effect: String, principal: Option<String>, method_arn: String) -> ApiGatewayCustomAuthorizerResponse;
-                async fn get_scope_query(&self, method: &str, path: &str) -> Result<Option<Vec<String>>, ApplicationError>;
+                async fn get_scope_query(&self, method: &str, path: &str) -> Result<Option<RoutePolicy>, ApplicationError>;
             }
         }
 
@@ -309,14 +261,12 @@ mod tests {
                 statement: vec![stmt],
             };
 
-            let response = ApiGatewayCustomAuthorizerResponse {
+            ApiGatewayCustomAuthorizerResponse {
                 principal_id: None,
                 policy_document: policy,
                 context: Value::Null,
                 usage_identifier_key: None,
-            };
-
-            response
+            }
         });
 
         // ACT
@@ -0,0 +1,24 @@
+use lambda_http::{self, service_fn, Error, Request};
+use lambda_request_authorizer::{
+    queries::revocation_list::RevocationList, revocation_api::execute,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_ansi(false)
+        .without_time()
+        .with_max_level(tracing_subscriber::filter::LevelFilter::INFO)
+        .init();
+
+    let config = aws_config::load_from_env().await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+    let revocation_list =
+        RevocationList::from_env(&dynamodb_client).expect("REVOCATION_TABLE_NAME must be set");
+
+    lambda_http::run(service_fn(|event: Request| {
+        execute(&revocation_list, event)
+    }))
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use lambda_request_authorizer::queries::{
+    bundle_get_scope::PolicyBundle, file_get_scope::FileGetScope,
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use shared::error::ApplicationError;
+
+/// sign_bundle <rules.json|rules.yml> <version> <bundle.json>
+///   signs the rules with the base64 PKCS#8 Ed25519 key in BUNDLE_SIGNING_KEY
+///   and writes bundle.json and bundle.json.sig
+/// sign_bundle --generate-key
+///   prints a new signing key and its public key for SCOPE_BUNDLE_PUBLIC_KEY
+fn main() -> Result<(), ApplicationError> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(String::as_str) == Some("--generate-key") {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|e| ApplicationError::InternalError(e.to_string()))?;
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+            .map_err(|e| ApplicationError::InternalError(e.to_string()))?;
+        println!("BUNDLE_SIGNING_KEY={}", base64::encode(pkcs8.as_ref()));
+        println!(
+            "SCOPE_BUNDLE_PUBLIC_KEY={}",
+            base64::encode(key_pair.public_key().as_ref())
+        );
+        return Ok(());
+    }
+
+    let (rules_path, version, output) = match args.as_slice() {
+        [rules_path, version, output] => (rules_path, version, output),
+        _ => {
+            return Err(ApplicationError::InitError(
+                "usage: sign_bundle <rules.json|rules.yml> <version> <bundle.json>".to_string(),
+            ))
+        }
+    };
+    let signing_key = std::env::var("BUNDLE_SIGNING_KEY")
+        .map_err(|_| ApplicationError::InitError("BUNDLE_SIGNING_KEY must be set".to_string()))?;
+    let signing_key = base64::decode(signing_key.trim())
+        .map_err(|e| ApplicationError::InitError(format!("Invalid BUNDLE_SIGNING_KEY {}", e)))?;
+
+    let content = std::fs::read_to_string(rules_path)
+        .map_err(|e| ApplicationError::InitError(format!("Cannot read {}: {}", rules_path, e)))?;
+    let rules = if rules_path.ends_with(".yml") || rules_path.ends_with(".yaml") {
+        FileGetScope::parse_yaml(&content)?
+    } else {
+        FileGetScope::parse_json(&content)?
+    };
+
+    let bundle = PolicyBundle {
+        version: version.clone(),
+        rules,
+    };
+    let (bundle, signature) = bundle.sign(&signing_key)?;
+    let write = |path: &str, content: &[u8]| {
+        std::fs::write(path, content)
+            .map_err(|e| ApplicationError::InitError(format!("Cannot write {}: {}", path, e)))
+    };
+    write(output, &bundle)?;
+    write(&format!("{}.sig", output), signature.as_bytes())?;
+    println!("bundle {} signed into {}", version, output);
+
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use crate::utils::{
+    decision::{DecisionEngine, DecisionRequest},
+    injections::jwt_di::JWTInitialisation,
+    token_source::TokenSources,
+};
+use aws_lambda_events::{
+    apigw::{ApiGatewayCustomAuthorizerRequestTypeRequest, ApiGatewayCustomAuthorizerResponse},
+    query_map::QueryMap,
+};
+use http::{HeaderMap, HeaderValue};
+use lambda_runtime::{Error, LambdaEvent};
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// The headers carrying credentials, besides the token sources.
+const CREDENTIAL_HEADERS: [&str; 6] = [
+    "authorization",
+    "cookie",
+    "x-api-key",
+    "dpop",
+    "x-webhook-signature",
+    "x-csrf-token",
+];
+const REDACTED: &str = "***";
+
+/// The REQUEST authorizer event, with the IAM caller of `identity.userArn` that aws_lambda_events does not map.
+#[derive(Debug, Clone)]
+pub struct AuthorizerEvent {
+    pub request: ApiGatewayCustomAuthorizerRequestTypeRequest,
+    pub user_arn: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for AuthorizerEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let user_arn = value
+            .pointer("/requestContext/identity/userArn")
+            .and_then(Value::as_str)
+            .filter(|user_arn| !user_arn.is_empty())
+            .map(str::to_string);
+        let request = serde_json::from_value(value).map_err(de::Error::custom)?;
+
+        Ok(AuthorizerEvent { request, user_arn })
+    }
+}
+
+impl From<ApiGatewayCustomAuthorizerRequestTypeRequest> for AuthorizerEvent {
+    fn from(request: ApiGatewayCustomAuthorizerRequestTypeRequest) -> Self {
+        AuthorizerEvent {
+            request,
+            user_arn: None,
+        }
+    }
+}
+
+impl From<&AuthorizerEvent> for DecisionRequest {
+    fn from(value: &AuthorizerEvent) -> Self {
+        DecisionRequest {
+            caller_arn: value.user_arn.clone(),
+            ..DecisionRequest::from(&value.request)
+        }
+    }
+}
+
+impl AuthorizerEvent {
+    /// The request to log, without the API key, the credential headers and the tokens.
+    pub fn redacted(
+        &self,
+        token_sources: &TokenSources,
+    ) -> ApiGatewayCustomAuthorizerRequestTypeRequest {
+        let mut request = self.request.clone();
+        if let Some(identity) = request.request_context.identity.as_mut() {
+            identity.api_key = identity.api_key.as_ref().map(|_| REDACTED.to_string());
+        }
+        let is_credential =
+            |name: &str| CREDENTIAL_HEADERS.contains(&name) || token_sources.reads_header(name);
+        redact_headers(&mut request.headers, is_credential);
+        redact_headers(&mut request.multi_value_headers, is_credential);
+        request.query_string_parameters =
+            redact_query(&request.query_string_parameters, token_sources);
+        request.multi_value_query_string_parameters =
+            redact_query(&request.multi_value_query_string_parameters, token_sources);
+
+        request
+    }
+}
+
+fn redact_headers(headers: &mut HeaderMap, is_credential: impl Fn(&str) -> bool) {
+    for (name, value) in headers.iter_mut() {
+        if is_credential(name.as_str()) {
+            *value = HeaderValue::from_static(REDACTED);
+        }
+    }
+}
+
+fn redact_query(query: &QueryMap, token_sources: &TokenSources) -> QueryMap {
+    let mut redacted: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, value) in query.iter() {
+        let value = match token_sources.reads_query(name) {
+            true => REDACTED,
+            false => value,
+        };
+        redacted
+            .entry(name.to_string())
+            .or_default()
+            .push(value.to_string());
+    }
+
+    redacted.into()
+}
+
+/// The LambdaRequestAuthorizer, shared by the `jwt` binary and the local gateway.
+pub async fn execute(
+    app_client: &dyn JWTInitialisation,
+    event: LambdaEvent<AuthorizerEvent>,
+) -> Result<ApiGatewayCustomAuthorizerResponse, Error> {
+    println!(
+        "event {:?}",
+        event.payload.redacted(&app_client.token_sources())
+    );
+    let method_arn = event.payload.request.method_arn.clone().unwrap();
+    let request = DecisionRequest::from(&event.payload);
+    let decision = DecisionEngine::decide(app_client, &request).await?;
+    println!("decision {:?}", decision);
+
+    let mut response =
+        app_client.to_response(decision.effect.to_string(), decision.principal, method_arn);
+    response.usage_identifier_key = decision.usage_identifier_key;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use shared::error::ApplicationError;
+
+    #[test]
+    fn redact_the_credentials_of_the_logged_event() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let event: AuthorizerEvent = serde_json::from_value(json!({
+            "type": "REQUEST",
+            "methodArn": "arn:aws:execute-api:eu-central-1:123456789012:abcdef123/prod/GET/one",
+            "headers": {
+                "Authorization": "Bearer secret-token",
+                "X-Api-Key": "secret-key",
+                "X-Access-Token": "other-token",
+                "Accept": "application/json"
+            },
+            "multiValueHeaders": {"Cookie": ["access_token=secret-cookie"]},
+            "queryStringParameters": {"access_token": "query-token", "page": "2"},
+            "requestContext": {"identity": {"apiKey": "secret-key", "sourceIp": "10.0.0.1"}}
+        }))?;
+        let token_sources = TokenSources(vec![
+            "header:Authorization:Bearer".parse()?,
+            "header:X-Access-Token".parse()?,
+            "query:access_token".parse()?,
+        ]);
+
+        // ACT
+        let logged = format!("{:?}", event.redacted(&token_sources));
+
+        // ASSERT
+        for secret in [
+            "secret-token",
+            "secret-key",
+            "other-token",
+            "secret-cookie",
+            "query-token",
+        ] {
+            assert!(!logged.contains(secret), "{} logged in {}", secret, logged);
+        }
+        assert!(logged.contains("application/json"));
+        assert!(logged.contains("10.0.0.1"));
+
+        Ok(())
+    }
+}
@@ -1,2 +1,4 @@
+pub mod handler;
 pub mod queries;
-pub mod utils;
+pub mod revocation_api;
+pub mod utils;
\ No newline at end of file
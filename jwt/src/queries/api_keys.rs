@@ -0,0 +1,154 @@
+use crate::{queries::cached_batch_get::CachedBatchGet, utils::authorizer::Claims};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use shared::{error::ApplicationError, utils::dynamodb::Timestamp};
+use std::time::SystemTime;
+use typed_builder::TypedBuilder as Builder;
+
+/// An API key, stored by the base64url SHA-256 of the key so the table never holds the keys themselves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub pk: String,
+
+    /// The customer the key was issued to, the principal of the decision.
+    pub owner: String,
+
+    pub scopes: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<Timestamp>,
+
+    #[serde(default = "ApiKey::default_enabled")]
+    pub enabled: bool,
+
+    /// The API Gateway key of the usage plan of the owner, the presented key by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_identifier_key: Option<String>,
+}
+
+/// An API key caller, with the key the usage plans throttle it by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyCaller {
+    pub claims: Claims,
+    pub usage_identifier_key: String,
+}
+
+impl ApiKey {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    pub fn hash(api_key: &str) -> String {
+        base64::encode_config(
+            digest::digest(&digest::SHA256, api_key.as_bytes()).as_ref(),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+
+    pub fn is_active_at(&self, now: SystemTime) -> bool {
+        self.enabled && self.expires_at.is_none_or(|expires_at| expires_at.0 > now)
+    }
+
+    pub fn to_caller(&self, api_key: &str, audience: &str) -> ApiKeyCaller {
+        let exp = self
+            .expires_at
+            .and_then(|expires_at| expires_at.0.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(usize::MAX, |exp| exp.as_secs() as usize);
+        let claims = Claims {
+            exp,
+            client_id: Some(self.owner.clone()),
+            ..Claims::for_principal(audience, &self.owner, None, &self.scopes)
+        };
+
+        ApiKeyCaller {
+            claims,
+            usage_identifier_key: self
+                .usage_identifier_key
+                .clone()
+                .unwrap_or_else(|| api_key.to_string()),
+        }
+    }
+}
+
+/// Reads the API keys by their hash.
+#[derive(Debug, Clone, Builder)]
+pub struct GetApiKey {
+    lookup: CachedBatchGet<ApiKey>,
+}
+
+impl GetApiKey {
+    /// Enabled by `API_KEY_TABLE_NAME`.
+    pub fn from_env(dynamo_db_client: &aws_sdk_dynamodb::Client) -> Option<Self> {
+        let table_name = std::env::var("API_KEY_TABLE_NAME").ok()?;
+
+        Some(
+            GetApiKey::builder()
+                .lookup(
+                    CachedBatchGet::builder()
+                        .table_name(table_name)
+                        .dynamo_db_client(dynamo_db_client.clone())
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    pub async fn execute(&self, api_key: &str) -> Result<Option<ApiKey>, ApplicationError> {
+        let items = self.lookup.get(vec![ApiKey::hash(api_key)]).await?;
+
+        Ok(items.into_iter().next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn read_an_api_key_by_its_hash() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.BatchGetItem")
+            .body(SdkBody::empty())
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(format!(
+                r#"{{"Responses": {{"api-keys": [
+                    {{"pk": {{"S": "{}"}}, "owner": {{"S": "acme"}}, "scopes": {{"SS": ["partner.read"]}}, "expires_at": {{"N": "1700000000"}}}}
+                ]}}}}"#,
+                ApiKey::hash("secret-key")
+            )))
+            .unwrap();
+        let conn = TestConnection::new(vec![(request, response)]);
+        let query = GetApiKey::builder()
+            .lookup(
+                CachedBatchGet::builder()
+                    .table_name("api-keys")
+                    .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+                    .build(),
+            )
+            .build();
+        let at = |seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+
+        // ACT
+        let api_key = query.execute("secret-key").await?.unwrap();
+        let caller = api_key.to_caller("secret-key", "my-audience");
+
+        // ASSERT
+        assert!(api_key.enabled);
+        assert!(api_key.is_active_at(at(1665000000)));
+        assert!(!api_key.is_active_at(at(1800000000)));
+        assert_eq!(caller.claims.email, "acme");
+        assert_eq!(caller.claims.scope, Some("partner.read".to_string()));
+        assert_eq!(caller.claims.exp, 1700000000);
+        assert_eq!(caller.usage_identifier_key, "secret-key");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+use crate::{
+    queries::{file_get_scope::ScopeRule, get_scope::GetScopeQuery},
+    utils::{route_rule::RoutePolicy, scope_index::ScopeIndex},
+};
+use async_trait::async_trait;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use shared::error::ApplicationError;
+
+/// The signed archive of all the scope rules built in CI: `bundle.json` plus the detached `bundle.json.sig`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyBundle {
+    pub version: String,
+    pub rules: Vec<ScopeRule>,
+}
+
+/// Where the bundle lives; the signature is always next to it with the `.sig` suffix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleLocation {
+    Path(String),
+    S3 { bucket: String, key: String },
+}
+
+impl BundleLocation {
+    /// Reads `s3://bucket/key` or a local path.
+    pub fn parse(location: &str) -> Result<Self, ApplicationError> {
+        match location.strip_prefix("s3://") {
+            Some(rest) => match rest.split_once('/') {
+                Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+                    Ok(BundleLocation::S3 {
+                        bucket: bucket.to_string(),
+                        key: key.to_string(),
+                    })
+                }
+                _ => Err(ApplicationError::InitError(format!(
+                    "Invalid bundle location {}, expected s3://bucket/key",
+                    location
+                ))),
+            },
+            None => Ok(BundleLocation::Path(location.to_string())),
+        }
+    }
+}
+
+impl PolicyBundle {
+    /// Verifies the Ed25519 signature of the raw bundle before parsing it.
+    pub fn verify(
+        bundle: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<Self, ApplicationError> {
+        let signature = base64::decode(String::from_utf8_lossy(signature).trim())
+            .map_err(|e| ApplicationError::InitError(format!("Invalid bundle signature {}", e)))?;
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(bundle, &signature)
+            .map_err(|_| {
+                ApplicationError::InitError("The bundle signature does not match".to_string())
+            })?;
+
+        Ok(serde_json::from_slice(bundle)?)
+    }
+
+    /// Returns the bundle and its base64 signature, used by the `sign_bundle` binary.
+    pub fn sign(&self, pkcs8: &[u8]) -> Result<(Vec<u8>, String), ApplicationError> {
+        let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|e| ApplicationError::InitError(format!("Invalid signing key {}", e)))?;
+        let bundle = serde_json::to_vec_pretty(self)?;
+        let signature = base64::encode(key_pair.sign(&bundle).as_ref());
+
+        Ok((bundle, signature))
+    }
+
+    /// Refuses a bundle older than `min_version`. Versions compare by their numeric parts,
+    /// so `2022-10-01.10` is newer than `2022-10-01.9`.
+    pub fn check_min_version(&self, min_version: &str) -> Result<(), ApplicationError> {
+        if version_parts(&self.version)? < version_parts(min_version)? {
+            return Err(ApplicationError::InitError(format!(
+                "The bundle version {} is older than the minimum version {}",
+                self.version, min_version
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn load(
+        location: &BundleLocation,
+        public_key: &[u8],
+        s3_client: &aws_sdk_s3::Client,
+    ) -> Result<Self, ApplicationError> {
+        let (bundle, signature) = match location {
+            BundleLocation::Path(path) => (read_file(path)?, read_file(&format!("{}.sig", path))?),
+            BundleLocation::S3 { bucket, key } => (
+                get_object(s3_client, bucket, key).await?,
+                get_object(s3_client, bucket, &format!("{}.sig", key)).await?,
+            ),
+        };
+
+        PolicyBundle::verify(&bundle, &signature, public_key)
+    }
+}
+
+fn version_parts(version: &str) -> Result<Vec<u64>, ApplicationError> {
+    version
+        .trim()
+        .split(['.', '-'])
+        .map(|part| {
+            part.parse().map_err(|_| {
+                ApplicationError::InitError(format!(
+                    "Invalid bundle version {}, expected numbers separated by . or -",
+                    version
+                ))
+            })
+        })
+        .collect()
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, ApplicationError> {
+    std::fs::read(path)
+        .map_err(|e| ApplicationError::InitError(format!("Cannot read {}: {}", path, e)))
+}
+
+async fn get_object(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: &str,
+) -> Result<Vec<u8>, ApplicationError> {
+    let result = s3_client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?;
+    let body = result.body.collect().await.map_err(|e| {
+        ApplicationError::SdkError(format!("Cannot read s3://{}/{}: {}", bucket, key, e))
+    })?;
+
+    Ok(body.into_bytes().to_vec())
+}
+
+/// Scope rules served from a verified `PolicyBundle`.
+#[derive(Debug, Clone)]
+pub struct BundleGetScope {
+    pub version: String,
+    index: ScopeIndex,
+}
+
+impl From<PolicyBundle> for BundleGetScope {
+    fn from(bundle: PolicyBundle) -> Self {
+        BundleGetScope {
+            version: bundle.version,
+            index: ScopeIndex::new(bundle.rules.into_iter().map(|rule| (rule.pk, rule.policy))),
+        }
+    }
+}
+
+#[async_trait]
+impl GetScopeQuery for BundleGetScope {
+    async fn execute(&self, api: &str) -> Result<Option<RoutePolicy>, ApplicationError> {
+        Ok(self.index.find(api))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    fn get_keys() -> (Vec<u8>, Vec<u8>) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        (
+            pkcs8.as_ref().to_vec(),
+            key_pair.public_key().as_ref().to_vec(),
+        )
+    }
+
+    fn get_bundle() -> PolicyBundle {
+        PolicyBundle {
+            version: "2022-10-01.1".to_string(),
+            rules: vec![ScopeRule {
+                pk: "GET/one".to_string(),
+                policy: vec!["my-audience.read".to_string()].into(),
+            }],
+        }
+    }
+
+    fn get_object_event(key: &str, body: Vec<u8>) -> (http::Request<SdkBody>, Response<SdkBody>) {
+        let request = http::Request::builder()
+            .uri(format!(
+                "https://bundles.s3.eu-central-1.amazonaws.com/{}",
+                key
+            ))
+            .body(SdkBody::empty())
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(body))
+            .unwrap();
+        (request, response)
+    }
+
+    #[tokio::test]
+    async fn load_a_signed_bundle_from_s3() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let (pkcs8, public_key) = get_keys();
+        let (bundle, signature) = get_bundle().sign(&pkcs8)?;
+        let conn = TestConnection::new(vec![
+            get_object_event("rules/bundle.json", bundle),
+            get_object_event("rules/bundle.json.sig", signature.into_bytes()),
+        ]);
+        let s3_client = UnitTestHelper::s3_fake_client(&conn).await;
+        let location = BundleLocation::parse("s3://bundles/rules/bundle.json")?;
+
+        // ACT
+        let query =
+            BundleGetScope::from(PolicyBundle::load(&location, &public_key, &s3_client).await?);
+
+        // ASSERT
+        assert_eq!(query.version, "2022-10-01.1");
+        assert_eq!(
+            query.execute("GET/one/").await?,
+            Some(vec!["my-audience.read".to_string()].into())
+        );
+        assert_eq!(conn.requests().len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn load_a_signed_bundle_from_disk() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let (pkcs8, public_key) = get_keys();
+        let (bundle, signature) = get_bundle().sign(&pkcs8)?;
+        let path = std::env::temp_dir().join(format!("bundle-{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        std::fs::write(&path, bundle).unwrap();
+        std::fs::write(format!("{}.sig", path), signature).unwrap();
+        let s3_client = UnitTestHelper::s3_fake_client(&TestConnection::new(vec![])).await;
+
+        // ACT
+        let result =
+            PolicyBundle::load(&BundleLocation::Path(path.clone()), &public_key, &s3_client).await;
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(format!("{}.sig", path)).unwrap();
+
+        // ASSERT
+        assert_eq!(result?, get_bundle());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_a_tampered_bundle() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let (pkcs8, public_key) = get_keys();
+        let (bundle, signature) = get_bundle().sign(&pkcs8)?;
+        let tampered = String::from_utf8(bundle)
+            .unwrap()
+            .replace("my-audience.read", "my-audience.admin");
+
+        // ACT
+        let result = PolicyBundle::verify(tampered.as_bytes(), signature.as_bytes(), &public_key);
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_a_bundle_signed_with_another_key() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let (pkcs8, _) = get_keys();
+        let (_, public_key) = get_keys();
+        let (bundle, signature) = get_bundle().sign(&pkcs8)?;
+
+        // ACT
+        let result = PolicyBundle::verify(&bundle, signature.as_bytes(), &public_key);
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_a_bundle_older_than_the_min_version() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let bundle = get_bundle();
+
+        // ACT
+        let same = bundle.check_min_version("2022-10-01.1");
+        let older = bundle.check_min_version("2022-9-30.12");
+        let newer = bundle.check_min_version("2022-10-01.10");
+        let invalid = bundle.check_min_version("latest");
+
+        // ASSERT
+        assert!(same.is_ok());
+        assert!(older.is_ok());
+        assert!(newer.is_err());
+        assert!(invalid.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use aws_sdk_dynamodb::model::{AttributeValue, KeysAndAttributes};
+use serde::de::DeserializeOwned;
+use shared::{error::ApplicationError, utils::dynamodb::from_item};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use typed_builder::TypedBuilder as Builder;
+
+#[derive(Debug, Clone)]
+struct CacheEntry<T> {
+    item: Option<T>,
+    expires_at: Instant,
+}
+
+/// Reads items by `pk` with one `BatchGetItem`; found and missing items are cached for `cache_ttl`.
+#[derive(Debug, Clone, Builder)]
+pub struct CachedBatchGet<T> {
+    #[builder(setter(into))]
+    pub table_name: String,
+
+    pub dynamo_db_client: aws_sdk_dynamodb::Client,
+
+    #[builder(default = Duration::from_secs(30))]
+    cache_ttl: Duration,
+
+    #[builder(default = 10000)]
+    max_entries: usize,
+
+    #[builder(default, setter(skip))]
+    cache: Arc<Mutex<HashMap<String, CacheEntry<T>>>>,
+}
+
+impl<T: DeserializeOwned + Clone> CachedBatchGet<T> {
+    pub async fn get(&self, keys: Vec<String>) -> Result<Vec<T>, ApplicationError> {
+        let mut items = Vec::new();
+        let mut missing = Vec::new();
+        {
+            let now = Instant::now();
+            let cache = self.cache.lock().unwrap();
+            for key in keys {
+                match cache.get(&key).filter(|entry| entry.expires_at > now) {
+                    Some(entry) => items.extend(entry.item.clone()),
+                    None => missing.push(key),
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            let mut found = self.fetch(&missing).await?;
+            for key in missing {
+                let item = found.remove(&key);
+                self.insert(key, item.clone());
+                items.extend(item);
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Forgets the cached item after a write.
+    pub fn invalidate(&self, key: &str) {
+        self.cache.lock().unwrap().remove(key);
+    }
+
+    async fn fetch(&self, keys: &[String]) -> Result<HashMap<String, T>, ApplicationError> {
+        let keys = keys
+            .iter()
+            .map(|key| HashMap::from([("pk".to_string(), AttributeValue::S(key.clone()))]))
+            .collect();
+        let result = self
+            .dynamo_db_client
+            .batch_get_item()
+            .request_items(
+                &self.table_name,
+                KeysAndAttributes::builder().set_keys(Some(keys)).build(),
+            )
+            .send()
+            .await?;
+
+        if result
+            .unprocessed_keys
+            .is_some_and(|unprocessed| !unprocessed.is_empty())
+        {
+            return Err(ApplicationError::SdkError(format!(
+                "The lookup on {} was throttled",
+                self.table_name
+            )));
+        }
+
+        let mut items = HashMap::new();
+        for item in result
+            .responses
+            .and_then(|mut responses| responses.remove(&self.table_name))
+            .unwrap_or_default()
+        {
+            if let Some(AttributeValue::S(pk)) = item.get("pk") {
+                items.insert(pk.clone(), from_item(&item)?);
+            }
+        }
+
+        Ok(items)
+    }
+
+    fn insert(&self, key: String, item: Option<T>) {
+        if self.cache_ttl.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.max_entries {
+            cache.retain(|_, entry| entry.expires_at > now);
+        }
+        if cache.len() < self.max_entries {
+            cache.insert(
+                key,
+                CacheEntry {
+                    item,
+                    expires_at: now + self.cache_ttl,
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{
+    queries::get_scope::GetScopeQuery,
+    utils::{
+        degradation::{self, DegradedMode, Dependency, FailureMode},
+        route_rule::RoutePolicy,
+    },
+};
+use async_trait::async_trait;
+use shared::error::ApplicationError;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use typed_builder::TypedBuilder as Builder;
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    policy: Option<RoutePolicy>,
+    expires_at: Instant,
+}
+
+/// Caches the scope lookups of the wrapped query across warm invocations.
+/// Routes without a rule are cached as well, for the shorter `negative_ttl`.
+/// When the wrapped query fails, `failure_mode` can serve the expired entry or deny.
+#[derive(Debug, Clone, Builder)]
+pub struct CachedGetScope<Q> {
+    query: Q,
+
+    #[builder(default = Duration::from_secs(300))]
+    ttl: Duration,
+
+    #[builder(default = Duration::from_secs(30))]
+    negative_ttl: Duration,
+
+    #[builder(default = 1000)]
+    max_entries: usize,
+
+    #[builder(default)]
+    failure_mode: FailureMode,
+
+    #[builder(default, setter(skip))]
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+impl<Q> CachedGetScope<Q> {
+    fn get(&self, api: &str, max_staleness: Duration) -> Option<Option<RoutePolicy>> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(api)
+            .filter(|entry| entry.expires_at + max_staleness > Instant::now())
+            .map(|entry| entry.policy.clone())
+    }
+
+    fn insert(&self, api: &str, policy: Option<RoutePolicy>) {
+        let ttl = if policy.is_some() {
+            self.ttl
+        } else {
+            self.negative_ttl
+        };
+        if ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        // expired entries are kept as long as they can still be served stale
+        let max_staleness = match self.failure_mode {
+            FailureMode::Stale { max_staleness } => max_staleness,
+            _ => Duration::ZERO,
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.max_entries && !cache.contains_key(api) {
+            cache.retain(|_, entry| entry.expires_at + max_staleness > now);
+        }
+        if cache.len() >= self.max_entries && !cache.contains_key(api) {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(
+            api.to_string(),
+            CacheEntry {
+                policy,
+                expires_at: now + ttl,
+            },
+        );
+    }
+}
+
+#[async_trait]
+impl<Q> GetScopeQuery for CachedGetScope<Q>
+where
+    Q: GetScopeQuery + Send + Sync,
+{
+    async fn execute(&self, api: &str) -> Result<Option<RoutePolicy>, ApplicationError> {
+        if let Some(policy) = self.get(api, Duration::ZERO) {
+            return Ok(policy);
+        }
+
+        let error = match self.query.execute(api).await {
+            Ok(policy) => {
+                self.insert(api, policy.clone());
+                return Ok(policy);
+            }
+            Err(e) => e,
+        };
+
+        match self.failure_mode {
+            FailureMode::Error => Err(error),
+            FailureMode::Deny => {
+                degradation::record(Dependency::ScopeRules, DegradedMode::Deny);
+                Ok(None)
+            }
+            FailureMode::Stale { max_staleness } => match self.get(api, max_staleness) {
+                Some(policy) => {
+                    degradation::record(Dependency::ScopeRules, DegradedMode::Stale);
+                    Ok(policy)
+                }
+                None => Err(error),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        queries::get_scope::GetScope,
+        utils::{
+            authorizer::Authorizer,
+            injections::jwt_di::{JWTAppClient, JWTInitialisation},
+        },
+    };
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use mockall::mock;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    mock! {
+        pub GetScope {}
+        #[async_trait]
+        impl GetScopeQuery for GetScope {
+            async fn execute(&self, api: &str) -> Result<Option<RoutePolicy>, ApplicationError>;
+        }
+    }
+
+    #[tokio::test]
+    async fn repeated_decisions_for_the_same_route_do_not_hit_dynamodb(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.GetItem")
+            .body(SdkBody::from(
+                r#"{"TableName":"some-table","Key":{"pk":{"S":"GET/one/"}}}"#,
+            ))
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(
+                r#"{
+                  "Item": {
+                    "pk": {"S": "GET/one/"},
+                    "scopes": {"L": [{"S": "my-audience.read"}]}
+                    }
+                  }"#,
+            ))
+            .unwrap();
+        let conn = UnitTestHelper::buil_test_connnection(Some(request), Some(response));
+        let dynamo_db_client = UnitTestHelper::dynamo_fake_client(&conn).await;
+        let app_client = JWTAppClient::builder()
+            .authorizer(Authorizer::default())
+            .get_scope_query(
+                CachedGetScope::builder()
+                    .query(
+                        GetScope::builder()
+                            .table_name("some-table")
+                            .dynamo_db_client(dynamo_db_client)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+
+        // ACT
+        let first = app_client.get_scope_query("GET", "/one").await?;
+        let second = app_client.get_scope_query("GET", "/one/").await?;
+
+        // ASSERT
+        assert_eq!(first, Some(vec!["my-audience.read".to_string()].into()));
+        assert_eq!(second, first);
+        assert_eq!(conn.requests().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cache_not_found_for_the_negative_ttl() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut query = MockGetScope::new();
+        query
+            .expect_execute()
+            .withf(|api| api == "GET/two/")
+            .times(2)
+            .returning(|_| Ok(None));
+        let cached = CachedGetScope::builder()
+            .query(query)
+            .negative_ttl(Duration::from_millis(50))
+            .build();
+
+        // ACT
+        let first = cached.execute("GET/two/").await?;
+        let second = cached.execute("GET/two/").await?;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let third = cached.execute("GET/two/").await?;
+
+        // ASSERT
+        assert!(first.is_none());
+        assert!(second.is_none());
+        assert!(third.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn evict_the_oldest_entry_when_full() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut query = MockGetScope::new();
+        query
+            .expect_execute()
+            .withf(|api| api == "GET/one/")
+            .times(2)
+            .returning(|_| Ok(Some(vec!["scope1".to_string()].into())));
+        query
+            .expect_execute()
+            .withf(|api| api == "GET/two/")
+            .times(1)
+            .returning(|_| Ok(Some(vec!["scope2".to_string()].into())));
+        let cached = CachedGetScope::builder()
+            .query(query)
+            .max_entries(1)
+            .build();
+
+        // ACT
+        cached.execute("GET/one/").await?;
+        cached.execute("GET/two/").await?;
+        cached.execute("GET/two/").await?;
+        let result = cached.execute("GET/one/").await?;
+
+        // ASSERT
+        assert_eq!(result, Some(vec!["scope1".to_string()].into()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn do_not_cache_errors() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut query = MockGetScope::new();
+        query
+            .expect_execute()
+            .times(2)
+            .returning(|_| Err(ApplicationError::SdkError("throttled".to_string())));
+        let cached = CachedGetScope::builder().query(query).build();
+
+        // ACT
+        let first = cached.execute("GET/one/").await;
+        let second = cached.execute("GET/one/").await;
+
+        // ASSERT
+        assert!(first.is_err());
+        assert!(second.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serve_the_expired_entry_when_the_query_fails() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut query = MockGetScope::new();
+        query
+            .expect_execute()
+            .times(1)
+            .returning(|_| Ok(Some(vec!["scope1".to_string()].into())));
+        query
+            .expect_execute()
+            .times(2)
+            .returning(|_| Err(ApplicationError::SdkError("throttled".to_string())));
+        let cached = CachedGetScope::builder()
+            .query(query)
+            .ttl(Duration::from_millis(10))
+            .failure_mode(FailureMode::Stale {
+                max_staleness: Duration::from_secs(60),
+            })
+            .build();
+
+        // ACT
+        cached.execute("GET/one/").await?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let (stale, degradations) = degradation::collect(cached.execute("GET/one/")).await;
+        let missing = cached.execute("GET/two/").await;
+
+        // ASSERT
+        assert_eq!(stale?, Some(vec!["scope1".to_string()].into()));
+        assert_eq!(degradations[0].mode, DegradedMode::Stale);
+        assert!(missing.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keep_the_expired_entries_that_can_be_served_stale() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let cached = CachedGetScope::builder()
+            .query(MockGetScope::new())
+            .ttl(Duration::from_millis(10))
+            .max_entries(3)
+            .failure_mode(FailureMode::Stale {
+                max_staleness: Duration::from_secs(60),
+            })
+            .build();
+        cached.insert("GET/one/", Some(vec!["scope1".to_string()].into()));
+        cached.insert("GET/two/", Some(vec!["scope2".to_string()].into()));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // ACT
+        cached.insert("GET/three/", Some(vec!["scope3".to_string()].into()));
+        cached.insert("GET/four/", Some(vec!["scope4".to_string()].into()));
+
+        // ASSERT
+        assert_eq!(cached.get("GET/one/", Duration::from_secs(60)), None);
+        assert_eq!(
+            cached.get("GET/two/", Duration::from_secs(60)),
+            Some(Some(vec!["scope2".to_string()].into()))
+        );
+        assert_eq!(cached.cache.lock().unwrap().len(), 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    queries::cached_batch_get::CachedBatchGet, utils::client_certificate::ClientCertificate,
+};
+use serde::{Deserialize, Serialize};
+use shared::error::ApplicationError;
+use typed_builder::TypedBuilder as Builder;
+
+/// The scopes granted to the client certificates by issuer DN, `issuer#<dn>`, or by subject DN
+/// or serial number within their issuer, `issuer#<dn>#subject#<dn>` and `issuer#<dn>#serial#<serial>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateGrant {
+    pub pk: String,
+
+    pub scopes: Vec<String>,
+
+    /// The principal of the decision, the subject DN by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<String>,
+}
+
+/// Reads the grants matching a client certificate.
+#[derive(Debug, Clone, Builder)]
+pub struct GetCertificateGrants {
+    lookup: CachedBatchGet<CertificateGrant>,
+}
+
+impl GetCertificateGrants {
+    /// Enabled by `CERTIFICATE_TABLE_NAME`.
+    pub fn from_env(dynamo_db_client: &aws_sdk_dynamodb::Client) -> Option<Self> {
+        let table_name = std::env::var("CERTIFICATE_TABLE_NAME").ok()?;
+
+        Some(
+            GetCertificateGrants::builder()
+                .lookup(
+                    CachedBatchGet::builder()
+                        .table_name(table_name)
+                        .dynamo_db_client(dynamo_db_client.clone())
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    pub async fn execute(
+        &self,
+        certificate: &ClientCertificate,
+    ) -> Result<Vec<CertificateGrant>, ApplicationError> {
+        let keys = certificate.grant_keys();
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.lookup.get(keys).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    #[tokio::test]
+    async fn read_the_grants_of_the_subject_and_the_issuer() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.BatchGetItem")
+            .body(SdkBody::empty())
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(
+                r#"{"Responses": {"certificates": [
+                    {"pk": {"S": "issuer#CN=Partner CA#subject#CN=partner"}, "scopes": {"SS": ["partner.read"]}, "principal": {"S": "partner"}},
+                    {"pk": {"S": "issuer#CN=Partner CA"}, "scopes": {"SS": ["partner.ping"]}}
+                ]}}"#,
+            ))
+            .unwrap();
+        let conn = TestConnection::new(vec![(request, response)]);
+        let query = GetCertificateGrants::builder()
+            .lookup(
+                CachedBatchGet::builder()
+                    .table_name("certificates")
+                    .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+                    .build(),
+            )
+            .build();
+        let certificate = ClientCertificate {
+            subject_dn: Some("CN=partner".to_string()),
+            issuer_dn: Some("CN=Partner CA".to_string()),
+            ..Default::default()
+        };
+
+        // ACT
+        let grants = query.execute(&certificate).await?;
+
+        // ASSERT
+        assert_eq!(grants.len(), 2);
+        assert_eq!(grants[0].scopes, vec!["partner.ping".to_string()]);
+        assert_eq!(grants[1].principal, Some("partner".to_string()));
+
+        Ok(())
+    }
+}
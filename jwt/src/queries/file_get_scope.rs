@@ -0,0 +1,159 @@
+use crate::{
+    queries::get_scope::GetScopeQuery,
+    utils::{route_rule::RoutePolicy, scope_index::ScopeIndex},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared::error::ApplicationError;
+use std::path::Path;
+
+/// A rule of the file and bundle stores, the same shape as an item of the scope table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScopeRule {
+    pub pk: String,
+
+    #[serde(flatten)]
+    pub policy: RoutePolicy,
+}
+
+/// Scope rules read from a JSON or YAML file in the same shape as the table items,
+/// for local development and tests without DynamoDB.
+#[derive(Debug, Clone)]
+pub struct FileGetScope {
+    index: ScopeIndex,
+}
+
+impl FileGetScope {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ApplicationError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            ApplicationError::InitError(format!("Cannot read {}: {}", path.display(), e))
+        })?;
+        let is_yaml = matches!(
+            path.extension().and_then(|x| x.to_str()),
+            Some("yml") | Some("yaml")
+        );
+
+        let rules = if is_yaml {
+            Self::parse_yaml(&content)?
+        } else {
+            Self::parse_json(&content)?
+        };
+
+        Ok(FileGetScope::from_rules(rules))
+    }
+
+    pub fn from_rules(rules: Vec<ScopeRule>) -> Self {
+        FileGetScope {
+            index: ScopeIndex::new(rules.into_iter().map(|rule| (rule.pk, rule.policy))),
+        }
+    }
+
+    pub fn parse_yaml(content: &str) -> Result<Vec<ScopeRule>, ApplicationError> {
+        serde_yaml::from_str(content)
+            .map_err(|e| ApplicationError::InitError(format!("Invalid scope rules {}", e)))
+    }
+
+    /// Accepts an array of rules as well as rules one after the other, like the README examples.
+    pub fn parse_json(content: &str) -> Result<Vec<ScopeRule>, ApplicationError> {
+        let mut rules = Vec::new();
+        for value in serde_json::Deserializer::from_str(content).into_iter::<Value>() {
+            match value? {
+                Value::Array(values) => {
+                    for value in values {
+                        rules.push(serde_json::from_value(value)?);
+                    }
+                }
+                value => rules.push(serde_json::from_value(value)?),
+            }
+        }
+
+        Ok(rules)
+    }
+}
+
+#[async_trait]
+impl GetScopeQuery for FileGetScope {
+    async fn execute(&self, api: &str) -> Result<Option<RoutePolicy>, ApplicationError> {
+        Ok(self.index.find(api))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn load_the_readme_examples() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let content = r#"
+{
+ "pk": "GET/one",
+ "scopes": [
+  "my-audience.read"
+ ]
+}
+
+{
+ "pk": "POST/two",
+ "scopes": [
+  "my-audience.write"
+ ]
+}"#;
+
+        // ACT
+        let query = FileGetScope::from_rules(FileGetScope::parse_json(content)?);
+
+        // ASSERT
+        assert_eq!(
+            query.execute("GET/one/").await?,
+            Some(vec!["my-audience.read".to_string()].into())
+        );
+        assert_eq!(
+            query.execute("POST/two/").await?,
+            Some(vec!["my-audience.write".to_string()].into())
+        );
+        assert_eq!(query.execute("GET/two/").await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn load_a_yaml_file() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let path = std::env::temp_dir().join(format!("scopes-{}.yml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+- pk: GET/one
+  scopes:
+    - my-audience.read
+"#,
+        )
+        .unwrap();
+
+        // ACT
+        let query = FileGetScope::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        // ASSERT
+        assert_eq!(
+            query?.execute("GET/one/").await?,
+            Some(vec!["my-audience.read".to_string()].into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_when_a_rule_is_not_valid() -> Result<(), ApplicationError> {
+        // ACT
+        let result = FileGetScope::parse_json(r#"[{"pk": "GET/one"}]"#);
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{self, model::AttributeValue};
+use serde::Deserialize;
+use shared::{error::ApplicationError, utils::dynamodb::from_item};
+use std::collections::HashMap;
+use typed_builder::TypedBuilder as Builder;
+
+#[async_trait]
+pub trait GetOperationRuleQuery {
+    async fn execute(&self, operation: &str) -> Result<Option<OperationRule>, ApplicationError>;
+}
+
+/// A rule for a GraphQL operation, keyed as `QUERY/getPost` or `QUERY/*` for every operation of a type.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct OperationRule {
+    pub pk: String,
+
+    pub scopes: Vec<String>,
+
+    #[serde(default)]
+    pub denied_fields: Vec<String>,
+
+    #[serde(default)]
+    pub resolver_context: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Builder)]
+pub struct GetOperationRule {
+    #[builder(setter(into))]
+    table_name: String,
+
+    #[builder(default, setter(strip_option))]
+    pub dynamo_db_client: Option<aws_sdk_dynamodb::Client>,
+}
+
+#[async_trait]
+impl GetOperationRuleQuery for GetOperationRule {
+    async fn execute(&self, operation: &str) -> Result<Option<OperationRule>, ApplicationError> {
+        let result = self
+            .dynamo_db_client
+            .as_ref()
+            .unwrap()
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(operation.to_owned()))
+            .send()
+            .await?;
+
+        match result.item {
+            Some(item) => Ok(Some(from_item(&item)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+    use tokio;
+
+    #[tokio::test]
+    async fn return_some_if_rule_found() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder();
+        let request = request
+            .header("x-amz-target", "DynamoDB_20120810.GetItem")
+            .body(SdkBody::from(
+                r#"{"TableName":"some-table","Key":{"pk":{"S":"QUERY/getPost"}}}"#,
+            ))
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(
+                r#"{
+                  "Item": {
+                    "pk": {"S": "QUERY/getPost"},
+                    "scopes": {"L": [{"S": "posts.read"}]},
+                    "denied_fields": {"L": [{"S": "Post.internalNotes"}]},
+                    "resolver_context": {"M": {"tenant": {"S": "blue"}}}
+                    }
+                  }"#,
+            ))
+            .unwrap();
+        let conn = UnitTestHelper::buil_test_connnection(Some(request), Some(response));
+        let dynamo_db_client = UnitTestHelper::dynamo_fake_client(&conn).await;
+
+        let query = GetOperationRule::builder()
+            .table_name("some-table".to_owned())
+            .dynamo_db_client(dynamo_db_client)
+            .build();
+
+        // ACT
+        let result = query.execute("QUERY/getPost").await?.unwrap();
+
+        // ASSERT
+        assert_eq!(result.scopes, vec!["posts.read".to_string()]);
+        assert_eq!(result.denied_fields, vec!["Post.internalNotes".to_string()]);
+        assert_eq!(
+            result.resolver_context.get("tenant"),
+            Some(&"blue".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_none_if_rule_not_found() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder();
+        let request = request
+            .header("x-amz-target", "DynamoDB_20120810.GetItem")
+            .body(SdkBody::from(
+                r#"{"TableName":"some-table","Key":{"pk":{"S":"QUERY/getPost"}}}"#,
+            ))
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(r#"{"Item": null}"#))
+            .unwrap();
+        let conn = UnitTestHelper::buil_test_connnection(Some(request), Some(response));
+        let dynamo_db_client = UnitTestHelper::dynamo_fake_client(&conn).await;
+
+        let query = GetOperationRule::builder()
+            .table_name("some-table".to_owned())
+            .dynamo_db_client(dynamo_db_client)
+            .build();
+
+        // ACT
+        let result = query.execute("QUERY/getPost").await?;
+
+        // ASSERT
+        assert!(result.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_a_malformed_item_return_error() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder();
+        let request = request
+            .header("x-amz-target", "DynamoDB_20120810.GetItem")
+            .body(SdkBody::from(
+                r#"{"TableName":"some-table","Key":{"pk":{"S":"QUERY/getPost"}}}"#,
+            ))
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(
+                r#"{"Item": {"pk": {"S": "QUERY/getPost"}, "scopes": {"S": "posts.read"}}}"#,
+            ))
+            .unwrap();
+        let conn = UnitTestHelper::buil_test_connnection(Some(request), Some(response));
+        let dynamo_db_client = UnitTestHelper::dynamo_fake_client(&conn).await;
+
+        let query = GetOperationRule::builder()
+            .table_name("some-table".to_owned())
+            .dynamo_db_client(dynamo_db_client)
+            .build();
+
+        // ACT
+        let result = query.execute("QUERY/getPost").await;
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
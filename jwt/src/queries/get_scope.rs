@@ -1,13 +1,42 @@
+use crate::utils::{
+    circuit_breaker::CircuitBreaker,
+    degradation::{self, DegradedMode, Dependency},
+    route_rule::{RoutePolicy, RouteRule},
+};
 use async_trait::async_trait;
-use aws_sdk_dynamodb::{self, model::AttributeValue};
-use shared::{error::ApplicationError, utils::dynamodb::AttributeValuesExt};
+use aws_sdk_dynamodb::{self, error::GetItemError, model::AttributeValue, types::SdkError};
+use shared::error::ApplicationError;
+use std::sync::Arc;
 use typed_builder::TypedBuilder as Builder;
 
 #[async_trait]
 pub trait GetScopeQuery {
-    async fn execute(&self, api: &str) -> Result<Option<Vec<String>>, ApplicationError>;
+    async fn execute(&self, api: &str) -> Result<Option<RoutePolicy>, ApplicationError>;
 }
 
+#[async_trait]
+impl GetScopeQuery for Arc<dyn GetScopeQuery + Send + Sync> {
+    async fn execute(&self, api: &str) -> Result<Option<RoutePolicy>, ApplicationError> {
+        self.as_ref().execute(api).await
+    }
+}
+
+/// A client of the scope table replicated in another region.
+#[derive(Debug, Clone)]
+pub struct RegionClient {
+    pub region: String,
+    pub client: aws_sdk_dynamodb::Client,
+}
+
+/// The policy of a route and the region that served it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeLookup {
+    pub policy: Option<RoutePolicy>,
+    pub region: String,
+}
+
+/// `GetItem` on the scope table. With `failover` set, throttling, timeouts and 5xx errors
+/// move the lookup to the next region in order, skipping the regions whose circuit is open.
 #[derive(Debug, Clone, Builder)]
 pub struct GetScope {
     #[builder(setter(into))]
@@ -15,30 +44,122 @@ pub struct GetScope {
 
     #[builder(default, setter(strip_option))]
     pub dynamo_db_client: Option<aws_sdk_dynamodb::Client>,
+
+    #[builder(default = "default".to_string(), setter(into))]
+    region: String,
+
+    #[builder(default)]
+    failover: Vec<RegionClient>,
+
+    #[builder(default)]
+    circuit_breaker: CircuitBreaker,
 }
 
-#[async_trait]
-impl GetScopeQuery for GetScope {
-    async fn execute(&self, api: &str) -> Result<Option<Vec<String>>, ApplicationError> {
-        let result = self
+impl GetScope {
+    pub async fn lookup(&self, api: &str) -> Result<ScopeLookup, ApplicationError> {
+        let regions = self
             .dynamo_db_client
-            .as_ref()
-            .unwrap()
+            .iter()
+            .map(|client| (self.region.as_str(), client))
+            .chain(self.failover.iter().map(|x| (x.region.as_str(), &x.client)));
+
+        let mut last_error = None;
+        let mut skipped = Vec::new();
+        for (region, client) in regions {
+            // asked region by region, a half-open region is only probed when it is called
+            if !self.circuit_breaker.is_available(region) {
+                skipped.push((region, client));
+                continue;
+            }
+            match self.get_item(region, client, api).await? {
+                Ok(lookup) => return Ok(lookup),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        // with every circuit open, trying them all beats failing without a call
+        if last_error.is_none() {
+            for (region, client) in skipped {
+                match self.get_item(region, client, api).await? {
+                    Ok(lookup) => return Ok(lookup),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            ApplicationError::InitError("GetScope needs a DynamoDB client".to_string())
+        }))
+    }
+
+    /// The inner error is a regional failure, worth trying the next region.
+    async fn get_item(
+        &self,
+        region: &str,
+        client: &aws_sdk_dynamodb::Client,
+        api: &str,
+    ) -> Result<Result<ScopeLookup, ApplicationError>, ApplicationError> {
+        let result = client
             .get_item()
             .table_name(&self.table_name)
             .key("pk", AttributeValue::S(api.to_owned()))
             .send()
-            .await?;
+            .await;
 
-        Ok(result
-            .item
-            .map(|item| item.get_array_string("scopes").unwrap()))
+        match result {
+            Ok(result) => {
+                self.circuit_breaker.record_success(region);
+                let policy = match result.item {
+                    Some(item) => Some(RouteRule::from_item(&item)?.policy),
+                    None => None,
+                };
+                Ok(Ok(ScopeLookup {
+                    policy,
+                    region: region.to_string(),
+                }))
+            }
+            Err(e) if is_regional_failure(&e) => {
+                println!("scope lookup failed in {}: {}", region, e);
+                self.circuit_breaker.record_failure(region);
+                Ok(Err(e.into()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Errors worth retrying in another region; anything else would fail there too.
+fn is_regional_failure(error: &SdkError<GetItemError>) -> bool {
+    match error {
+        SdkError::TimeoutError(_) => true,
+        SdkError::DispatchFailure(e) => e.is_timeout() || e.is_io(),
+        SdkError::ResponseError { raw, .. } => raw.http().status().is_server_error(),
+        SdkError::ServiceError { err, raw } => {
+            raw.http().status().is_server_error()
+                || err.is_provisioned_throughput_exceeded_exception()
+                || err.is_request_limit_exceeded()
+                || err.is_internal_server_error()
+                || err.code() == Some("ThrottlingException")
+        }
+        SdkError::ConstructionFailure(_) => false,
+    }
+}
+
+#[async_trait]
+impl GetScopeQuery for GetScope {
+    async fn execute(&self, api: &str) -> Result<Option<RoutePolicy>, ApplicationError> {
+        let lookup = self.lookup(api).await?;
+        if lookup.region != self.region {
+            println!("scope rule served by {}", lookup.region);
+            degradation::record(Dependency::ScopeRules, DegradedMode::Failover);
+        }
+        Ok(lookup.policy)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
     use aws_smithy_http::body::SdkBody;
     use lambda_http::Response;
     use shared::utils::unit_tests_helper::UnitTestHelper;
@@ -101,7 +222,7 @@ mod tests {
         let result = query.execute("pk_value").await?;
 
         // ASSERT
-        assert_eq!(result.unwrap().len(), 2);
+        assert_eq!(result.unwrap().scopes.len(), 2);
 
         Ok(())
     }
@@ -156,6 +277,43 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn given_a_malformed_item_return_error() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.GetItem")
+            .body(SdkBody::from(
+                r#"{"TableName":"some-table","Key":{"pk":{"S":"pk_value"}}}"#,
+            ))
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(
+                r#"{
+                  "Item": {
+                    "pk": {"S": "pk_value"},
+                    "scopes": {"S": "scope1"}
+                    }
+                  }"#,
+            ))
+            .unwrap();
+        let conn = UnitTestHelper::buil_test_connnection(Some(request), Some(response));
+        let dynamo_db_client = UnitTestHelper::dynamo_fake_client(&conn).await;
+
+        let query = GetScope::builder()
+            .table_name("some-table".to_owned())
+            .dynamo_db_client(dynamo_db_client)
+            .build();
+
+        // ACT
+        let result = query.execute("pk_value").await;
+
+        // ASSERT
+        assert!(result.unwrap_err().to_string().contains("scopes"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn given_a_dynamodb_error_return_error() -> Result<(), ApplicationError> {
         // ARRANGE
@@ -201,4 +359,126 @@ mod tests {
 
         Ok(())
     }
+
+    fn get_item_event(status: u16, body: &str) -> (http::Request<SdkBody>, Response<SdkBody>) {
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.GetItem")
+            .body(SdkBody::from(
+                r#"{"TableName":"some-table","Key":{"pk":{"S":"pk_value"}}}"#,
+            ))
+            .unwrap();
+        let response = Response::builder()
+            .status(status)
+            .body(SdkBody::from(body.to_string()))
+            .unwrap();
+        (request, response)
+    }
+
+    const ITEM: &str = r#"{"Item": {"pk": {"S": "pk_value"}, "scopes": {"L": [{"S": "scope1"}]}}}"#;
+    const INTERNAL_SERVER_ERROR: &str =
+        r#"{"__type": "com.amazonaws.dynamodb.v20120810#InternalServerError", "message": "boom"}"#;
+
+    async fn get_query(
+        primary: &TestConnection<SdkBody>,
+        secondary: &TestConnection<SdkBody>,
+    ) -> GetScope {
+        GetScope::builder()
+            .table_name("some-table")
+            .dynamo_db_client(
+                UnitTestHelper::dynamo_fake_client_in_region(primary, "eu-central-1").await,
+            )
+            .region("eu-central-1")
+            .failover(vec![RegionClient {
+                region: "eu-west-1".to_string(),
+                client: UnitTestHelper::dynamo_fake_client_in_region(secondary, "eu-west-1").await,
+            }])
+            .circuit_breaker(CircuitBreaker::builder().failure_threshold(1).build())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn fail_over_to_the_next_region_on_a_server_error() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let primary = TestConnection::new(vec![get_item_event(500, INTERNAL_SERVER_ERROR)]);
+        let secondary = TestConnection::new(vec![get_item_event(200, ITEM)]);
+        let query = get_query(&primary, &secondary).await;
+
+        // ACT
+        let result = query.lookup("pk_value").await?;
+
+        // ASSERT
+        assert_eq!(
+            result,
+            ScopeLookup {
+                policy: Some(vec!["scope1".to_string()].into()),
+                region: "eu-west-1".to_string(),
+            }
+        );
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(secondary.requests().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn record_the_failover() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let primary = TestConnection::new(vec![
+            get_item_event(200, ITEM),
+            get_item_event(500, INTERNAL_SERVER_ERROR),
+        ]);
+        let secondary = TestConnection::new(vec![get_item_event(200, ITEM)]);
+        let query = get_query(&primary, &secondary).await;
+
+        // ACT
+        let (_, primary_degradations) = degradation::collect(query.execute("pk_value")).await;
+        let (result, failover_degradations) = degradation::collect(query.execute("pk_value")).await;
+
+        // ASSERT
+        assert_eq!(result?, Some(vec!["scope1".to_string()].into()));
+        assert!(primary_degradations.is_empty());
+        assert_eq!(failover_degradations[0].mode, DegradedMode::Failover);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_the_region_with_an_open_circuit() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let primary = TestConnection::new(vec![get_item_event(500, INTERNAL_SERVER_ERROR)]);
+        let secondary =
+            TestConnection::new(vec![get_item_event(200, ITEM), get_item_event(200, ITEM)]);
+        let query = get_query(&primary, &secondary).await;
+
+        // ACT
+        query.execute("pk_value").await?;
+        let result = query.lookup("pk_value").await?;
+
+        // ASSERT
+        assert_eq!(result.region, "eu-west-1");
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(secondary.requests().len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn do_not_fail_over_on_a_client_error() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let primary = TestConnection::new(vec![get_item_event(
+            400,
+            r#"{"__type": "com.amazon.coral.validate#ValidationException", "message": "bad key"}"#,
+        )]);
+        let secondary = TestConnection::new(vec![]);
+        let query = get_query(&primary, &secondary).await;
+
+        // ACT
+        let result = query.lookup("pk_value").await;
+
+        // ASSERT
+        assert!(result.is_err());
+        assert_eq!(secondary.requests().len(), 0);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,330 @@
+use crate::utils::degradation::{self, DegradedMode, Dependency, FailureMode};
+use serde::{Deserialize, Serialize};
+use shared::{error::ApplicationError, utils::dynamodb::from_item};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use typed_builder::TypedBuilder as Builder;
+
+/// The scopes granted to the IAM principals whose ARN matches `pk`,
+/// a pattern like `arn:aws:sts::123456789012:assumed-role/billing-*` where `*` matches anything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IamGrant {
+    pub pk: String,
+
+    pub scopes: Vec<String>,
+
+    /// The principal of the decision, the ARN of the caller by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<String>,
+}
+
+impl IamGrant {
+    pub fn matches(&self, arn: &str) -> bool {
+        matches_pattern(self.pk.as_bytes(), arn.as_bytes())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    grants: Arc<Vec<IamGrant>>,
+    loaded_at: Instant,
+}
+
+/// Scans the grants, few and matched by pattern, and keeps them for `cache_ttl`.
+/// When a scan fails, `failure_mode` can serve the expired grants or deny.
+#[derive(Debug, Clone, Builder)]
+pub struct GetIamGrants {
+    #[builder(setter(into))]
+    pub table_name: String,
+
+    pub dynamo_db_client: aws_sdk_dynamodb::Client,
+
+    #[builder(default = Duration::from_secs(60))]
+    cache_ttl: Duration,
+
+    #[builder(default)]
+    failure_mode: FailureMode,
+
+    #[builder(default, setter(skip))]
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+}
+
+impl GetIamGrants {
+    /// Enabled by `IAM_GRANT_TABLE_NAME`.
+    pub fn from_env(
+        dynamo_db_client: &aws_sdk_dynamodb::Client,
+        failure_mode: FailureMode,
+    ) -> Option<Self> {
+        let table_name = std::env::var("IAM_GRANT_TABLE_NAME").ok()?;
+
+        Some(
+            GetIamGrants::builder()
+                .table_name(table_name)
+                .dynamo_db_client(dynamo_db_client.clone())
+                .failure_mode(failure_mode)
+                .build(),
+        )
+    }
+
+    pub async fn execute(&self, arn: &str) -> Result<Vec<IamGrant>, ApplicationError> {
+        let grants = self.grants().await?;
+
+        Ok(grants
+            .iter()
+            .filter(|grant| grant.matches(arn))
+            .cloned()
+            .collect())
+    }
+
+    async fn grants(&self) -> Result<Arc<Vec<IamGrant>>, ApplicationError> {
+        let snapshot = self.snapshot.lock().unwrap().clone();
+        if let Some(snapshot) = &snapshot {
+            if snapshot.loaded_at.elapsed() < self.cache_ttl {
+                return Ok(snapshot.grants.clone());
+            }
+        }
+
+        let error = match self.scan().await {
+            Ok(grants) => {
+                let grants = Arc::new(grants);
+                *self.snapshot.lock().unwrap() = Some(Snapshot {
+                    grants: grants.clone(),
+                    loaded_at: Instant::now(),
+                });
+                return Ok(grants);
+            }
+            Err(e) => e,
+        };
+
+        match self.failure_mode {
+            FailureMode::Error => Err(error),
+            FailureMode::Deny => {
+                degradation::record(Dependency::IamGrants, DegradedMode::Deny);
+                Ok(Arc::default())
+            }
+            FailureMode::Stale { max_staleness } => match snapshot
+                .filter(|snapshot| snapshot.loaded_at.elapsed() < self.cache_ttl + max_staleness)
+            {
+                Some(snapshot) => {
+                    degradation::record(Dependency::IamGrants, DegradedMode::Stale);
+                    Ok(snapshot.grants)
+                }
+                None => Err(error),
+            },
+        }
+    }
+
+    async fn scan(&self) -> Result<Vec<IamGrant>, ApplicationError> {
+        let mut grants = Vec::new();
+        let mut exclusive_start_key = None;
+        loop {
+            let result = self
+                .dynamo_db_client
+                .scan()
+                .table_name(&self.table_name)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in result.items.unwrap_or_default() {
+                match from_item::<IamGrant>(&item) {
+                    Ok(grant) => grants.push(grant),
+                    Err(e) => println!("skipping IAM grant {:?}", e),
+                }
+            }
+
+            exclusive_start_key = result.last_evaluated_key.filter(|key| !key.is_empty());
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(grants)
+    }
+}
+
+/// Glob matching where `*` matches any sequence, `/` included, and `?` any character.
+/// Iterative, a mismatch only resumes after the last `*`, so the caller's session name cannot make it backtrack.
+fn matches_pattern(pattern: &[u8], value: &[u8]) -> bool {
+    let (mut p, mut v) = (0, 0);
+    // the position of the last `*` and of the value it was resumed from
+    let mut star = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, v));
+                p += 1;
+            }
+            Some(&expected) if expected == b'?' || expected == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match star {
+                Some((star_p, star_v)) => {
+                    // the star consumes one more character
+                    star = Some((star_p, star_v + 1));
+                    p = star_p + 1;
+                    v = star_v + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    #[test]
+    fn match_the_globs() {
+        // ARRANGE
+        let arn = "arn:aws:sts::123456789012:assumed-role/billing-admin/session";
+
+        // ACT
+        let star = matches_pattern(b"arn:aws:sts::*:assumed-role/billing-*", arn.as_bytes());
+        let question = matches_pattern(b"arn:aws:sts::12345678901?:*", arn.as_bytes());
+        let trailing = matches_pattern(b"arn:aws:sts::*:assumed-role/billing-*/x", arn.as_bytes());
+        let empty_star = matches_pattern(b"*arn:aws:sts::*session*", arn.as_bytes());
+
+        // ASSERT
+        assert!(star);
+        assert!(question);
+        assert!(!trailing);
+        assert!(empty_star);
+    }
+
+    #[test]
+    fn do_not_backtrack_on_a_long_non_matching_arn() {
+        // ARRANGE
+        let arn = format!(
+            "arn:aws:sts::123456789012:assumed-role/billing/{}",
+            ":".repeat(100_000)
+        );
+
+        // ACT
+        let matched = matches_pattern(b"arn:*:*:*:*:*:*x", arn.as_bytes());
+
+        // ASSERT
+        assert!(!matched);
+    }
+
+    #[tokio::test]
+    async fn match_the_role_patterns_from_one_scan() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.Scan")
+            .body(SdkBody::empty())
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(
+                r#"{"Items": [
+                    {"pk": {"S": "arn:aws:sts::123456789012:assumed-role/billing-*"}, "scopes": {"SS": ["invoices.read"]}, "principal": {"S": "billing"}},
+                    {"pk": {"S": "arn:aws:iam::123456789012:role/reporting"}, "scopes": {"SS": ["reports.read"]}}
+                ]}"#,
+            ))
+            .unwrap();
+        let conn = TestConnection::new(vec![(request, response)]);
+        let query = GetIamGrants::builder()
+            .table_name("iam-grants")
+            .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+            .build();
+
+        // ACT
+        let billing = query
+            .execute("arn:aws:sts::123456789012:assumed-role/billing-api/session-1")
+            .await?;
+        let other_account = query
+            .execute("arn:aws:sts::210987654321:assumed-role/billing-api/session-1")
+            .await?;
+
+        // ASSERT
+        assert_eq!(billing.len(), 1);
+        assert_eq!(billing[0].principal, Some("billing".to_string()));
+        assert!(other_account.is_empty());
+        assert_eq!(conn.requests().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn apply_the_failure_mode_when_the_scan_fails() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = || {
+            UnitTestHelper::dynamodb_request_builder()
+                .header("x-amz-target", "DynamoDB_20120810.Scan")
+                .body(SdkBody::empty())
+                .unwrap()
+        };
+        let failure = || {
+            Response::builder()
+                .status(400)
+                .body(SdkBody::from("{}"))
+                .unwrap()
+        };
+        let conn = TestConnection::new(vec![
+            (
+                request(),
+                Response::builder()
+                    .status(200)
+                    .body(SdkBody::from(
+                        r#"{"Items": [{"pk": {"S": "arn:aws:iam::123456789012:role/reporting"}, "scopes": {"SS": ["reports.read"]}}]}"#,
+                    ))
+                    .unwrap(),
+            ),
+            (request(), failure()),
+            (request(), failure()),
+            (request(), failure()),
+            (request(), failure()),
+        ]);
+        let arn = "arn:aws:iam::123456789012:role/reporting";
+        let stale = GetIamGrants::builder()
+            .table_name("iam-grants")
+            .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+            .cache_ttl(Duration::ZERO)
+            .failure_mode(FailureMode::Stale {
+                max_staleness: Duration::from_secs(60),
+            })
+            .build();
+        let deny = GetIamGrants {
+            failure_mode: FailureMode::Deny,
+            ..stale.clone()
+        };
+        let error = GetIamGrants {
+            failure_mode: FailureMode::Error,
+            ..stale.clone()
+        };
+        let expired = GetIamGrants {
+            failure_mode: FailureMode::Stale {
+                max_staleness: Duration::ZERO,
+            },
+            ..stale.clone()
+        };
+        stale.execute(arn).await?;
+
+        // ACT
+        let (stale_grants, stale_degradations) = degradation::collect(stale.execute(arn)).await;
+        let (denied_grants, deny_degradations) = degradation::collect(deny.execute(arn)).await;
+        let error_grants = error.execute(arn).await;
+        let expired_grants = expired.execute(arn).await;
+
+        // ASSERT
+        assert_eq!(stale_grants?.len(), 1);
+        assert_eq!(stale_degradations[0].mode, DegradedMode::Stale);
+        assert!(denied_grants?.is_empty());
+        assert_eq!(deny_degradations[0].mode, DegradedMode::Deny);
+        assert!(error_grants.is_err());
+        assert!(expired_grants.is_err());
+
+        Ok(())
+    }
+}
@@ -1 +1,15 @@
-pub mod get_scope;
\ No newline at end of file
+pub mod api_keys;
+pub mod bundle_get_scope;
+pub mod cached_batch_get;
+pub mod cached_get_scope;
+pub mod certificate_grants;
+pub mod file_get_scope;
+pub mod get_operation_rule;
+pub mod get_scope;
+pub mod iam_grants;
+pub mod preloaded_get_scope;
+pub mod principal_status;
+pub mod proof_replay;
+pub mod revocation_list;
+pub mod scope_store;
+pub mod webhook_integrations;
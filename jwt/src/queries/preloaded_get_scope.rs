@@ -0,0 +1,371 @@
+use crate::{
+    queries::get_scope::GetScopeQuery,
+    utils::{
+        degradation::{self, DegradedMode, Dependency, FailureMode},
+        route_rule::{RoutePolicy, RouteRule},
+        scope_index::ScopeIndex,
+    },
+};
+use async_trait::async_trait;
+use shared::error::ApplicationError;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::time::MissedTickBehavior;
+use typed_builder::TypedBuilder as Builder;
+
+/// Scans the whole scope table into a `ScopeIndex` at cold start, with `start`, and answers from memory.
+/// The snapshot is then refreshed by a background task every `refresh_interval`; when a refresh fails the last good one is kept.
+/// When the cold start scan fails, `failure_mode` can deny until a refresh succeeds, there is no snapshot to serve stale.
+/// A snapshot older than `refresh_interval` is served as stale for at most `max_staleness`, then `failure_mode` applies.
+#[derive(Debug, Clone, Builder)]
+pub struct PreloadedGetScope {
+    #[builder(setter(into))]
+    table_name: String,
+
+    #[builder(default, setter(strip_option))]
+    pub dynamo_db_client: Option<aws_sdk_dynamodb::Client>,
+
+    #[builder(default = Duration::from_secs(300))]
+    refresh_interval: Duration,
+
+    #[builder(default)]
+    failure_mode: FailureMode,
+
+    #[builder(default = Duration::from_secs(3600))]
+    max_staleness: Duration,
+
+    #[builder(default, setter(skip))]
+    snapshot: Arc<RwLock<Option<Arc<ScopeIndex>>>>,
+
+    #[builder(default, setter(skip))]
+    refreshed_at: Arc<Mutex<Option<Instant>>>,
+
+    #[builder(default, setter(skip))]
+    refresher_started: Arc<AtomicBool>,
+}
+
+impl PreloadedGetScope {
+    /// Scans the table before the first request and starts the refresher.
+    /// A failed scan fails the start, unless the failure mode denies meanwhile.
+    pub async fn start(&self) -> Result<(), ApplicationError> {
+        if let Err(e) = self.refresh().await {
+            if self.failure_mode != FailureMode::Deny {
+                return Err(e);
+            }
+            println!(
+                "scope table cold start scan failed, denying until a refresh {:?}",
+                e
+            );
+        }
+        self.refresh_periodically();
+
+        Ok(())
+    }
+
+    /// Scans all the pages of the table and swaps the snapshot.
+    pub async fn refresh(&self) -> Result<(), ApplicationError> {
+        let mut rules = Vec::new();
+        let mut exclusive_start_key = None;
+        loop {
+            let result = self
+                .dynamo_db_client
+                .as_ref()
+                .unwrap()
+                .scan()
+                .table_name(&self.table_name)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in result.items.unwrap_or_default() {
+                match RouteRule::from_item(&item) {
+                    Ok(rule) => rules.push((rule.pk, rule.policy)),
+                    Err(e) => println!("skipping scope rule {:?}", e),
+                }
+            }
+
+            exclusive_start_key = result.last_evaluated_key.filter(|key| !key.is_empty());
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        let index = ScopeIndex::new(rules);
+        println!("scope table loaded with {} rules", index.len());
+        *self.snapshot.write().unwrap() = Some(Arc::new(index));
+        *self.refreshed_at.lock().unwrap() = Some(Instant::now());
+
+        Ok(())
+    }
+
+    /// Starts the task refreshing the snapshot every `refresh_interval`, once.
+    fn refresh_periodically(&self) {
+        if self.refresher_started.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let query = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(query.refresh_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // the first tick completes immediately, the table was just scanned
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = query.refresh().await {
+                    let age = query
+                        .refreshed_at
+                        .lock()
+                        .unwrap()
+                        .map(|refreshed_at| refreshed_at.elapsed());
+                    println!(
+                        "scope table refresh failed, keeping the snapshot refreshed {:?} ago {:?}",
+                        age, e
+                    );
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl GetScopeQuery for PreloadedGetScope {
+    async fn execute(&self, api: &str) -> Result<Option<RoutePolicy>, ApplicationError> {
+        let snapshot = self.snapshot.read().unwrap().clone();
+        let snapshot = match snapshot {
+            Some(snapshot) => snapshot,
+            // the cold start scan failed and no refresh succeeded yet
+            None => return self.unavailable("The scope table is not loaded".to_string()),
+        };
+
+        let age = self
+            .refreshed_at
+            .lock()
+            .unwrap()
+            .map_or(Duration::ZERO, |refreshed_at| refreshed_at.elapsed());
+        if age > self.refresh_interval + self.max_staleness {
+            return self.unavailable(format!("The scope table snapshot is {:?} old", age));
+        }
+        if age > self.refresh_interval {
+            degradation::record(Dependency::ScopeRules, DegradedMode::Stale);
+        }
+
+        Ok(snapshot.find(api))
+    }
+}
+
+impl PreloadedGetScope {
+    /// Without a snapshot to serve, denies or fails as `failure_mode` says.
+    fn unavailable(&self, message: String) -> Result<Option<RoutePolicy>, ApplicationError> {
+        match self.failure_mode {
+            FailureMode::Deny => {
+                degradation::record(Dependency::ScopeRules, DegradedMode::Deny);
+                Ok(None)
+            }
+            FailureMode::Error | FailureMode::Stale { .. } => {
+                Err(ApplicationError::InternalError(message))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    fn scan_event(
+        response_status: u16,
+        response_body: &str,
+    ) -> (http::Request<SdkBody>, Response<SdkBody>) {
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.Scan")
+            .body(SdkBody::from(r#"{"TableName":"some-table"}"#))
+            .unwrap();
+        let response = Response::builder()
+            .status(response_status)
+            .body(SdkBody::from(response_body.to_string()))
+            .unwrap();
+        (request, response)
+    }
+
+    async fn get_query(conn: &TestConnection<SdkBody>) -> PreloadedGetScope {
+        PreloadedGetScope::builder()
+            .table_name("some-table")
+            .dynamo_db_client(UnitTestHelper::dynamo_fake_client(conn).await)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn scan_all_pages_once_and_answer_from_memory() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let conn = TestConnection::new(vec![
+            scan_event(
+                200,
+                r#"{
+                  "Items": [{"pk": {"S": "GET/one"}, "scopes": {"L": [{"S": "my-audience.read"}]}}],
+                  "LastEvaluatedKey": {"pk": {"S": "GET/one"}}
+                }"#,
+            ),
+            scan_event(
+                200,
+                r#"{
+                  "Items": [{"pk": {"S": "GET/items/"}, "scopes": {"L": [{"S": "items.read"}]}}]
+                }"#,
+            ),
+        ]);
+        let query = get_query(&conn).await;
+
+        // ACT
+        query.start().await?;
+        let one = query.execute("GET/one/").await?;
+        let item = query.execute("GET/items/").await?;
+        let missing = query.execute("POST/two/").await?;
+
+        // ASSERT
+        assert_eq!(one, Some(vec!["my-audience.read".to_string()].into()));
+        assert_eq!(item, Some(vec!["items.read".to_string()].into()));
+        assert_eq!(missing, None);
+        assert_eq!(conn.requests().len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keep_the_last_snapshot_when_refresh_fails() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let conn = TestConnection::new(vec![
+            scan_event(
+                200,
+                r#"{"Items": [{"pk": {"S": "GET/one/"}, "scopes": {"L": [{"S": "my-audience.read"}]}}]}"#,
+            ),
+            scan_event(400, "{}"),
+        ]);
+        let query = get_query(&conn).await;
+        query.start().await?;
+
+        // ACT
+        let refresh = query.refresh().await;
+        let result = query.execute("GET/one/").await?;
+
+        // ASSERT
+        assert!(refresh.is_err());
+        assert_eq!(result, Some(vec!["my-audience.read".to_string()].into()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refresh_periodically_and_only_mark_the_successful_scans(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut events = vec![scan_event(
+            200,
+            r#"{"Items": [{"pk": {"S": "GET/one/"}, "scopes": {"L": [{"S": "my-audience.read"}]}}]}"#,
+        )];
+        events.extend((0..10).map(|_| scan_event(400, "{}")));
+        let conn = TestConnection::new(events);
+        let query = PreloadedGetScope::builder()
+            .table_name("some-table")
+            .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+            .refresh_interval(Duration::from_millis(20))
+            .build();
+        query.start().await?;
+        let loaded_at = *query.refreshed_at.lock().unwrap();
+
+        // ACT
+        tokio::time::sleep(Duration::from_millis(70)).await;
+        let result = query.execute("GET/one/").await?;
+
+        // ASSERT
+        assert!(conn.requests().len() >= 3);
+        assert_eq!(*query.refreshed_at.lock().unwrap(), loaded_at);
+        assert_eq!(result, Some(vec!["my-audience.read".to_string()].into()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_error_when_the_cold_start_scan_fails() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let conn = TestConnection::new(vec![scan_event(400, "{}")]);
+        let query = get_query(&conn).await;
+
+        // ACT
+        let result = query.start().await;
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_when_the_cold_start_scan_fails_and_the_failure_mode_denies(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let conn = TestConnection::new(vec![scan_event(400, "{}")]);
+        let query = PreloadedGetScope::builder()
+            .table_name("some-table")
+            .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+            .failure_mode(FailureMode::Deny)
+            .build();
+
+        // ACT
+        let started = query.start().await;
+        let (result, degradations) = degradation::collect(query.execute("GET/one/")).await;
+
+        // ASSERT
+        assert!(started.is_ok());
+        assert_eq!(result?, None);
+        assert_eq!(degradations[0].mode, DegradedMode::Deny);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mark_the_stale_snapshot_then_deny_after_the_max_staleness(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let conn = TestConnection::new(vec![scan_event(
+            200,
+            r#"{"Items": [{"pk": {"S": "GET/one/"}, "scopes": {"L": [{"S": "my-audience.read"}]}}]}"#,
+        )]);
+        let query = PreloadedGetScope::builder()
+            .table_name("some-table")
+            .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+            .refresh_interval(Duration::from_secs(300))
+            .max_staleness(Duration::from_secs(600))
+            .failure_mode(FailureMode::Deny)
+            .build();
+        query.refresh().await?;
+        let refreshed_at = |age: u64| {
+            *query.refreshed_at.lock().unwrap() = Some(Instant::now() - Duration::from_secs(age));
+        };
+
+        // ACT
+        let (fresh, fresh_degradations) = degradation::collect(query.execute("GET/one/")).await;
+        refreshed_at(400);
+        let (stale, stale_degradations) = degradation::collect(query.execute("GET/one/")).await;
+        refreshed_at(1000);
+        let (denied, deny_degradations) = degradation::collect(query.execute("GET/one/")).await;
+
+        // ASSERT
+        assert_eq!(fresh?, Some(vec!["my-audience.read".to_string()].into()));
+        assert!(fresh_degradations.is_empty());
+        assert_eq!(stale?, Some(vec!["my-audience.read".to_string()].into()));
+        assert_eq!(stale_degradations[0].mode, DegradedMode::Stale);
+        assert_eq!(denied?, None);
+        assert_eq!(deny_degradations[0].mode, DegradedMode::Deny);
+
+        Ok(())
+    }
+}
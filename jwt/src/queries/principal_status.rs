@@ -0,0 +1,227 @@
+use crate::{
+    queries::cached_batch_get::CachedBatchGet,
+    utils::{authorizer::Claims, decision::Reason},
+};
+use serde::{Deserialize, Serialize};
+use shared::{error::ApplicationError, utils::dynamodb::Timestamp};
+use std::time::{Duration, SystemTime};
+use typed_builder::TypedBuilder as Builder;
+
+/// The status of a user, `sub#<sub>`, or of an application, `client#<client_id>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrincipalStatus {
+    pub pk: String,
+
+    #[serde(default)]
+    pub suspended: bool,
+
+    /// Tokens issued before, like the ones of a password that was reset, are superseded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<Timestamp>,
+}
+
+impl PrincipalStatus {
+    pub fn denial(&self, claims: &Claims) -> Option<Reason> {
+        if self.suspended {
+            return Some(Reason::PrincipalSuspended);
+        }
+
+        let not_before = self.not_before?.0;
+        // without `iat` the token may have been issued before `not_before`
+        let superseded = claims.iat.is_none_or(|iat| {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(iat as u64) < not_before
+        });
+        superseded.then_some(Reason::TokenSuperseded)
+    }
+}
+
+/// Reads the status of the subject and of the client of a token.
+#[derive(Debug, Clone, Builder)]
+pub struct GetPrincipalStatus {
+    lookup: CachedBatchGet<PrincipalStatus>,
+}
+
+impl GetPrincipalStatus {
+    /// Enabled by `PRINCIPAL_TABLE_NAME`, a missing one is logged at startup. The statuses, and their absence, are cached for
+    /// `PRINCIPAL_CACHE_TTL` seconds, 30 by default and 0 to read them on every request,
+    /// so a suspension can take that long to apply.
+    pub fn from_env(
+        dynamo_db_client: &aws_sdk_dynamodb::Client,
+    ) -> Result<Option<Self>, ApplicationError> {
+        let table_name = match std::env::var("PRINCIPAL_TABLE_NAME") {
+            Ok(table_name) => table_name,
+            Err(_) => {
+                println!("PRINCIPAL_TABLE_NAME is not set, the principal statuses are not checked");
+                return Ok(None);
+            }
+        };
+        let cache_ttl = match std::env::var("PRINCIPAL_CACHE_TTL") {
+            Ok(cache_ttl) => Duration::from_secs(cache_ttl.parse().map_err(|_| {
+                ApplicationError::InitError(format!(
+                    "Invalid PRINCIPAL_CACHE_TTL {}, expected seconds",
+                    cache_ttl
+                ))
+            })?),
+            Err(_) => Duration::from_secs(30),
+        };
+
+        Ok(Some(
+            GetPrincipalStatus::builder()
+                .lookup(
+                    CachedBatchGet::builder()
+                        .table_name(table_name)
+                        .dynamo_db_client(dynamo_db_client.clone())
+                        .cache_ttl(cache_ttl)
+                        .build(),
+                )
+                .build(),
+        ))
+    }
+
+    pub async fn execute(&self, claims: &Claims) -> Result<Vec<PrincipalStatus>, ApplicationError> {
+        let keys = std::iter::once(format!("sub#{}", claims.sub))
+            .chain(
+                claims
+                    .client_id
+                    .iter()
+                    .map(|client_id| format!("client#{}", client_id)),
+            )
+            .collect();
+
+        self.lookup.get(keys).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use serde_json::json;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    fn get_claims(iat: Option<u64>) -> Claims {
+        serde_json::from_value(json!({
+            "aud": "my-audience",
+            "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+            "email": "a@a.com",
+            "exp": 4102444800u64,
+            "iat": iat,
+            "azp": "my-app",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_the_status_of_the_subject_and_the_client() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.BatchGetItem")
+            .body(SdkBody::empty())
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(
+                r#"{"Responses": {"principals": [
+                    {"pk": {"S": "sub#12408bde-207d-45a5-a143-6aa02f049df7"}, "not_before": {"N": "1665000000"}},
+                    {"pk": {"S": "client#my-app"}, "suspended": {"BOOL": true}}
+                ]}}"#,
+            ))
+            .unwrap();
+        let conn = TestConnection::new(vec![(request, response)]);
+        let query = GetPrincipalStatus::builder()
+            .lookup(
+                CachedBatchGet::builder()
+                    .table_name("principals")
+                    .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+                    .build(),
+            )
+            .build();
+
+        // ACT
+        let statuses = query.execute(&get_claims(Some(1665001000))).await?;
+
+        // ASSERT
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(
+            statuses[0].not_before,
+            Some(Timestamp(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1665000000)
+            ))
+        );
+        assert_eq!(
+            statuses[1].denial(&get_claims(Some(1665001000))),
+            Some(Reason::PrincipalSuspended)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_the_status_on_every_request_without_cache_ttl() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let event = |body: &'static str| {
+            (
+                UnitTestHelper::dynamodb_request_builder()
+                    .header("x-amz-target", "DynamoDB_20120810.BatchGetItem")
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                Response::builder()
+                    .status(200)
+                    .body(SdkBody::from(body))
+                    .unwrap(),
+            )
+        };
+        let conn = TestConnection::new(vec![
+            event(r#"{"Responses": {"principals": []}}"#),
+            event(
+                r#"{"Responses": {"principals": [
+                    {"pk": {"S": "sub#12408bde-207d-45a5-a143-6aa02f049df7"}, "suspended": {"BOOL": true}}
+                ]}}"#,
+            ),
+        ]);
+        let query = GetPrincipalStatus::builder()
+            .lookup(
+                CachedBatchGet::builder()
+                    .table_name("principals")
+                    .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+                    .cache_ttl(Duration::ZERO)
+                    .build(),
+            )
+            .build();
+
+        // ACT
+        let before = query.execute(&get_claims(Some(1665001000))).await?;
+        let after = query.execute(&get_claims(Some(1665001000))).await?;
+
+        // ASSERT
+        assert!(before.is_empty());
+        assert!(after[0].suspended);
+        assert_eq!(conn.requests().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn supersede_the_tokens_issued_before_not_before() {
+        // ARRANGE
+        let status = PrincipalStatus {
+            pk: "sub#12408bde-207d-45a5-a143-6aa02f049df7".to_string(),
+            suspended: false,
+            not_before: Some(Timestamp(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1665000000),
+            )),
+        };
+
+        // ACT
+        let before = status.denial(&get_claims(Some(1664999000)));
+        let after = status.denial(&get_claims(Some(1665001000)));
+        let without_iat = status.denial(&get_claims(None));
+
+        // ASSERT
+        assert_eq!(before, Some(Reason::TokenSuperseded));
+        assert_eq!(after, None);
+        assert_eq!(without_iat, Some(Reason::TokenSuperseded));
+    }
+}
@@ -0,0 +1,219 @@
+use aws_sdk_dynamodb::{error::PutItemErrorKind, model::AttributeValue, types::SdkError};
+use shared::error::ApplicationError;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use typed_builder::TypedBuilder as Builder;
+
+/// Remembers the `jti` of the proofs until they expire, to reject their replay.
+/// Without a table the proofs are only remembered by this instance, at most `max_entries` of them;
+/// when full the oldest is forgotten, the conditional put on the table being the authoritative check.
+#[derive(Debug, Clone, Builder)]
+pub struct ProofReplay {
+    #[builder(default, setter(strip_option))]
+    pub dynamo_db_client: Option<aws_sdk_dynamodb::Client>,
+
+    #[builder(default, setter(strip_option, into))]
+    pub table_name: Option<String>,
+
+    #[builder(default = 10000)]
+    max_entries: usize,
+
+    #[builder(default, setter(skip))]
+    seen: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Default for ProofReplay {
+    fn default() -> Self {
+        ProofReplay::builder().build()
+    }
+}
+
+impl ProofReplay {
+    /// Enabled across the instances by `DPOP_REPLAY_TABLE_NAME`, a table with a TTL on `expires_at`.
+    pub fn from_env(dynamo_db_client: &aws_sdk_dynamodb::Client) -> Self {
+        ProofReplay::from_table_env(dynamo_db_client, "DPOP_REPLAY_TABLE_NAME")
+    }
+
+    /// Enabled across the instances by the table named in `variable`.
+    pub fn from_table_env(dynamo_db_client: &aws_sdk_dynamodb::Client, variable: &str) -> Self {
+        match std::env::var(variable) {
+            Ok(table_name) => ProofReplay::builder()
+                .dynamo_db_client(dynamo_db_client.clone())
+                .table_name(table_name)
+                .build(),
+            Err(_) => ProofReplay::builder().build(),
+        }
+    }
+
+    /// True the first time `key` is used within `ttl`.
+    pub async fn first_use(&self, key: &str, ttl: Duration) -> Result<bool, ApplicationError> {
+        if !self.remember(key, ttl) {
+            return Ok(false);
+        }
+
+        let (client, table_name) = match (&self.dynamo_db_client, &self.table_name) {
+            (Some(client), Some(table_name)) => (client, table_name),
+            _ => return Ok(true),
+        };
+        let expires_at = (SystemTime::now() + ttl)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let result = client
+            .put_item()
+            .table_name(table_name)
+            .item("pk", AttributeValue::S(key.to_string()))
+            .item("expires_at", AttributeValue::N(expires_at.to_string()))
+            .condition_expression("attribute_not_exists(pk)")
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    PutItemErrorKind::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn remember(&self, key: &str, ttl: Duration) -> bool {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap();
+        if seen.get(key).is_some_and(|expires_at| *expires_at > now) {
+            return false;
+        }
+        if seen.len() >= self.max_entries {
+            seen.retain(|_, expires_at| *expires_at > now);
+        }
+        // denying would let a single client lock out every other one with fresh keys
+        if seen.len() >= self.max_entries {
+            let oldest = seen
+                .iter()
+                .min_by_key(|(_, expires_at)| **expires_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                seen.remove(&oldest);
+            }
+        }
+        seen.insert(key.to_string(), now + ttl);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    #[tokio::test]
+    async fn reject_a_key_already_used_by_another_instance() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = || {
+            UnitTestHelper::dynamodb_request_builder()
+                .header("x-amz-target", "DynamoDB_20120810.PutItem")
+                .body(SdkBody::empty())
+                .unwrap()
+        };
+        let conflict = Response::builder()
+            .status(400)
+            .body(SdkBody::from(
+                r#"{"__type": "com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException", "message": "The conditional request failed"}"#,
+            ))
+            .unwrap();
+        let conn = TestConnection::new(vec![
+            (
+                request(),
+                Response::builder()
+                    .status(200)
+                    .body(SdkBody::from("{}"))
+                    .unwrap(),
+            ),
+            (request(), conflict),
+        ]);
+        let client = UnitTestHelper::dynamo_fake_client(&conn).await;
+        let this_instance = ProofReplay::builder()
+            .dynamo_db_client(client.clone())
+            .table_name("dpop-replay")
+            .build();
+        let other_instance = ProofReplay {
+            seen: Arc::default(),
+            ..this_instance.clone()
+        };
+
+        // ACT
+        let first = this_instance
+            .first_use("jti#abc", Duration::from_secs(60))
+            .await?;
+        let replayed_here = this_instance
+            .first_use("jti#abc", Duration::from_secs(60))
+            .await?;
+        let replayed_there = other_instance
+            .first_use("jti#abc", Duration::from_secs(60))
+            .await?;
+
+        // ASSERT
+        assert!(first);
+        assert!(!replayed_here);
+        assert!(!replayed_there);
+        assert_eq!(conn.requests().len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forget_the_oldest_key_when_full_and_let_the_table_decide(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let event = |status: u16, body: &'static str| {
+            (
+                UnitTestHelper::dynamodb_request_builder()
+                    .header("x-amz-target", "DynamoDB_20120810.PutItem")
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                Response::builder()
+                    .status(status)
+                    .body(SdkBody::from(body))
+                    .unwrap(),
+            )
+        };
+        let conn = TestConnection::new(vec![
+            event(200, "{}"),
+            event(200, "{}"),
+            event(
+                400,
+                r#"{"__type": "com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException", "message": "The conditional request failed"}"#,
+            ),
+        ]);
+        let replay = ProofReplay::builder()
+            .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+            .table_name("dpop-replay")
+            .max_entries(1)
+            .build();
+
+        // ACT
+        let first = replay.first_use("jti#abc", Duration::from_secs(60)).await?;
+        let other = replay.first_use("jti#def", Duration::from_secs(60)).await?;
+        let forgotten = replay.first_use("jti#abc", Duration::from_secs(60)).await?;
+
+        // ASSERT
+        assert!(first);
+        assert!(other);
+        assert!(!forgotten);
+        assert_eq!(conn.requests().len(), 3);
+
+        Ok(())
+    }
+}
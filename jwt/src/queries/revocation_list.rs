@@ -0,0 +1,246 @@
+use crate::{queries::cached_batch_get::CachedBatchGet, utils::authorizer::Claims};
+use serde::{Deserialize, Serialize};
+use shared::{
+    error::ApplicationError,
+    utils::dynamodb::{to_item, Timestamp},
+};
+use std::time::{Duration, SystemTime};
+use typed_builder::TypedBuilder as Builder;
+
+/// An item of the revocation table, deleted by the DynamoDB TTL on `expires_at`:
+/// `jti#<jti>` revokes one token until its `exp`, `sub#<sub>` all the tokens of the subject issued up to `revoked_before`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revocation {
+    pub pk: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_before: Option<Timestamp>,
+
+    pub expires_at: Timestamp,
+}
+
+impl Revocation {
+    pub fn token(jti: &str, exp: SystemTime) -> Self {
+        Revocation {
+            pk: format!("jti#{}", jti),
+            revoked_before: None,
+            expires_at: Timestamp(exp),
+        }
+    }
+
+    pub fn subject(sub: &str, revoked_before: SystemTime, max_token_lifetime: Duration) -> Self {
+        Revocation {
+            pk: format!("sub#{}", sub),
+            revoked_before: Some(Timestamp(revoked_before)),
+            expires_at: Timestamp(revoked_before + max_token_lifetime),
+        }
+    }
+
+    /// TTL deletes items up to days late, so expired items are ignored.
+    fn revokes(&self, claims: &Claims, now: SystemTime) -> bool {
+        if self.expires_at.0 <= now {
+            return false;
+        }
+
+        match self.revoked_before {
+            None => true,
+            // without `iat` the token may have been issued before the revocation
+            Some(revoked_before) => claims.iat.is_none_or(|iat| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(iat as u64) <= revoked_before.0
+            }),
+        }
+    }
+}
+
+/// The denylist of revoked tokens, checked by `jti` and `sub`.
+/// Lookups are cached, which bounds how long a revocation takes to apply.
+#[derive(Debug, Clone, Builder)]
+pub struct RevocationList {
+    lookup: CachedBatchGet<Revocation>,
+
+    /// How long a subject revocation is kept, at least the lifetime of the access tokens.
+    #[builder(default = Duration::from_secs(86400))]
+    max_token_lifetime: Duration,
+}
+
+impl RevocationList {
+    /// Enabled by `REVOCATION_TABLE_NAME`, a missing one is logged at startup.
+    pub fn from_env(dynamo_db_client: &aws_sdk_dynamodb::Client) -> Option<Self> {
+        let table_name = match std::env::var("REVOCATION_TABLE_NAME") {
+            Ok(table_name) => table_name,
+            Err(_) => {
+                println!("REVOCATION_TABLE_NAME is not set, the revoked tokens are accepted");
+                return None;
+            }
+        };
+
+        Some(
+            RevocationList::builder()
+                .lookup(
+                    CachedBatchGet::builder()
+                        .table_name(table_name)
+                        .dynamo_db_client(dynamo_db_client.clone())
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    pub async fn is_revoked(&self, claims: &Claims) -> Result<bool, ApplicationError> {
+        let keys = claims
+            .jti
+            .iter()
+            .map(|jti| format!("jti#{}", jti))
+            .chain(std::iter::once(format!("sub#{}", claims.sub)))
+            .collect();
+        let revocations = self.lookup.get(keys).await?;
+
+        let now = SystemTime::now();
+        Ok(revocations
+            .iter()
+            .any(|revocation| revocation.revokes(claims, now)))
+    }
+
+    /// Revokes one token until its `exp`.
+    pub async fn revoke_token(
+        &self,
+        jti: &str,
+        exp: SystemTime,
+    ) -> Result<Revocation, ApplicationError> {
+        self.put(Revocation::token(jti, exp)).await
+    }
+
+    /// Revokes all the tokens of the subject issued until now.
+    pub async fn revoke_subject(&self, sub: &str) -> Result<Revocation, ApplicationError> {
+        self.put(Revocation::subject(
+            sub,
+            SystemTime::now(),
+            self.max_token_lifetime,
+        ))
+        .await
+    }
+
+    async fn put(&self, revocation: Revocation) -> Result<Revocation, ApplicationError> {
+        self.lookup
+            .dynamo_db_client
+            .put_item()
+            .table_name(&self.lookup.table_name)
+            .set_item(Some(to_item(&revocation)?))
+            .send()
+            .await?;
+        self.lookup.invalidate(&revocation.pk);
+
+        Ok(revocation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use serde_json::json;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    fn get_claims(jti: Option<&str>, iat: Option<u64>) -> Claims {
+        serde_json::from_value(json!({
+            "aud": "my-audience",
+            "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+            "email": "a@a.com",
+            "exp": 4102444800u64,
+            "jti": jti,
+            "iat": iat,
+        }))
+        .unwrap()
+    }
+
+    fn batch_get_item_event(items: &str) -> (http::Request<SdkBody>, Response<SdkBody>) {
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.BatchGetItem")
+            .body(SdkBody::empty())
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(format!(
+                r#"{{"Responses": {{"revocations": [{}]}}, "UnprocessedKeys": {{}}}}"#,
+                items
+            )))
+            .unwrap();
+        (request, response)
+    }
+
+    async fn get_list(conn: &TestConnection<SdkBody>) -> RevocationList {
+        RevocationList::builder()
+            .lookup(
+                CachedBatchGet::builder()
+                    .table_name("revocations")
+                    .dynamo_db_client(UnitTestHelper::dynamo_fake_client(conn).await)
+                    .build(),
+            )
+            .build()
+    }
+
+    #[tokio::test]
+    async fn reject_a_revoked_jti_and_cache_the_lookup() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let conn = TestConnection::new(vec![batch_get_item_event(
+            r#"{"pk": {"S": "jti#abc"}, "expires_at": {"N": "4102444800"}}"#,
+        )]);
+        let list = get_list(&conn).await;
+        let claims = get_claims(Some("abc"), Some(1665000000));
+
+        // ACT
+        let first = list.is_revoked(&claims).await?;
+        let second = list.is_revoked(&claims).await?;
+
+        // ASSERT
+        assert!(first);
+        assert!(second);
+        assert_eq!(conn.requests().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_the_tokens_issued_before_the_subject_revocation() -> Result<(), ApplicationError>
+    {
+        // ARRANGE
+        let subject = r#"{
+            "pk": {"S": "sub#12408bde-207d-45a5-a143-6aa02f049df7"},
+            "revoked_before": {"N": "1665000000"},
+            "expires_at": {"N": "4102444800"}
+        }"#;
+        let conn = TestConnection::new(vec![
+            batch_get_item_event(subject),
+            batch_get_item_event(subject),
+        ]);
+        let list = get_list(&conn).await;
+
+        // ACT
+        let before = list
+            .is_revoked(&get_claims(Some("before"), Some(1664999000)))
+            .await?;
+        let after = list
+            .is_revoked(&get_claims(Some("after"), Some(1665001000)))
+            .await?;
+
+        // ASSERT
+        assert!(before);
+        assert!(!after);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_an_expired_revocation() {
+        // ARRANGE
+        let revocation = Revocation::token("abc", SystemTime::UNIX_EPOCH);
+
+        // ACT
+        let revoked = revocation.revokes(&get_claims(Some("abc"), None), SystemTime::now());
+
+        // ASSERT
+        assert!(!revoked);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::queries::{
+    bundle_get_scope::{BundleGetScope, BundleLocation, PolicyBundle},
+    cached_get_scope::CachedGetScope,
+    file_get_scope::FileGetScope,
+    get_scope::{GetScope, GetScopeQuery, RegionClient},
+    preloaded_get_scope::PreloadedGetScope,
+};
+use crate::utils::degradation::FailureMode;
+use shared::error::ApplicationError;
+use std::{sync::Arc, time::Duration};
+
+/// The backend of the scope rules, chosen with `SCOPE_STORE`:
+/// * `dynamodb` (default): one cached `GetItem` per route on `SCOPE_TABLE_NAME`,
+///   failing over to the comma separated `SCOPE_TABLE_FAILOVER_REGIONS` in order
+/// * `dynamodb-preload`: the whole `SCOPE_TABLE_NAME` scanned at cold start, its snapshot served
+///   at most `SCOPE_MAX_STALENESS` seconds after a missed refresh, 3600 by default
+/// * `file`: the JSON or YAML file at `SCOPE_FILE`
+/// * `bundle`: the signed `PolicyBundle` at `SCOPE_BUNDLE`, verified with the base64 Ed25519 `SCOPE_BUNDLE_PUBLIC_KEY`,
+///   and at least `SCOPE_BUNDLE_MIN_VERSION` when set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScopeStore {
+    DynamoDb {
+        table_name: String,
+        region: Option<String>,
+        failover_regions: Vec<String>,
+    },
+    DynamoDbPreload {
+        table_name: String,
+        max_staleness: Duration,
+    },
+    File {
+        path: String,
+    },
+    Bundle {
+        location: BundleLocation,
+        public_key: Vec<u8>,
+        min_version: Option<String>,
+    },
+}
+
+impl ScopeStore {
+    pub fn from_env() -> Result<Self, ApplicationError> {
+        let store = ScopeStore::new(
+            std::env::var("SCOPE_STORE").ok().as_deref(),
+            std::env::var("SCOPE_TABLE_NAME").ok(),
+            std::env::var("SCOPE_FILE").ok(),
+            std::env::var("SCOPE_BUNDLE").ok(),
+            std::env::var("SCOPE_BUNDLE_PUBLIC_KEY").ok(),
+        )?;
+
+        Ok(store
+            .with_max_staleness(std::env::var("SCOPE_MAX_STALENESS").ok().as_deref())?
+            .with_failover(
+                std::env::var("AWS_REGION").ok(),
+                std::env::var("SCOPE_TABLE_FAILOVER_REGIONS")
+                    .ok()
+                    .as_deref(),
+            )
+            .with_min_version(std::env::var("SCOPE_BUNDLE_MIN_VERSION").ok()))
+    }
+
+    /// Names the region of the default client and adds the replicas to try after it.
+    pub fn with_failover(self, region: Option<String>, failover_regions: Option<&str>) -> Self {
+        match self {
+            ScopeStore::DynamoDb { table_name, .. } => ScopeStore::DynamoDb {
+                table_name,
+                region,
+                failover_regions: failover_regions
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|region| !region.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            },
+            store => store,
+        }
+    }
+
+    /// Bounds how long the preloaded snapshot is served after a missed refresh.
+    pub fn with_max_staleness(self, max_staleness: Option<&str>) -> Result<Self, ApplicationError> {
+        match (self, max_staleness) {
+            (ScopeStore::DynamoDbPreload { table_name, .. }, Some(max_staleness)) => {
+                Ok(ScopeStore::DynamoDbPreload {
+                    table_name,
+                    max_staleness: Duration::from_secs(max_staleness.parse().map_err(|_| {
+                        ApplicationError::InitError(format!(
+                            "Invalid SCOPE_MAX_STALENESS {}, expected seconds",
+                            max_staleness
+                        ))
+                    })?),
+                })
+            }
+            (store, _) => Ok(store),
+        }
+    }
+
+    /// Refuses the bundles older than `min_version`, so a stale bundle cannot be rolled back to.
+    pub fn with_min_version(self, min_version: Option<String>) -> Self {
+        match self {
+            ScopeStore::Bundle {
+                location,
+                public_key,
+                ..
+            } => ScopeStore::Bundle {
+                location,
+                public_key,
+                min_version: min_version.filter(|version| !version.trim().is_empty()),
+            },
+            store => store,
+        }
+    }
+
+    pub fn bundle(location: &str, public_key: &str) -> Result<Self, ApplicationError> {
+        Ok(ScopeStore::Bundle {
+            location: BundleLocation::parse(location)?,
+            public_key: base64::decode(public_key.trim()).map_err(|e| {
+                ApplicationError::InitError(format!("Invalid SCOPE_BUNDLE_PUBLIC_KEY {}", e))
+            })?,
+            min_version: None,
+        })
+    }
+
+    pub fn new(
+        store: Option<&str>,
+        table_name: Option<String>,
+        path: Option<String>,
+        bundle: Option<String>,
+        public_key: Option<String>,
+    ) -> Result<Self, ApplicationError> {
+        let table_name = || {
+            table_name.ok_or_else(|| {
+                ApplicationError::InitError("SCOPE_TABLE_NAME must be set".to_string())
+            })
+        };
+
+        match store.unwrap_or("dynamodb") {
+            "dynamodb" => Ok(ScopeStore::DynamoDb {
+                table_name: table_name()?,
+                region: None,
+                failover_regions: Vec::new(),
+            }),
+            "dynamodb-preload" => Ok(ScopeStore::DynamoDbPreload {
+                table_name: table_name()?,
+                max_staleness: Duration::from_secs(3600),
+            }),
+            "file" => Ok(ScopeStore::File {
+                path: path.ok_or_else(|| {
+                    ApplicationError::InitError("SCOPE_FILE must be set".to_string())
+                })?,
+            }),
+            "bundle" => ScopeStore::bundle(
+                &bundle.ok_or_else(|| {
+                    ApplicationError::InitError("SCOPE_BUNDLE must be set".to_string())
+                })?,
+                &public_key.ok_or_else(|| {
+                    ApplicationError::InitError("SCOPE_BUNDLE_PUBLIC_KEY must be set".to_string())
+                })?,
+            ),
+            store => Err(ApplicationError::InitError(format!(
+                "Unknown SCOPE_STORE {}, expected dynamodb, dynamodb-preload, file or bundle",
+                store
+            ))),
+        }
+    }
+
+    /// The file and the bundle are read once at startup, with no lookup left to fail,
+    /// so only the default failure mode applies to them.
+    pub async fn build(
+        &self,
+        dynamo_db_client: aws_sdk_dynamodb::Client,
+        s3_client: aws_sdk_s3::Client,
+        failure_mode: FailureMode,
+    ) -> Result<Arc<dyn GetScopeQuery + Send + Sync>, ApplicationError> {
+        if matches!(self, ScopeStore::File { .. } | ScopeStore::Bundle { .. })
+            && failure_mode != FailureMode::Error
+        {
+            return Err(ApplicationError::InitError(
+                "SCOPE_FAILURE_MODE applies to the dynamodb and dynamodb-preload stores only"
+                    .to_string(),
+            ));
+        }
+
+        Ok(match self {
+            ScopeStore::DynamoDb {
+                table_name,
+                region,
+                failover_regions,
+            } => {
+                let mut failover = Vec::new();
+                for region in failover_regions {
+                    let config = aws_config::from_env()
+                        .region(aws_sdk_dynamodb::Region::new(region.clone()))
+                        .load()
+                        .await;
+                    failover.push(RegionClient {
+                        region: region.clone(),
+                        client: aws_sdk_dynamodb::Client::new(&config),
+                    });
+                }
+
+                Arc::new(
+                    CachedGetScope::builder()
+                        .query(
+                            GetScope::builder()
+                                .table_name(table_name)
+                                .dynamo_db_client(dynamo_db_client)
+                                .region(region.clone().unwrap_or_else(|| "default".to_string()))
+                                .failover(failover)
+                                .build(),
+                        )
+                        .failure_mode(failure_mode)
+                        .build(),
+                )
+            }
+            ScopeStore::DynamoDbPreload {
+                table_name,
+                max_staleness,
+            } => {
+                let query = PreloadedGetScope::builder()
+                    .table_name(table_name)
+                    .dynamo_db_client(dynamo_db_client)
+                    .failure_mode(failure_mode)
+                    .max_staleness(*max_staleness)
+                    .build();
+                query.start().await?;
+                Arc::new(query)
+            }
+            ScopeStore::File { path } => Arc::new(FileGetScope::load(path)?),
+            ScopeStore::Bundle {
+                location,
+                public_key,
+                min_version,
+            } => {
+                let bundle = PolicyBundle::load(location, public_key, &s3_client).await?;
+                if let Some(min_version) = min_version {
+                    bundle.check_min_version(min_version)?;
+                }
+                println!("policy bundle {} loaded", bundle.version);
+                Arc::new(BundleGetScope::from(bundle))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_to_dynamodb() -> Result<(), ApplicationError> {
+        // ACT
+        let store = ScopeStore::new(None, Some("scopes".to_string()), None, None, None)?;
+
+        // ASSERT
+        assert_eq!(
+            store,
+            ScopeStore::DynamoDb {
+                table_name: "scopes".to_string(),
+                region: None,
+                failover_regions: Vec::new(),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_the_failover_regions() -> Result<(), ApplicationError> {
+        // ACT
+        let store = ScopeStore::new(
+            Some("dynamodb"),
+            Some("scopes".to_string()),
+            None,
+            None,
+            None,
+        )?
+        .with_failover(
+            Some("eu-central-1".to_string()),
+            Some("eu-west-1, eu-south-1"),
+        );
+
+        // ASSERT
+        assert_eq!(
+            store,
+            ScopeStore::DynamoDb {
+                table_name: "scopes".to_string(),
+                region: Some("eu-central-1".to_string()),
+                failover_regions: vec!["eu-west-1".to_string(), "eu-south-1".to_string()],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_the_max_staleness_of_the_preloaded_snapshot() -> Result<(), ApplicationError> {
+        // ACT
+        let store = ScopeStore::new(
+            Some("dynamodb-preload"),
+            Some("scopes".to_string()),
+            None,
+            None,
+            None,
+        )?
+        .with_max_staleness(Some("600"))?;
+        let invalid = store.clone().with_max_staleness(Some("ten minutes"));
+
+        // ASSERT
+        assert_eq!(
+            store,
+            ScopeStore::DynamoDbPreload {
+                table_name: "scopes".to_string(),
+                max_staleness: Duration::from_secs(600),
+            }
+        );
+        assert!(invalid.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_when_the_configuration_is_not_valid() -> Result<(), ApplicationError> {
+        // ACT
+        let missing_file =
+            ScopeStore::new(Some("file"), Some("scopes".to_string()), None, None, None);
+        let unknown = ScopeStore::new(Some("redis"), None, None, None, None);
+        let bundle = ScopeStore::bundle("s3://bundles", "AAAA");
+        let missing_public_key = ScopeStore::new(
+            Some("bundle"),
+            None,
+            None,
+            Some("s3://bundles/bundle.json".to_string()),
+            None,
+        );
+
+        // ASSERT
+        assert!(missing_file.is_err());
+        assert!(unknown.is_err());
+        assert!(bundle.is_err());
+        assert!(missing_public_key.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_error_when_the_file_store_has_a_failure_mode() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let config = aws_config::from_env()
+            .region(aws_sdk_dynamodb::Region::new("eu-central-1"))
+            .load()
+            .await;
+        let store = ScopeStore::File {
+            path: "scopes.json".to_string(),
+        };
+
+        // ACT
+        let result = store
+            .build(
+                aws_sdk_dynamodb::Client::new(&config),
+                aws_sdk_s3::Client::new(&config),
+                FailureMode::Deny,
+            )
+            .await;
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_the_bundle_with_its_min_version() -> Result<(), ApplicationError> {
+        // ACT
+        let store = ScopeStore::new(
+            Some("bundle"),
+            None,
+            None,
+            Some("s3://bundles/bundle.json".to_string()),
+            Some("AAAA".to_string()),
+        )?
+        .with_min_version(Some("2022-10-01.1".to_string()));
+
+        // ASSERT
+        assert_eq!(
+            store,
+            ScopeStore::Bundle {
+                location: BundleLocation::S3 {
+                    bucket: "bundles".to_string(),
+                    key: "bundle.json".to_string(),
+                },
+                public_key: vec![0, 0, 0],
+                min_version: Some("2022-10-01.1".to_string()),
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{queries::cached_batch_get::CachedBatchGet, utils::authorizer::Claims};
+use serde::{Deserialize, Serialize};
+use shared::error::ApplicationError;
+use typed_builder::TypedBuilder as Builder;
+
+/// A third party sending webhooks, keyed by the integration id it sends, with the secret it signs with.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookIntegration {
+    pub pk: String,
+
+    pub secret: String,
+
+    pub scopes: Vec<String>,
+
+    #[serde(default = "WebhookIntegration::default_enabled")]
+    pub enabled: bool,
+}
+
+/// Never logs the secret.
+impl std::fmt::Debug for WebhookIntegration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookIntegration")
+            .field("pk", &self.pk)
+            .field("scopes", &self.scopes)
+            .field("enabled", &self.enabled)
+            .finish()
+    }
+}
+
+impl WebhookIntegration {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    pub fn to_claims(&self, audience: &str) -> Claims {
+        Claims {
+            client_id: Some(self.pk.clone()),
+            ..Claims::for_principal(audience, &self.pk, None, &self.scopes)
+        }
+    }
+}
+
+/// Reads the webhook integrations by their id.
+#[derive(Debug, Clone, Builder)]
+pub struct GetWebhookIntegration {
+    lookup: CachedBatchGet<WebhookIntegration>,
+}
+
+impl GetWebhookIntegration {
+    /// Enabled by `WEBHOOK_TABLE_NAME`.
+    pub fn from_env(dynamo_db_client: &aws_sdk_dynamodb::Client) -> Option<Self> {
+        let table_name = std::env::var("WEBHOOK_TABLE_NAME").ok()?;
+
+        Some(
+            GetWebhookIntegration::builder()
+                .lookup(
+                    CachedBatchGet::builder()
+                        .table_name(table_name)
+                        .dynamo_db_client(dynamo_db_client.clone())
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    pub async fn execute(
+        &self,
+        integration_id: &str,
+    ) -> Result<Option<WebhookIntegration>, ApplicationError> {
+        let items = self.lookup.get(vec![integration_id.to_string()]).await?;
+
+        Ok(items.into_iter().next())
+    }
+}
@@ -0,0 +1,165 @@
+use crate::queries::revocation_list::RevocationList;
+use lambda_http::{self, http::Method, http::StatusCode, Body, Error, Request, Response};
+use serde::Deserialize;
+use serde_json::json;
+use shared::utils::api_helper::ApiHelper;
+use std::time::{Duration, SystemTime};
+
+/// `{"jti": "...", "exp": 1665000000}` revokes one token, `{"sub": "..."}` all the tokens of a subject.
+#[derive(Debug, Deserialize)]
+struct RevokeRequest {
+    jti: Option<String>,
+    exp: Option<u64>,
+    sub: Option<String>,
+}
+
+/// `POST /revocations`, the companion API of the revocation list, protected with IAM.
+pub async fn execute(
+    revocation_list: &RevocationList,
+    event: Request,
+) -> Result<Response<String>, Error> {
+    if event.method() != Method::POST {
+        return Ok(error(StatusCode::METHOD_NOT_ALLOWED, "Use POST"));
+    }
+
+    let body = match event.body() {
+        Body::Text(text) => text.as_bytes(),
+        Body::Binary(bytes) => bytes.as_slice(),
+        Body::Empty => &[],
+    };
+    let request: RevokeRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return Ok(error(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+
+    let revocation = match request {
+        RevokeRequest {
+            jti: Some(jti),
+            exp: Some(exp),
+            sub: None,
+        } => {
+            revocation_list
+                .revoke_token(&jti, SystemTime::UNIX_EPOCH + Duration::from_secs(exp))
+                .await?
+        }
+        RevokeRequest {
+            jti: None,
+            exp: None,
+            sub: Some(sub),
+        } => revocation_list.revoke_subject(&sub).await?,
+        _ => {
+            return Ok(error(
+                StatusCode::BAD_REQUEST,
+                "Expected either jti and exp, or sub",
+            ))
+        }
+    };
+    // the key holds the jti or the subject, only its kind is logged
+    let kind = revocation.pk.split('#').next().unwrap_or_default();
+    println!("revoked {} until {:?}", kind, revocation.expires_at);
+
+    Ok(ApiHelper::response(
+        StatusCode::CREATED,
+        serde_json::to_string(&revocation)?,
+        "application/json".to_string(),
+    ))
+}
+
+fn error(status_code: StatusCode, message: &str) -> Response<String> {
+    ApiHelper::response(
+        status_code,
+        json!({ "message": message }).to_string(),
+        "application/json".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queries::cached_batch_get::CachedBatchGet;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use serde_json::Value;
+    use shared::{error::ApplicationError, utils::unit_tests_helper::UnitTestHelper};
+
+    fn get_request(body: &str) -> Request {
+        lambda_http::http::Request::builder()
+            .method("POST")
+            .uri("/revocations")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn revoke_a_token_until_its_exp() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from("{}"))
+            .unwrap();
+        let conn = UnitTestHelper::buil_test_connnection(
+            Some(
+                UnitTestHelper::dynamodb_request_builder()
+                    .header("x-amz-target", "DynamoDB_20120810.PutItem")
+                    .body(SdkBody::from(
+                        r#"{"TableName":"revocations","Item":{"pk":{"S":"jti#abc"},"expires_at":{"N":"1665000000"}}}"#,
+                    ))
+                    .unwrap(),
+            ),
+            Some(response),
+        );
+        let revocation_list = RevocationList::builder()
+            .lookup(
+                CachedBatchGet::builder()
+                    .table_name("revocations")
+                    .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+                    .build(),
+            )
+            .build();
+
+        // ACT
+        let response = execute(
+            &revocation_list,
+            get_request(r#"{"jti": "abc", "exp": 1665000000}"#),
+        )
+        .await
+        .unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: Value = serde_json::from_str(response.body())?;
+        assert_eq!(body["pk"], "jti#abc");
+        assert_eq!(body["expires_at"], 1665000000);
+        assert_eq!(conn.requests().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_an_ambiguous_request() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let conn = TestConnection::new(vec![]);
+        let revocation_list = RevocationList::builder()
+            .lookup(
+                CachedBatchGet::builder()
+                    .table_name("revocations")
+                    .dynamo_db_client(UnitTestHelper::dynamo_fake_client(&conn).await)
+                    .build(),
+            )
+            .build();
+
+        // ACT
+        let response = execute(
+            &revocation_list,
+            get_request(r#"{"jti": "abc", "sub": "someone"}"#),
+        )
+        .await
+        .unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(conn.requests().len(), 0);
+
+        Ok(())
+    }
+}
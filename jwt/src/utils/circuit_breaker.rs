@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use typed_builder::TypedBuilder as Builder;
+
+/// Per-region circuit breaker: after `failure_threshold` consecutive failures the region is skipped
+/// for `open_for`, then half-open: a single lookup is let through to probe it, and the others are
+/// skipped until the probe succeeds, fails, or is lost for another `open_for`.
+#[derive(Debug, Clone, Builder)]
+pub struct CircuitBreaker {
+    #[builder(default = 3)]
+    failure_threshold: u32,
+
+    #[builder(default = Duration::from_secs(30))]
+    open_for: Duration,
+
+    #[builder(default, setter(skip))]
+    state: Arc<Mutex<HashMap<String, RegionState>>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct RegionState {
+    failures: u32,
+    open_until: Option<Instant>,
+    probe_until: Option<Instant>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker::builder().build()
+    }
+}
+
+impl CircuitBreaker {
+    /// Whether the region can be called: closed, or half-open without a probe in flight,
+    /// in which case the caller is the probe and must record its outcome.
+    pub fn is_available(&self, region: &str) -> bool {
+        let mut states = self.state.lock().unwrap();
+        let state = match states.get_mut(region) {
+            Some(state) => state,
+            None => return true,
+        };
+
+        let now = Instant::now();
+        match state.open_until {
+            None => true,
+            Some(open_until) if now < open_until => false,
+            Some(_)
+                if state
+                    .probe_until
+                    .is_some_and(|probe_until| now < probe_until) =>
+            {
+                false
+            }
+            Some(_) => {
+                state.probe_until = Some(now + self.open_for);
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self, region: &str) {
+        self.state.lock().unwrap().remove(region);
+    }
+
+    pub fn record_failure(&self, region: &str) {
+        let mut state = self.state.lock().unwrap();
+        let state = state.entry(region.to_string()).or_default();
+        state.failures += 1;
+        if state.failures >= self.failure_threshold {
+            state.open_until = Some(Instant::now() + self.open_for);
+            state.probe_until = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_after_the_threshold_and_probe_after_the_timeout() {
+        // ARRANGE
+        let breaker = CircuitBreaker::builder()
+            .failure_threshold(2)
+            .open_for(Duration::from_millis(20))
+            .build();
+
+        // ACT
+        breaker.record_failure("eu-central-1");
+        let still_closed = breaker.is_available("eu-central-1");
+        breaker.record_failure("eu-central-1");
+        let open = breaker.is_available("eu-central-1");
+        std::thread::sleep(Duration::from_millis(30));
+        let probe = breaker.is_available("eu-central-1");
+        let during_probe = breaker.is_available("eu-central-1");
+        breaker.record_failure("eu-central-1");
+        let reopened = breaker.is_available("eu-central-1");
+        breaker.record_success("eu-central-1");
+
+        // ASSERT
+        assert!(still_closed);
+        assert!(!open);
+        assert!(probe);
+        assert!(!during_probe);
+        assert!(!reopened);
+        assert!(breaker.is_available("eu-central-1"));
+        assert!(breaker.is_available("eu-west-1"));
+    }
+}
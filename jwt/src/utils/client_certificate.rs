@@ -0,0 +1,250 @@
+use aws_lambda_events::apigw::ApiGatewayCustomAuthorizerRequestTypeRequestIdentityClientCert;
+use chrono::NaiveDateTime;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use shared::error::ApplicationError;
+use std::time::{Duration, SystemTime};
+
+/// The client certificate of a mutual TLS request, as far as the gateway describes it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientCertificate {
+    #[serde(default)]
+    pub pem: Option<String>,
+
+    #[serde(default)]
+    pub subject_dn: Option<String>,
+
+    #[serde(default)]
+    pub issuer_dn: Option<String>,
+
+    #[serde(default)]
+    pub serial_number: Option<String>,
+
+    /// Like API Gateway, `May 28 12:30:02 2019 GMT`.
+    #[serde(default)]
+    pub not_before: Option<String>,
+
+    #[serde(default)]
+    pub not_after: Option<String>,
+}
+
+impl From<&ApiGatewayCustomAuthorizerRequestTypeRequestIdentityClientCert> for ClientCertificate {
+    fn from(value: &ApiGatewayCustomAuthorizerRequestTypeRequestIdentityClientCert) -> Self {
+        ClientCertificate {
+            pem: value.client_cert_pem.clone(),
+            subject_dn: value.subject_dn.clone(),
+            issuer_dn: value.issuer_dn.clone(),
+            serial_number: value.serial_number.clone(),
+            not_before: value.validity.not_before.clone(),
+            not_after: value.validity.not_after.clone(),
+        }
+    }
+}
+
+impl ClientCertificate {
+    /// The keys of the certificate grants: `issuer#<dn>`, `issuer#<dn>#subject#<dn>` and `issuer#<dn>#serial#<serial>`.
+    /// A subject or a serial number is only unique within its issuer, any CA of the truststore can issue `CN=partner`.
+    pub fn grant_keys(&self) -> Vec<String> {
+        let issuer = match &self.issuer_dn {
+            Some(issuer) => issuer,
+            None => return Vec::new(),
+        };
+
+        [
+            Some(format!("issuer#{}", issuer)),
+            self.subject_dn
+                .as_ref()
+                .map(|dn| format!("issuer#{}#subject#{}", issuer, dn)),
+            self.serial_number
+                .as_ref()
+                .map(|serial| format!("issuer#{}#serial#{}", issuer, serial)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Checks the validity window when the gateway provides it, a validity that does not parse is not valid.
+    pub fn is_valid_at(&self, now: SystemTime) -> bool {
+        let not_before = self.not_before.as_deref().map(parse_validity).transpose();
+        let not_after = self.not_after.as_deref().map(parse_validity).transpose();
+        match (not_before, not_after) {
+            (Ok(not_before), Ok(not_after)) => {
+                not_before.is_none_or(|not_before| now >= not_before)
+                    && not_after.is_none_or(|not_after| now <= not_after)
+            }
+            _ => false,
+        }
+    }
+
+    /// The end of the validity window, when the gateway provides it.
+    pub fn expires_at(&self) -> Result<Option<SystemTime>, ApplicationError> {
+        self.not_after.as_deref().map(parse_validity).transpose()
+    }
+}
+
+fn parse_validity(value: &str) -> Result<SystemTime, ApplicationError> {
+    let date =
+        NaiveDateTime::parse_from_str(value.trim(), "%b %e %H:%M:%S %Y GMT").map_err(|e| {
+            ApplicationError::ClientError(format!("Invalid certificate validity {}: {}", value, e))
+        })?;
+    let seconds = date.and_utc().timestamp();
+
+    Ok(if seconds >= 0 {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        SystemTime::UNIX_EPOCH
+    })
+}
+
+/// The DER of the first certificate of a PEM, which may be URL-encoded like Envoy `source.certificate`.
+pub fn to_der(pem: &str) -> Result<Vec<u8>, ApplicationError> {
+    let pem = if pem.contains('%') {
+        percent_decode(pem)
+    } else {
+        pem.to_string()
+    };
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| !line.starts_with("-----BEGIN CERTIFICATE-----"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----END CERTIFICATE-----"))
+        .collect();
+    if body.is_empty() {
+        return Err(ApplicationError::ClientError(
+            "The client certificate is not a PEM certificate".to_string(),
+        ));
+    }
+
+    base64::decode(body)
+        .map_err(|e| ApplicationError::ClientError(format!("Invalid client certificate {}", e)))
+}
+
+/// RFC 8705 `x5t#S256`: the base64url SHA-256 of the DER certificate.
+pub fn thumbprint(pem: &str) -> Result<String, ApplicationError> {
+    let der = to_der(pem)?;
+    let hash = digest::digest(&digest::SHA256, &der);
+
+    Ok(base64::encode_config(
+        hash.as_ref(),
+        base64::URL_SAFE_NO_PAD,
+    ))
+}
+
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A self-signed test certificate, `CN=partner`.
+    pub const CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----
+MIIBeTCCAR+gAwIBAgIUO6BbvIpN3E2Ypo4RnpsL2WpChJQwCgYIKoZIzj0EAwIw
+EjEQMA4GA1UEAwwHcGFydG5lcjAeFw0yNjEwMTgxODIyMjNaFw0zNjEwMTUxODIy
+MjNaMBIxEDAOBgNVBAMMB3BhcnRuZXIwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNC
+AATitkHtdYBMME+1WW2ao6YaGAZ4etBKrPOmgM4ZP07yCs82hnx+UX6H7R6qkNe9
+j+aCMp3WV0j6oQWLQq3K23OAo1MwUTAdBgNVHQ4EFgQUrxLZC8oS38Mx6w/yOPoR
+kUHP4lYwHwYDVR0jBBgwFoAUrxLZC8oS38Mx6w/yOPoRkUHP4lYwDwYDVR0TAQH/
+BAUwAwEB/zAKBggqhkjOPQQDAgNIADBFAiEAjXWgVByR57Nfgs9fbf8FSbKn/6kz
+g1qEoYa0TO3ptO0CIALHkjyuoNgmc/1hlZfM3qnagdfXHV2vHhDtE/ujU2r5
+-----END CERTIFICATE-----
+";
+
+    /// `openssl x509 -outform der | openssl dgst -sha256 -binary`, base64url.
+    pub const THUMBPRINT: &str = "Xbwbznm4GK4bOshpUQ_Egh5WfM38rNEH_CCBVsgqFn4";
+
+    #[test]
+    fn compute_the_same_thumbprint_for_a_url_encoded_pem() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let encoded = CERTIFICATE
+            .replace('\n', "%0A")
+            .replace(' ', "%20")
+            .replace('+', "%2B")
+            .replace('/', "%2F")
+            .replace('=', "%3D");
+
+        // ACT
+        let plain_thumbprint = thumbprint(CERTIFICATE)?;
+        let encoded_thumbprint = thumbprint(&encoded)?;
+
+        // ASSERT
+        assert_eq!(plain_thumbprint, THUMBPRINT);
+        assert_eq!(encoded_thumbprint, THUMBPRINT);
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_the_validity_window() {
+        // ARRANGE
+        let certificate = ClientCertificate {
+            subject_dn: Some("CN=partner".to_string()),
+            serial_number: Some("3BA05B".to_string()),
+            not_before: Some("May  8 12:30:02 2019 GMT".to_string()),
+            not_after: Some("May 28 12:30:02 2029 GMT".to_string()),
+            ..Default::default()
+        };
+        let at = |seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+
+        // ACT
+        let before = certificate.is_valid_at(at(1557000000));
+        let during = certificate.is_valid_at(at(1665000000));
+        let after = certificate.is_valid_at(at(1900000000));
+        let unparseable = ClientCertificate {
+            not_after: Some("2029-05-28T12:30:02Z".to_string()),
+            ..certificate.clone()
+        }
+        .is_valid_at(at(1665000000));
+
+        // ASSERT
+        assert!(!before);
+        assert!(during);
+        assert!(!after);
+        assert!(!unparseable);
+        assert!(certificate.grant_keys().is_empty());
+        assert_eq!(
+            ClientCertificate {
+                issuer_dn: Some("CN=Partner CA".to_string()),
+                ..certificate
+            }
+            .grant_keys(),
+            vec![
+                "issuer#CN=Partner CA".to_string(),
+                "issuer#CN=Partner CA#subject#CN=partner".to_string(),
+                "issuer#CN=Partner CA#serial#3BA05B".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn return_error_for_a_pem_without_certificate() {
+        // ACT
+        let result = thumbprint("-----BEGIN PUBLIC KEY-----\nAQAB\n-----END PUBLIC KEY-----");
+
+        // ASSERT
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::utils::decision::DecisionRequest;
+use ring::constant_time;
+use serde::{Deserialize, Serialize};
+
+/// The CSRF checks of a route for the credentials a browser sends on its own, the cookies.
+/// Every configured check must pass; a policy without any check denies.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsrfPolicy {
+    /// Double-submit: the header must repeat the value of the cookie.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub double_submit: Option<DoubleSubmit>,
+
+    /// The origins allowed, like `https://app.example.com`, read from `Origin` or else `Referer`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoubleSubmit {
+    #[serde(default = "DoubleSubmit::default_cookie")]
+    pub cookie: String,
+
+    #[serde(default = "DoubleSubmit::default_header")]
+    pub header: String,
+}
+
+impl Default for DoubleSubmit {
+    fn default() -> Self {
+        DoubleSubmit {
+            cookie: DoubleSubmit::default_cookie(),
+            header: DoubleSubmit::default_header(),
+        }
+    }
+}
+
+impl DoubleSubmit {
+    fn default_cookie() -> String {
+        "csrf_token".to_string()
+    }
+
+    fn default_header() -> String {
+        "x-csrf-token".to_string()
+    }
+
+    fn check(&self, request: &DecisionRequest) -> bool {
+        match (request.cookie(&self.cookie), request.header(&self.header)) {
+            (Some(cookie), Some(header)) if !cookie.is_empty() => {
+                constant_time::verify_slices_are_equal(cookie.as_bytes(), header.trim().as_bytes())
+                    .is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl CsrfPolicy {
+    /// The methods that must not change state, never checked.
+    pub fn is_safe_method(method: &str) -> bool {
+        ["GET", "HEAD", "OPTIONS", "TRACE"]
+            .iter()
+            .any(|x| x.eq_ignore_ascii_case(method))
+    }
+
+    pub fn check(&self, request: &DecisionRequest) -> bool {
+        if Self::is_safe_method(&request.method) {
+            return true;
+        }
+        if self.double_submit.is_none() && self.allowed_origins.is_empty() {
+            return false;
+        }
+
+        self.double_submit
+            .as_ref()
+            .is_none_or(|double_submit| double_submit.check(request))
+            && (self.allowed_origins.is_empty() || self.is_allowed_origin(request))
+    }
+
+    fn is_allowed_origin(&self, request: &DecisionRequest) -> bool {
+        let origin = match request.header("origin") {
+            Some(origin) => Some(origin.trim()),
+            None => request.header("referer").and_then(origin_of),
+        };
+
+        origin.is_some_and(|origin| {
+            self.allowed_origins
+                .iter()
+                .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+        })
+    }
+}
+
+/// The `scheme://host[:port]` of a URL.
+fn origin_of(url: &str) -> Option<&str> {
+    let url = url.trim();
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    if authority.is_empty() {
+        return None;
+    }
+
+    Some(&url[..scheme.len() + 3 + authority.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_request(method: &str, headers: &[(&str, &str)]) -> DecisionRequest {
+        DecisionRequest::builder()
+            .method(method)
+            .path("/payments")
+            .headers(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn check_the_double_submitted_token() {
+        // ARRANGE
+        let policy = CsrfPolicy {
+            double_submit: Some(DoubleSubmit::default()),
+            allowed_origins: Vec::new(),
+        };
+        let cookie = ("Cookie", "access_token=token; csrf_token=abc123");
+
+        // ACT
+        let matching = policy.check(&get_request("POST", &[cookie, ("X-CSRF-Token", "abc123")]));
+        let different = policy.check(&get_request("POST", &[cookie, ("X-CSRF-Token", "other")]));
+        let missing = policy.check(&get_request("DELETE", &[cookie]));
+        let safe = policy.check(&get_request("GET", &[cookie]));
+
+        // ASSERT
+        assert!(matching);
+        assert!(!different);
+        assert!(!missing);
+        assert!(safe);
+    }
+
+    #[test]
+    fn check_the_origin_or_else_the_referer() {
+        // ARRANGE
+        let policy = CsrfPolicy {
+            double_submit: None,
+            allowed_origins: vec!["https://app.example.com".to_string()],
+        };
+
+        // ACT
+        let origin = policy.check(&get_request(
+            "POST",
+            &[("Origin", "https://app.example.com")],
+        ));
+        let referer = policy.check(&get_request(
+            "POST",
+            &[("Referer", "https://app.example.com/checkout?step=2")],
+        ));
+        let other = policy.check(&get_request(
+            "POST",
+            &[("Origin", "https://evil.example.com")],
+        ));
+        let prefix = policy.check(&get_request(
+            "POST",
+            &[("Referer", "https://app.example.com.evil.com/")],
+        ));
+        let missing = policy.check(&get_request("PUT", &[]));
+        let unconfigured = CsrfPolicy::default().check(&get_request("POST", &[]));
+
+        // ASSERT
+        assert!(origin);
+        assert!(referer);
+        assert!(!other);
+        assert!(!prefix);
+        assert!(!missing);
+        assert!(!unconfigured);
+    }
+}
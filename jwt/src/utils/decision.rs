@@ -0,0 +1,1100 @@
+use crate::utils::{
+    authorizer::Claims,
+    client_certificate::{self, ClientCertificate},
+    csrf::CsrfPolicy,
+    degradation::{self, Degradation, DegradedMode},
+    injections::jwt_di::JWTInitialisation,
+    route_rule::Credentials,
+    webhook_signature::WEBHOOK_SIGNATURE_HEADER,
+};
+use aws_lambda_events::apigw::ApiGatewayCustomAuthorizerRequestTypeRequest;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use shared::error::ApplicationError;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::SystemTime;
+use typed_builder::TypedBuilder as Builder;
+
+/// The request to authorize, independent of the event source (API Gateway, HTTP, etc.).
+#[derive(Debug, Clone, Default, Builder, Serialize, Deserialize)]
+pub struct DecisionRequest {
+    #[builder(setter(into))]
+    pub method: String,
+
+    #[builder(setter(into))]
+    pub path: String,
+
+    /// The path the client called, with the stage or the base path the gateway strips from `path`,
+    /// `requestContext.path` for API Gateway.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(default)]
+    pub external_path: Option<String>,
+
+    #[builder(default)]
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    /// The query string parameters, decoded.
+    #[builder(default)]
+    #[serde(default)]
+    pub query: HashMap<String, String>,
+
+    #[builder(default, setter(strip_option, into))]
+    #[serde(default)]
+    pub source_ip: Option<String>,
+
+    /// The client certificate presented over mutual TLS.
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub client_cert: Option<ClientCertificate>,
+
+    /// The API key of the request, `identity.apiKey` for API Gateway.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(default)]
+    pub api_key: Option<String>,
+
+    /// The IAM principal of a SigV4 request, `identity.userArn` for API Gateway.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(default)]
+    pub caller_arn: Option<String>,
+}
+
+impl From<&ApiGatewayCustomAuthorizerRequestTypeRequest> for DecisionRequest {
+    fn from(value: &ApiGatewayCustomAuthorizerRequestTypeRequest) -> Self {
+        DecisionRequest {
+            method: value.http_method.clone().unwrap_or_default().to_string(),
+            path: value.path.clone().unwrap_or_default(),
+            external_path: value.request_context.path.clone(),
+            headers: value
+                .headers
+                .iter()
+                .filter_map(|(key, value)| {
+                    Some((key.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+            query: value
+                .query_string_parameters
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            source_ip: value
+                .request_context
+                .identity
+                .as_ref()
+                .and_then(|identity| identity.source_ip.clone()),
+            client_cert: value
+                .request_context
+                .identity
+                .as_ref()
+                .and_then(|identity| identity.client_cert.as_ref())
+                .map(ClientCertificate::from),
+            api_key: value
+                .request_context
+                .identity
+                .as_ref()
+                .and_then(|identity| identity.api_key.clone())
+                .filter(|api_key| !api_key.is_empty()),
+            // not mapped by aws_lambda_events, see `AuthorizerEvent`
+            caller_arn: None,
+        }
+    }
+}
+
+impl DecisionRequest {
+    /// RFC 8705: the reason to deny when the token is not bound to the presented client certificate.
+    pub fn certificate_binding_denial(&self, claims: &Claims) -> Option<Reason> {
+        let expected = match claims.cnf.as_ref().and_then(|cnf| cnf.x5t_s256.as_ref()) {
+            Some(expected) => expected,
+            None => return Some(Reason::CertificateMismatch),
+        };
+
+        let pem = self
+            .client_cert
+            .as_ref()
+            .and_then(|client_cert| client_cert.pem.as_deref());
+        match pem.map(client_certificate::thumbprint) {
+            Some(Ok(thumbprint)) if &thumbprint == expected => None,
+            Some(Err(_)) => Some(Reason::InvalidCertificate),
+            _ => Some(Reason::CertificateMismatch),
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.header("cookie")?
+            .split(';')
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Percent-decodes the path and resolves its `.` and `..` segments, so `/a/../admin` and
+    /// `/a%2F..%2Fadmin` both look up the rule of `/admin`. A path that does not decode to UTF-8 is `None`.
+    pub fn normalize_path(path: &str) -> Option<String> {
+        let decoded = percent_decode_str(path).decode_utf8().ok()?;
+        let mut segments: Vec<&str> = Vec::new();
+        for segment in decoded.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                segment => segments.push(segment),
+            }
+        }
+
+        let mut path = format!("/{}", segments.join("/"));
+        let is_directory =
+            decoded.ends_with('/') || decoded.ends_with("/.") || decoded.ends_with("/..");
+        if is_directory && !segments.is_empty() {
+            path.push('/');
+        }
+
+        Some(path)
+    }
+
+    /// The parameters of a raw query string, `a=1&b=2`.
+    pub fn parse_query(query: &str) -> HashMap<String, String> {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| {
+                (
+                    client_certificate::percent_decode(key),
+                    client_certificate::percent_decode(value),
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Effect::Allow => write!(f, "ALLOW"),
+            Effect::Deny => write!(f, "DENY"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    MissingToken,
+    InvalidToken,
+    MissingScopeClaim,
+    NoRuleForRoute,
+    InsufficientScope,
+    ScopeGranted,
+    DependencyUnavailable,
+    PrincipalSuspended,
+    TokenSuperseded,
+    CertificateMismatch,
+    InvalidCertificate,
+    CertificateOutsideValidity,
+    InvalidDpopProof,
+    InvalidApiKey,
+    CredentialNotAccepted,
+    InvalidIamCaller,
+    ConflictingTokens,
+    CsrfCheckFailed,
+    InvalidWebhookSignature,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Decision {
+    pub effect: Effect,
+    pub principal: Option<String>,
+    pub reasons: Vec<Reason>,
+
+    /// The dependencies that were unavailable and how the decision coped with them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub degraded: Vec<Degradation>,
+
+    /// The claims of the token that was allowed, used to build the identity forwarded upstream.
+    #[serde(skip)]
+    pub claims: Option<Claims>,
+
+    /// The API key the usage plans throttle an API key caller by.
+    #[serde(skip)]
+    pub usage_identifier_key: Option<String>,
+}
+
+/// Logged with each decision, so without the principal and the claims,
+/// which may be the PII of an encrypted token.
+impl fmt::Debug for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Decision")
+            .field("effect", &self.effect)
+            .field("principal", &self.principal.as_ref().map(|_| "***"))
+            .field("reasons", &self.reasons)
+            .field("degraded", &self.degraded)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Decision {
+    pub fn allow(claims: Claims, reason: Reason) -> Self {
+        Decision {
+            effect: Effect::Allow,
+            principal: Some(claims.email.clone()),
+            reasons: vec![reason],
+            degraded: Vec::new(),
+            claims: Some(claims),
+            usage_identifier_key: None,
+        }
+    }
+
+    pub fn deny(reason: Reason) -> Self {
+        Decision {
+            effect: Effect::Deny,
+            principal: None,
+            reasons: vec![reason],
+            degraded: Vec::new(),
+            claims: None,
+            usage_identifier_key: None,
+        }
+    }
+
+    pub fn is_allowed(&self) -> bool {
+        self.effect == Effect::Allow
+    }
+
+    /// True when the caller did not present a valid credential, as opposed to a credential without access.
+    pub fn is_unauthenticated(&self) -> bool {
+        self.reasons.iter().any(|reason| {
+            matches!(
+                reason,
+                Reason::MissingToken
+                    | Reason::InvalidToken
+                    | Reason::InvalidCertificate
+                    | Reason::CertificateOutsideValidity
+                    | Reason::InvalidDpopProof
+                    | Reason::InvalidApiKey
+                    | Reason::InvalidIamCaller
+                    | Reason::ConflictingTokens
+                    | Reason::InvalidWebhookSignature
+            )
+        })
+    }
+}
+
+pub struct DecisionEngine;
+
+impl DecisionEngine {
+    /// Decides and marks the decision with the degradations of its dependencies;
+    /// a dependency failing closed turns it into a DENY.
+    pub async fn decide(
+        app_client: &dyn JWTInitialisation,
+        request: &DecisionRequest,
+    ) -> Result<Decision, ApplicationError> {
+        let (decision, degraded) = degradation::collect(Self::evaluate(app_client, request)).await;
+        let mut decision = decision?;
+        if degraded.is_empty() {
+            return Ok(decision);
+        }
+
+        if degraded.iter().any(|x| x.mode == DegradedMode::Deny) {
+            decision = Decision::deny(Reason::DependencyUnavailable);
+        }
+        decision.degraded = degraded;
+        println!("degraded decision {:?}", decision);
+        degradation::publish_metrics(&decision.degraded);
+
+        Ok(decision)
+    }
+
+    async fn evaluate(
+        app_client: &dyn JWTInitialisation,
+        request: &DecisionRequest,
+    ) -> Result<Decision, ApplicationError> {
+        let token_sources = app_client.token_sources();
+        let tokens = token_sources.tokens(request);
+        if tokens.len() > 1 {
+            return Ok(Decision::deny(Reason::ConflictingTokens));
+        }
+
+        let mut usage_identifier_key = None;
+        let (claims, credential) = match (
+            tokens.first(),
+            &request.api_key,
+            request.header(WEBHOOK_SIGNATURE_HEADER),
+            &request.caller_arn,
+            &request.client_cert,
+        ) {
+            (Some(token), _, _, _, _) => match app_client.validate_token(token.clone()).await? {
+                Some(claims) => (claims, Some(Credentials::Jwt)),
+                None => return Ok(Decision::deny(Reason::InvalidToken)),
+            },
+            (None, Some(api_key), _, _, _) => {
+                match app_client.authenticate_api_key(api_key).await? {
+                    Some(caller) => {
+                        usage_identifier_key = Some(caller.usage_identifier_key);
+                        (caller.claims, Some(Credentials::ApiKey))
+                    }
+                    None => return Ok(Decision::deny(Reason::InvalidApiKey)),
+                }
+            }
+            // third parties signing their webhooks with a shared secret
+            (None, None, Some(_), _, _) => match app_client.authenticate_webhook(request).await? {
+                Some(claims) => (claims, Some(Credentials::Webhook)),
+                None => return Ok(Decision::deny(Reason::InvalidWebhookSignature)),
+            },
+            // internal services signing their requests with SigV4
+            (None, None, None, Some(caller_arn), _) => {
+                match app_client.authenticate_iam_caller(caller_arn).await? {
+                    Some(claims) => (claims, Some(Credentials::Iam)),
+                    None => return Ok(Decision::deny(Reason::InvalidIamCaller)),
+                }
+            }
+            // machine callers without token, authenticated by their certificate alone
+            (None, None, None, None, Some(client_cert)) => {
+                if !client_cert.is_valid_at(SystemTime::now()) {
+                    return Ok(Decision::deny(Reason::CertificateOutsideValidity));
+                }
+                match app_client.authenticate_certificate(client_cert).await? {
+                    Some(claims) => (claims, Some(Credentials::Certificate)),
+                    None => return Ok(Decision::deny(Reason::InvalidCertificate)),
+                }
+            }
+            (None, None, None, None, None) => return Ok(Decision::deny(Reason::MissingToken)),
+        };
+
+        let statuses = app_client.get_principal_status(&claims).await?;
+        if let Some(reason) = statuses.iter().find_map(|status| status.denial(&claims)) {
+            return Ok(Decision::deny(reason));
+        }
+
+        let token_scope = match claims.scope.clone() {
+            Some(token_scope) => token_scope,
+            None => return Ok(Decision::deny(Reason::MissingScopeClaim)),
+        };
+
+        let policy = match app_client
+            .get_scope_query(&request.method, &request.path)
+            .await?
+        {
+            Some(policy) => policy,
+            None => return Ok(Decision::deny(Reason::NoRuleForRoute)),
+        };
+
+        if credential.is_some_and(|credential| !policy.accepts.accepts(credential)) {
+            return Ok(Decision::deny(Reason::CredentialNotAccepted));
+        }
+
+        // a browser sends the cookies on the cross-site requests too,
+        // without csrf policy a cookie only reaches the safe methods
+        let is_csrf = credential == Some(Credentials::Jwt)
+            && token_sources.is_cookie_only(request)
+            && !policy.csrf.as_ref().map_or_else(
+                || CsrfPolicy::is_safe_method(&request.method),
+                |csrf| csrf.check(request),
+            );
+        if is_csrf {
+            return Ok(Decision::deny(Reason::CsrfCheckFailed));
+        }
+
+        if policy.certificate_bound {
+            if let Some(reason) = request.certificate_binding_denial(&claims) {
+                return Ok(Decision::deny(reason));
+            }
+        }
+
+        if !app_client
+            .check_dpop(request, tokens.first().cloned(), &claims, policy.dpop_bound)
+            .await?
+        {
+            return Ok(Decision::deny(Reason::InvalidDpopProof));
+        }
+
+        for api_scope in policy.scopes {
+            if token_scope.split(' ').any(|x| x == api_scope.as_str()) {
+                let mut decision = Decision::allow(claims, Reason::ScopeGranted);
+                decision.usage_identifier_key = usage_identifier_key;
+                return Ok(decision);
+            }
+        }
+
+        Ok(Decision::deny(Reason::InsufficientScope))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        queries::{api_keys::ApiKeyCaller, principal_status::PrincipalStatus},
+        utils::{
+            authorizer::Confirmation,
+            client_certificate::tests::{CERTIFICATE, THUMBPRINT},
+            csrf::CsrfPolicy,
+            degradation::Dependency,
+            injections::jwt_di::MockJWTInitialisation,
+            route_rule::RoutePolicy,
+            token_source::TokenSources,
+        },
+    };
+    use serde_json::Value;
+
+    fn get_claims(scope: Option<&str>) -> Result<Option<Claims>, ApplicationError> {
+        let mut data: Value = serde_json::from_str(
+            r#"{
+          "exp": 1654242297,
+          "iss": "https://somedomain.com",
+          "aud": "my-audience",
+          "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+          "email": "a@a.com"
+        }"#,
+        )?;
+        if let Some(scope) = scope {
+            data["scope"] = Value::from(scope);
+        }
+        Ok(Some(serde_json::from_value(data)?))
+    }
+
+    /// The answers of a plain request, added last so the expectations of the test match first.
+    fn with_default_expectations(mut mock: MockJWTInitialisation) -> MockJWTInitialisation {
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+
+        mock
+    }
+
+    fn get_request() -> DecisionRequest {
+        DecisionRequest::builder()
+            .method("GET")
+            .path("/one")
+            .headers(HashMap::from([(
+                "Authorization".to_string(),
+                "Bearer token".to_string(),
+            )]))
+            .source_ip("127.0.0.1")
+            .build()
+    }
+
+    #[tokio::test]
+    async fn allow_when_token_has_the_route_scope() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims(Some("profile my-audience.read")));
+        mock.expect_get_scope_query()
+            .withf(|method, path| method == "GET" && path == "/one")
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &get_request()).await?;
+
+        // ASSERT
+        assert!(decision.is_allowed());
+        assert_eq!(decision.principal, Some("a@a.com".to_string()));
+        assert_eq!(decision.reasons, vec![Reason::ScopeGranted]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_when_authorization_header_is_missing() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token().times(0);
+        let mut request = get_request();
+        request.headers.clear();
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &request).await?;
+
+        // ASSERT
+        assert_eq!(decision, Decision::deny(Reason::MissingToken));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_when_token_has_no_scope_claim() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims(None));
+        mock.expect_get_scope_query().times(0);
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &get_request()).await?;
+
+        // ASSERT
+        assert_eq!(decision, Decision::deny(Reason::MissingScopeClaim));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_when_route_has_no_rule() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims(Some("my-audience.read")));
+        mock.expect_get_scope_query()
+            .times(1)
+            .returning(|_, _| Ok(None));
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &get_request()).await?;
+
+        // ASSERT
+        assert_eq!(decision, Decision::deny(Reason::NoRuleForRoute));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_when_token_scope_does_not_match() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims(Some("my-audience.read")));
+        mock.expect_get_scope_query()
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.write".to_string()].into())));
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &get_request()).await?;
+
+        // ASSERT
+        assert_eq!(decision, Decision::deny(Reason::InsufficientScope));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_when_the_principal_is_suspended() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_get_principal_status().times(1).returning(|_| {
+            Ok(vec![PrincipalStatus {
+                pk: "sub#12408bde-207d-45a5-a143-6aa02f049df7".to_string(),
+                suspended: true,
+                not_before: None,
+            }])
+        });
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims(Some("my-audience.read")));
+        mock.expect_get_scope_query().times(0);
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &get_request()).await?;
+
+        // ASSERT
+        assert_eq!(decision, Decision::deny(Reason::PrincipalSuspended));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_a_certificate_bound_route_without_the_bound_certificate(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token().times(3).returning(|_| {
+            let mut claims = get_claims(Some("my-audience.read"))?.unwrap();
+            claims.cnf = Some(Confirmation {
+                x5t_s256: Some(THUMBPRINT.to_string()),
+                ..Default::default()
+            });
+            Ok(Some(claims))
+        });
+        mock.expect_get_scope_query().times(3).returning(|_, _| {
+            Ok(Some(RoutePolicy {
+                scopes: vec!["my-audience.read".to_string()],
+                certificate_bound: true,
+                ..Default::default()
+            }))
+        });
+        let mut bound = get_request();
+        bound.client_cert = Some(ClientCertificate {
+            pem: Some(CERTIFICATE.to_string()),
+            ..Default::default()
+        });
+        let mut invalid = get_request();
+        invalid.client_cert = Some(ClientCertificate {
+            pem: Some("-----BEGIN PUBLIC KEY-----\nAQAB\n-----END PUBLIC KEY-----".to_string()),
+            ..Default::default()
+        });
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let allowed = DecisionEngine::decide(&mock, &bound).await?;
+        let denied = DecisionEngine::decide(&mock, &get_request()).await?;
+        let invalid = DecisionEngine::decide(&mock, &invalid).await?;
+
+        // ASSERT
+        assert!(allowed.is_allowed());
+        assert_eq!(denied, Decision::deny(Reason::CertificateMismatch));
+        assert_eq!(invalid, Decision::deny(Reason::InvalidCertificate));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_a_dpop_bound_route_without_a_valid_proof() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_check_dpop()
+            .withf(|request, _, _, route_bound| *route_bound && request.header("dpop").is_none())
+            .times(1)
+            .returning(|_, _, _, _| Ok(false));
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims(Some("my-audience.read")));
+        mock.expect_get_scope_query().times(1).returning(|_, _| {
+            Ok(Some(RoutePolicy {
+                scopes: vec!["my-audience.read".to_string()],
+                dpop_bound: true,
+                ..Default::default()
+            }))
+        });
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &get_request()).await?;
+
+        // ASSERT
+        assert_eq!(decision, Decision::deny(Reason::InvalidDpopProof));
+        assert!(decision.is_unauthenticated());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authorize_an_api_key_on_the_routes_accepting_it() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token().times(0);
+        mock.expect_authenticate_api_key()
+            .withf(|api_key| api_key == "secret-key")
+            .times(2)
+            .returning(|_| {
+                Ok(Some(ApiKeyCaller {
+                    claims: get_claims(Some("my-audience.read"))?.unwrap(),
+                    usage_identifier_key: "usage-plan-key".to_string(),
+                }))
+            });
+        mock.expect_get_scope_query()
+            .withf(|_, path| path == "/one")
+            .returning(|_, _| {
+                Ok(Some(RoutePolicy {
+                    scopes: vec!["my-audience.read".to_string()],
+                    accepts: Credentials::Either,
+                    ..Default::default()
+                }))
+            });
+        mock.expect_get_scope_query()
+            .withf(|_, path| path == "/two")
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let mut request = get_request();
+        request.headers.clear();
+        request.api_key = Some("secret-key".to_string());
+        let mut jwt_only = request.clone();
+        jwt_only.path = "/two".to_string();
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let allowed = DecisionEngine::decide(&mock, &request).await?;
+        let denied = DecisionEngine::decide(&mock, &jwt_only).await?;
+
+        // ASSERT
+        assert!(allowed.is_allowed());
+        assert_eq!(
+            allowed.usage_identifier_key,
+            Some("usage-plan-key".to_string())
+        );
+        assert_eq!(denied, Decision::deny(Reason::CredentialNotAccepted));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authorize_a_signed_webhook_on_the_webhook_routes() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token().times(0);
+        mock.expect_authenticate_webhook()
+            .withf(|request| request.header("x-webhook-signature") == Some("v1=valid"))
+            .times(2)
+            .returning(|_| get_claims(Some("payments.webhook")));
+        mock.expect_authenticate_webhook().returning(|_| Ok(None));
+        mock.expect_get_scope_query()
+            .withf(|_, path| path == "/webhooks/payments")
+            .returning(|_, _| {
+                Ok(Some(RoutePolicy {
+                    scopes: vec!["payments.webhook".to_string()],
+                    accepts: Credentials::Webhook,
+                    ..Default::default()
+                }))
+            });
+        mock.expect_get_scope_query()
+            .returning(|_, _| Ok(Some(vec!["payments.webhook".to_string()].into())));
+        let mut request = get_request();
+        request.path = "/webhooks/payments".to_string();
+        request.headers = HashMap::from([
+            ("X-Webhook-Id".to_string(), "acme-payments".to_string()),
+            ("X-Webhook-Signature".to_string(), "v1=valid".to_string()),
+        ]);
+        let mut jwt_only = request.clone();
+        jwt_only.path = "/one".to_string();
+        let mut forged = request.clone();
+        forged
+            .headers
+            .insert("X-Webhook-Signature".to_string(), "v1=forged".to_string());
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let allowed = DecisionEngine::decide(&mock, &request).await?;
+        let not_accepted = DecisionEngine::decide(&mock, &jwt_only).await?;
+        let denied = DecisionEngine::decide(&mock, &forged).await?;
+
+        // ASSERT
+        assert!(allowed.is_allowed());
+        assert_eq!(not_accepted, Decision::deny(Reason::CredentialNotAccepted));
+        assert_eq!(denied, Decision::deny(Reason::InvalidWebhookSignature));
+        assert!(denied.is_unauthenticated());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_the_requests_with_conflicting_tokens() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources().return_const(TokenSources(vec![
+            "header:Authorization:Bearer".parse()?,
+            "cookie:access_token".parse()?,
+        ]));
+        mock.expect_validate_token()
+            .withf(|token| token == "token")
+            .times(1)
+            .returning(|_| get_claims(Some("my-audience.read")));
+        mock.expect_get_scope_query()
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let mut cookie = get_request();
+        cookie.headers = HashMap::from([("Cookie".to_string(), "access_token=token".to_string())]);
+        let mut conflicting = get_request();
+        conflicting
+            .headers
+            .insert("Cookie".to_string(), "access_token=other".to_string());
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let allowed = DecisionEngine::decide(&mock, &cookie).await?;
+        let denied = DecisionEngine::decide(&mock, &conflicting).await?;
+
+        // ASSERT
+        assert!(allowed.is_allowed());
+        assert_eq!(denied, Decision::deny(Reason::ConflictingTokens));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_the_cookie_requests_failing_the_csrf_check() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources().return_const(TokenSources(vec![
+            "header:Authorization:Bearer".parse()?,
+            "cookie:access_token".parse()?,
+        ]));
+        mock.expect_validate_token()
+            .times(3)
+            .returning(|_| get_claims(Some("my-audience.read")));
+        mock.expect_get_scope_query().times(3).returning(|_, _| {
+            Ok(Some(RoutePolicy {
+                scopes: vec!["my-audience.read".to_string()],
+                csrf: Some(CsrfPolicy {
+                    double_submit: None,
+                    allowed_origins: vec!["https://app.example.com".to_string()],
+                }),
+                ..Default::default()
+            }))
+        });
+        let mut cross_site = get_request();
+        cross_site.method = "POST".to_string();
+        cross_site.headers = HashMap::from([
+            ("Cookie".to_string(), "access_token=token".to_string()),
+            ("Origin".to_string(), "https://evil.example.com".to_string()),
+        ]);
+        let mut same_site = cross_site.clone();
+        same_site
+            .headers
+            .insert("Origin".to_string(), "https://app.example.com".to_string());
+        let mut bearer = get_request();
+        bearer.method = "POST".to_string();
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let denied = DecisionEngine::decide(&mock, &cross_site).await?;
+        let allowed = DecisionEngine::decide(&mock, &same_site).await?;
+        let bearer = DecisionEngine::decide(&mock, &bearer).await?;
+
+        // ASSERT
+        assert_eq!(denied, Decision::deny(Reason::CsrfCheckFailed));
+        assert!(!denied.is_unauthenticated());
+        assert!(allowed.is_allowed());
+        assert!(bearer.is_allowed());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_the_unsafe_cookie_requests_without_csrf_policy() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources().return_const(TokenSources(vec![
+            "header:Authorization:Bearer".parse()?,
+            "cookie:access_token".parse()?,
+        ]));
+        mock.expect_validate_token()
+            .times(2)
+            .returning(|_| get_claims(Some("my-audience.read")));
+        mock.expect_get_scope_query()
+            .times(2)
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let mut unsafe_method = get_request();
+        unsafe_method.method = "POST".to_string();
+        unsafe_method.headers =
+            HashMap::from([("Cookie".to_string(), "access_token=token".to_string())]);
+        let mut safe_method = unsafe_method.clone();
+        safe_method.method = "GET".to_string();
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let denied = DecisionEngine::decide(&mock, &unsafe_method).await?;
+        let allowed = DecisionEngine::decide(&mock, &safe_method).await?;
+
+        // ASSERT
+        assert_eq!(denied, Decision::deny(Reason::CsrfCheckFailed));
+        assert!(allowed.is_allowed());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authorize_an_iam_caller_by_its_grants() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token().times(0);
+        mock.expect_authenticate_iam_caller()
+            .returning(|caller_arn| match caller_arn.contains("billing-") {
+                true => get_claims(Some("my-audience.read")),
+                false => Ok(None),
+            });
+        mock.expect_get_scope_query()
+            .withf(|_, path| path == "/invoices")
+            .returning(|_, _| {
+                Ok(Some(RoutePolicy {
+                    scopes: vec!["my-audience.read".to_string()],
+                    accepts: Credentials::Iam,
+                    ..Default::default()
+                }))
+            });
+        mock.expect_get_scope_query()
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let mut billing = get_request();
+        billing.path = "/invoices".to_string();
+        billing.headers.clear();
+        billing.caller_arn =
+            Some("arn:aws:sts::123456789012:assumed-role/billing-api/session-1".to_string());
+        let mut jwt_only = billing.clone();
+        jwt_only.path = "/hello".to_string();
+        let mut unknown = billing.clone();
+        unknown.caller_arn =
+            Some("arn:aws:sts::123456789012:assumed-role/reporting/session-1".to_string());
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let allowed = DecisionEngine::decide(&mock, &billing).await?;
+        let not_accepted = DecisionEngine::decide(&mock, &jwt_only).await?;
+        let denied = DecisionEngine::decide(&mock, &unknown).await?;
+
+        // ASSERT
+        assert!(allowed.is_allowed());
+        assert_eq!(not_accepted, Decision::deny(Reason::CredentialNotAccepted));
+        assert_eq!(denied, Decision::deny(Reason::InvalidIamCaller));
+        assert!(denied.is_unauthenticated());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authorize_a_client_certificate_without_token() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token().times(0);
+        mock.expect_authenticate_certificate()
+            .withf(|certificate| certificate.subject_dn.as_deref() == Some("CN=partner"))
+            .times(2)
+            .returning(|_| get_claims(Some("partner.read")));
+        mock.expect_authenticate_certificate()
+            .times(1)
+            .returning(|_| Ok(None));
+        mock.expect_get_scope_query()
+            .withf(|_, path| path == "/partners")
+            .returning(|_, _| {
+                Ok(Some(RoutePolicy {
+                    scopes: vec!["partner.read".to_string()],
+                    accepts: Credentials::Certificate,
+                    ..Default::default()
+                }))
+            });
+        mock.expect_get_scope_query()
+            .returning(|_, _| Ok(Some(vec!["partner.read".to_string()].into())));
+        let mut partner = get_request();
+        partner.path = "/partners".to_string();
+        partner.headers.clear();
+        partner.client_cert = Some(ClientCertificate {
+            subject_dn: Some("CN=partner".to_string()),
+            ..Default::default()
+        });
+        let mut jwt_only = partner.clone();
+        jwt_only.path = "/hello".to_string();
+        let mut unknown = partner.clone();
+        unknown.client_cert = Some(ClientCertificate::default());
+        let mut expired = partner.clone();
+        expired.client_cert = Some(ClientCertificate {
+            subject_dn: Some("CN=partner".to_string()),
+            not_after: Some("May 28 12:30:02 2019 GMT".to_string()),
+            ..Default::default()
+        });
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let allowed = DecisionEngine::decide(&mock, &partner).await?;
+        let not_accepted = DecisionEngine::decide(&mock, &jwt_only).await?;
+        let denied = DecisionEngine::decide(&mock, &unknown).await?;
+        let outside_validity = DecisionEngine::decide(&mock, &expired).await?;
+
+        // ASSERT
+        assert!(allowed.is_allowed());
+        assert_eq!(not_accepted, Decision::deny(Reason::CredentialNotAccepted));
+        assert_eq!(denied, Decision::deny(Reason::InvalidCertificate));
+        assert!(denied.is_unauthenticated());
+        assert_eq!(
+            outside_validity,
+            Decision::deny(Reason::CertificateOutsideValidity)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn log_a_decision_without_the_values_of_the_claims() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut claims = get_claims(Some("my-audience.read"))?.unwrap();
+        claims.jti = Some("5a7f3c9e-jti".to_string());
+        let mut decision = Decision::allow(claims.clone(), Reason::ScopeGranted);
+        decision.degraded = vec![Degradation {
+            dependency: Dependency::Jwks,
+            mode: DegradedMode::Stale,
+        }];
+
+        // ACT
+        let logged = format!("{:?}", decision);
+
+        // ASSERT
+        for value in [
+            claims.sub,
+            claims.email,
+            claims.aud,
+            claims.jti.unwrap(),
+            claims.iss.unwrap(),
+            claims.scope.unwrap(),
+        ] {
+            assert!(!logged.contains(&value), "{} logged in {}", value, logged);
+        }
+        assert!(logged.contains("Allow"));
+        assert!(logged.contains("ScopeGranted"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deny_when_a_dependency_fails_closed() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims(Some("my-audience.read")));
+        mock.expect_get_scope_query().times(1).returning(|_, _| {
+            degradation::record(Dependency::ScopeRules, DegradedMode::Deny);
+            Ok(None)
+        });
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &get_request()).await?;
+
+        // ASSERT
+        assert!(!decision.is_allowed());
+        assert_eq!(decision.reasons, vec![Reason::DependencyUnavailable]);
+        assert_eq!(
+            decision.degraded,
+            vec![Degradation {
+                dependency: Dependency::ScopeRules,
+                mode: DegradedMode::Deny
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mark_the_decision_served_with_stale_keys() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token().times(1).returning(|_| {
+            degradation::record(Dependency::Jwks, DegradedMode::Stale);
+            get_claims(Some("my-audience.read"))
+        });
+        mock.expect_get_scope_query()
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let mock = with_default_expectations(mock);
+
+        // ACT
+        let decision = DecisionEngine::decide(&mock, &get_request()).await?;
+
+        // ASSERT
+        assert!(decision.is_allowed());
+        assert_eq!(decision.degraded[0].dependency, Dependency::Jwks);
+
+        Ok(())
+    }
+}
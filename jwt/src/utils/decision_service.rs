@@ -0,0 +1,537 @@
+use crate::utils::{
+    decision::{DecisionEngine, DecisionRequest},
+    identity_headers::IdentityHeaders,
+    injections::jwt_di::JWTInitialisation,
+};
+use http_body::{LengthLimitError, Limited};
+use hyper::{header::HeaderValue, Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+use shared::utils::api_helper::ApiHelper;
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+
+/// The largest `DecisionRequest` body read, larger bodies are rejected with 413.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Serves the authorization decision over HTTP, for containers, tests and non-AWS gateways.
+///
+/// - `POST /decision` takes a `DecisionRequest` and returns the `Decision` with its reasons
+/// - `/forward-auth` answers NGINX `auth_request` and Traefik ForwardAuth subrequests with 200, 401 or 403
+/// - `GET /health` returns 200 when the service is up
+#[derive(Clone)]
+pub struct DecisionService {
+    app_client: Arc<dyn JWTInitialisation>,
+    identity_headers: IdentityHeaders,
+}
+
+impl DecisionService {
+    pub fn new(app_client: Arc<dyn JWTInitialisation>, identity_headers: IdentityHeaders) -> Self {
+        DecisionService {
+            app_client,
+            identity_headers,
+        }
+    }
+
+    pub async fn handle(&self, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let response = match (request.method(), request.uri().path()) {
+            (&Method::POST, "/decision") => self.decision(request).await,
+            (_, "/forward-auth") => self.forward_auth(request).await,
+            (&Method::GET, "/health") => Self::json(StatusCode::OK, json!({ "status": "ok" })),
+            _ => Self::json(StatusCode::NOT_FOUND, json!({ "message": "not found" })),
+        };
+
+        Ok(response)
+    }
+
+    async fn decision(&self, request: Request<Body>) -> Response<Body> {
+        let body =
+            match hyper::body::to_bytes(Limited::new(request.into_body(), MAX_BODY_SIZE)).await {
+                Ok(body) => body,
+                Err(e) if e.is::<LengthLimitError>() => {
+                    return Self::json(
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        json!({ "message": e.to_string() }),
+                    )
+                }
+                Err(e) => {
+                    return Self::json(StatusCode::BAD_REQUEST, json!({ "message": e.to_string() }))
+                }
+            };
+        let decision_request: DecisionRequest = match serde_json::from_slice(&body) {
+            Ok(decision_request) => decision_request,
+            Err(e) => {
+                return Self::json(StatusCode::BAD_REQUEST, json!({ "message": e.to_string() }))
+            }
+        };
+
+        match DecisionEngine::decide(self.app_client.as_ref(), &decision_request).await {
+            Ok(decision) => Self::json(StatusCode::OK, json!(decision)),
+            Err(e) => Self::json(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({ "message": e.to_string() }),
+            ),
+        }
+    }
+
+    /// The original request is described by `X-Original-Method`/`X-Original-URI` (NGINX)
+    /// or `X-Forwarded-Method`/`X-Forwarded-Uri` (Traefik), and is denied without them.
+    async fn forward_auth(&self, request: Request<Body>) -> Response<Body> {
+        let decision_request = match Self::to_forward_auth_request(&request) {
+            Some(decision_request) => decision_request,
+            None => {
+                return Self::json(
+                    StatusCode::FORBIDDEN,
+                    json!({ "message": "missing or invalid original method and uri" }),
+                )
+            }
+        };
+
+        let decision =
+            match DecisionEngine::decide(self.app_client.as_ref(), &decision_request).await {
+                Ok(decision) => decision,
+                Err(e) => {
+                    return Self::json(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        json!({ "message": e.to_string() }),
+                    )
+                }
+            };
+        println!("decision {:?}", decision);
+
+        if !decision.is_allowed() {
+            let status_code = if decision.is_unauthenticated() {
+                StatusCode::UNAUTHORIZED
+            } else {
+                StatusCode::FORBIDDEN
+            };
+            return Self::json(status_code, json!({ "reasons": decision.reasons }));
+        }
+
+        let mut response = Response::new(Body::empty());
+        for (header, value) in self.identity_headers.headers(&decision) {
+            if let (Ok(name), Ok(value)) = (
+                hyper::header::HeaderName::from_bytes(header.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                response.headers_mut().insert(name, value);
+            }
+        }
+
+        response
+    }
+
+    fn to_forward_auth_request(request: &Request<Body>) -> Option<DecisionRequest> {
+        let headers = request
+            .headers()
+            .iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let mut decision_request = DecisionRequest {
+            method: String::new(),
+            path: String::new(),
+            external_path: None,
+            headers,
+            query: HashMap::new(),
+            source_ip: None,
+            client_cert: None,
+            api_key: None,
+            caller_arn: None,
+        };
+
+        let method = decision_request
+            .header("x-original-method")
+            .or_else(|| decision_request.header("x-forwarded-method"))?
+            .trim()
+            .to_uppercase();
+        let uri = decision_request
+            .header("x-original-uri")
+            .or_else(|| decision_request.header("x-forwarded-uri"))?
+            .trim()
+            .to_string();
+        let (path, query) = uri.split_once('?').unwrap_or((&uri, ""));
+        decision_request.method = method;
+        decision_request.path = DecisionRequest::normalize_path(path)?;
+        decision_request.query = DecisionRequest::parse_query(query);
+        decision_request.api_key = decision_request.header("x-api-key").map(str::to_string);
+        // the proxy in front of the service appends the address it was called from, the rest is sent by the client
+        decision_request.source_ip = decision_request
+            .header("x-forwarded-for")
+            .and_then(|ips| ips.rsplit(',').next())
+            .or_else(|| decision_request.header("x-real-ip"))
+            .map(|ip| ip.trim().to_string());
+
+        Some(decision_request)
+    }
+
+    fn json(status_code: StatusCode, body: serde_json::Value) -> Response<Body> {
+        ApiHelper::response(
+            status_code,
+            body.to_string(),
+            "application/json".to_string(),
+        )
+        .map(Body::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{
+        authorizer::Claims,
+        decision::{Decision, Reason},
+        injections::jwt_di::MockJWTInitialisation,
+        token_source::TokenSources,
+    };
+    use serde_json::Value;
+    use shared::error::ApplicationError;
+
+    async fn read_body(response: Response<Body>) -> Value {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn return_the_decision_with_reasons() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+        mock.expect_validate_token().times(1).returning(|_| {
+            let data = r#"
+                    {
+              "exp": 1654242297,
+              "aud": "my-audience",
+              "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+              "scope": "my-audience.read",
+              "email": "a@a.com"
+            }"#;
+            let claims: Claims = serde_json::from_str(data)?;
+            Ok(Some(claims))
+        });
+        mock.expect_get_scope_query()
+            .withf(|method, path| method == "GET" && path == "/one/")
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let service = DecisionService::new(Arc::new(mock), IdentityHeaders::default());
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/decision")
+            .body(Body::from(
+                r#"{"method":"GET","path":"/one/","headers":{"authorization":"Bearer token"},"source_ip":"10.0.0.1"}"#,
+            ))
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::OK);
+        let decision: Decision = serde_json::from_value(read_body(response).await)?;
+        assert!(decision.is_allowed());
+        assert_eq!(decision.principal, Some("a@a.com".to_string()));
+        assert_eq!(decision.reasons, vec![Reason::ScopeGranted]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_deny_when_authorization_is_missing() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_validate_token().times(0);
+        let service = DecisionService::new(Arc::new(mock), IdentityHeaders::default());
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/decision")
+            .body(Body::from(r#"{"method":"GET","path":"/one/"}"#))
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = read_body(response).await;
+        assert_eq!(body["effect"], "DENY");
+        assert_eq!(body["reasons"], json!(["missing_token"]));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_bad_request_when_body_is_not_valid() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let service = DecisionService::new(
+            Arc::new(MockJWTInitialisation::default()),
+            IdentityHeaders::default(),
+        );
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/decision")
+            .body(Body::from(r#"{"path":"/one/"}"#))
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_payload_too_large_when_body_exceeds_the_limit() -> Result<(), ApplicationError>
+    {
+        // ARRANGE
+        let service = DecisionService::new(
+            Arc::new(MockJWTInitialisation::default()),
+            IdentityHeaders::default(),
+        );
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/decision")
+            .body(Body::from(vec![b' '; MAX_BODY_SIZE + 1]))
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_internal_server_error_when_the_decision_fails() -> Result<(), ApplicationError>
+    {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| Err(ApplicationError::SdkError("jwks unavailable".to_string())));
+        let service = DecisionService::new(Arc::new(mock), IdentityHeaders::default());
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/decision")
+            .body(Body::from(
+                r#"{"method":"GET","path":"/one/","headers":{"Authorization":"Bearer token"}}"#,
+            ))
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_not_found_for_unknown_route() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let service = DecisionService::new(
+            Arc::new(MockJWTInitialisation::default()),
+            IdentityHeaders::default(),
+        );
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/unknown")
+            .body(Body::empty())
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    fn get_claims() -> Result<Option<Claims>, ApplicationError> {
+        let data = r#"
+                {
+          "exp": 1654242297,
+          "aud": "my-audience",
+          "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+          "scope": "my-audience.read",
+          "email": "a@a.com"
+        }"#;
+        Ok(Some(serde_json::from_str(data)?))
+    }
+
+    #[tokio::test]
+    async fn forward_auth_return_ok_with_identity_headers() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+        mock.expect_validate_token()
+            .withf(|token| token == "token")
+            .times(1)
+            .returning(|_| get_claims());
+        mock.expect_get_scope_query()
+            .withf(|method, path| method == "POST" && path == "/two/")
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let service = DecisionService::new(Arc::new(mock), IdentityHeaders::default());
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/forward-auth")
+            .header("Authorization", "Bearer token")
+            .header("X-Original-Method", "post")
+            .header("X-Original-URI", "/two/?page=1")
+            .header("X-Forwarded-For", "10.0.0.1, 10.0.0.2")
+            .body(Body::empty())
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("x-auth-subject").unwrap(),
+            "12408bde-207d-45a5-a143-6aa02f049df7"
+        );
+        assert_eq!(response.headers().get("x-auth-email").unwrap(), "a@a.com");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forward_auth_understand_traefik_headers() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims());
+        mock.expect_get_scope_query()
+            .withf(|method, path| method == "DELETE" && path == "/one/")
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.write".to_string()].into())));
+        let service = DecisionService::new(Arc::new(mock), IdentityHeaders::default());
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/forward-auth")
+            .header("Authorization", "Bearer token")
+            .header("X-Forwarded-Method", "DELETE")
+            .header("X-Forwarded-Uri", "/one/")
+            .body(Body::empty())
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            read_body(response).await["reasons"],
+            json!(["insufficient_scope"])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forward_auth_return_unauthorized_without_token() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_validate_token().times(0);
+        let service = DecisionService::new(Arc::new(mock), IdentityHeaders::default());
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/forward-auth")
+            .header("X-Original-Method", "GET")
+            .header("X-Original-URI", "/one/")
+            .body(Body::empty())
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forward_auth_return_forbidden_without_the_original_request(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_validate_token().times(0);
+        let service = DecisionService::new(Arc::new(mock), IdentityHeaders::default());
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/forward-auth")
+            .header("Authorization", "Bearer token")
+            .body(Body::empty())
+            .unwrap();
+
+        // ACT
+        let response = service.handle(request).await.unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        Ok(())
+    }
+
+    #[test]
+    fn forward_auth_normalize_the_original_uri_and_read_the_appended_address(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let get_request = |uri: &str| {
+            Request::builder()
+                .method(Method::GET)
+                .uri("/forward-auth")
+                .header("X-Original-Method", "GET")
+                .header("X-Original-URI", uri)
+                .header("X-Forwarded-For", "1.2.3.4, 10.0.0.2")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // ACT
+        let dot_segments =
+            DecisionService::to_forward_auth_request(&get_request("/one/../admin/?page=1"));
+        let encoded = DecisionService::to_forward_auth_request(&get_request("/one%2F..%2Fadmin"));
+        let above_root = DecisionService::to_forward_auth_request(&get_request("/../../admin/./"));
+        let invalid = DecisionService::to_forward_auth_request(&get_request("/one/%FF"));
+
+        // ASSERT
+        let dot_segments = dot_segments.unwrap();
+        assert_eq!(dot_segments.path, "/admin/");
+        assert_eq!(dot_segments.query.get("page"), Some(&"1".to_string()));
+        assert_eq!(dot_segments.source_ip, Some("10.0.0.2".to_string()));
+        assert_eq!(encoded.unwrap().path, "/admin");
+        assert_eq!(above_root.unwrap().path, "/admin/");
+        assert!(invalid.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use shared::error::ApplicationError;
+use std::{cell::RefCell, future::Future, time::Duration, time::SystemTime};
+
+/// What to do when a dependency of the decision is unavailable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Return the error, API Gateway answers 500.
+    #[default]
+    Error,
+    /// Fail closed with a DENY.
+    Deny,
+    /// Serve the last good data, at most `max_staleness` after it expired, then return the error.
+    Stale { max_staleness: Duration },
+}
+
+impl FailureMode {
+    pub fn parse(
+        mode: Option<&str>,
+        max_staleness: Option<&str>,
+    ) -> Result<Self, ApplicationError> {
+        match mode.unwrap_or("error") {
+            "error" => Ok(FailureMode::Error),
+            "deny" => Ok(FailureMode::Deny),
+            "stale" => Ok(FailureMode::Stale {
+                max_staleness: Duration::from_secs(
+                    max_staleness.unwrap_or("3600").parse().map_err(|_| {
+                        ApplicationError::InitError(format!(
+                            "Invalid max staleness {:?}, expected seconds",
+                            max_staleness
+                        ))
+                    })?,
+                ),
+            }),
+            mode => Err(ApplicationError::InitError(format!(
+                "Unknown failure mode {}, expected error, deny or stale",
+                mode
+            ))),
+        }
+    }
+}
+
+/// The failure mode of each dependency:
+/// * `JWKS_FAILURE_MODE` and `JWKS_MAX_STALENESS` for the signing keys
+/// * `SCOPE_FAILURE_MODE` and `SCOPE_MAX_STALENESS` for the scope rules
+/// * `IAM_GRANT_FAILURE_MODE` and `IAM_GRANT_MAX_STALENESS` for the IAM grants
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DegradationPolicy {
+    pub keys: FailureMode,
+    pub rules: FailureMode,
+    pub grants: FailureMode,
+}
+
+impl DegradationPolicy {
+    pub fn from_env() -> Result<Self, ApplicationError> {
+        Ok(DegradationPolicy {
+            keys: FailureMode::parse(
+                std::env::var("JWKS_FAILURE_MODE").ok().as_deref(),
+                std::env::var("JWKS_MAX_STALENESS").ok().as_deref(),
+            )?,
+            rules: FailureMode::parse(
+                std::env::var("SCOPE_FAILURE_MODE").ok().as_deref(),
+                std::env::var("SCOPE_MAX_STALENESS").ok().as_deref(),
+            )?,
+            grants: FailureMode::parse(
+                std::env::var("IAM_GRANT_FAILURE_MODE").ok().as_deref(),
+                std::env::var("IAM_GRANT_MAX_STALENESS").ok().as_deref(),
+            )?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dependency {
+    Jwks,
+    ScopeRules,
+    IamGrants,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DegradedMode {
+    Stale,
+    Deny,
+    /// Served by a replica in another region.
+    Failover,
+}
+
+/// A dependency that was unavailable while deciding, and how the decision coped with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Degradation {
+    pub dependency: Dependency,
+    pub mode: DegradedMode,
+}
+
+tokio::task_local! {
+    static DEGRADATIONS: RefCell<Vec<Degradation>>;
+}
+
+/// Called by the dependencies when they apply their failure mode, outside of `collect` it only logs.
+pub fn record(dependency: Dependency, mode: DegradedMode) {
+    println!("degraded {:?} served {:?}", dependency, mode);
+    let degradation = Degradation { dependency, mode };
+    let _ = DEGRADATIONS.try_with(|degradations| {
+        let mut degradations = degradations.borrow_mut();
+        if !degradations.contains(&degradation) {
+            degradations.push(degradation);
+        }
+    });
+}
+
+/// Runs the decision and returns the degradations recorded while it ran.
+pub async fn collect<F: Future>(future: F) -> (F::Output, Vec<Degradation>) {
+    DEGRADATIONS
+        .scope(RefCell::new(Vec::new()), async {
+            let output = future.await;
+            (
+                output,
+                DEGRADATIONS.with(|degradations| degradations.take()),
+            )
+        })
+        .await
+}
+
+/// One CloudWatch embedded metric per degradation, `DegradedDecisions` by `Dependency` and `Mode`.
+pub fn publish_metrics(degradations: &[Degradation]) {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    for degradation in degradations {
+        println!(
+            "{}",
+            json!({
+                "_aws": {
+                    "Timestamp": timestamp,
+                    "CloudWatchMetrics": [{
+                        "Namespace": "LambdaRequestAuthorizer",
+                        "Dimensions": [["Dependency", "Mode"]],
+                        "Metrics": [{"Name": "DegradedDecisions", "Unit": "Count"}]
+                    }]
+                },
+                "Dependency": degradation.dependency,
+                "Mode": degradation.mode,
+                "DegradedDecisions": 1
+            })
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_the_failure_modes() -> Result<(), ApplicationError> {
+        // ACT
+        let default = FailureMode::parse(None, None)?;
+        let stale = FailureMode::parse(Some("stale"), Some("600"))?;
+        let unknown = FailureMode::parse(Some("retry"), None);
+
+        // ASSERT
+        assert_eq!(default, FailureMode::Error);
+        assert_eq!(
+            stale,
+            FailureMode::Stale {
+                max_staleness: Duration::from_secs(600)
+            }
+        );
+        assert!(unknown.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn collect_the_degradations_of_the_decision() {
+        // ACT
+        let (output, degradations) = collect(async {
+            record(Dependency::Jwks, DegradedMode::Stale);
+            record(Dependency::Jwks, DegradedMode::Stale);
+            42
+        })
+        .await;
+
+        // ASSERT
+        assert_eq!(output, 42);
+        assert_eq!(
+            degradations,
+            vec![Degradation {
+                dependency: Dependency::Jwks,
+                mode: DegradedMode::Stale
+            }]
+        );
+    }
+}
@@ -0,0 +1,517 @@
+use crate::{
+    queries::proof_replay::ProofReplay,
+    utils::{authorizer::Claims, decision::DecisionRequest},
+};
+use jsonwebtoken::{decode, decode_header, jwk::Jwk, Algorithm, DecodingKey, Validation};
+use ring::digest;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use shared::error::ApplicationError;
+use std::time::{Duration, SystemTime};
+use typed_builder::TypedBuilder as Builder;
+
+#[derive(Debug, Deserialize)]
+struct ProofClaims {
+    jti: String,
+    htm: String,
+    htu: String,
+    iat: u64,
+    ath: Option<String>,
+}
+
+/// Validates the `DPoP` header proofs of possession, RFC 9449.
+/// A proof is required for the `DPoP` scheme, the tokens with `cnf.jkt`, the tokens of `issuers`
+/// and the `dpop_bound` routes.
+#[derive(Debug, Clone, Builder)]
+pub struct DpopValidator {
+    #[builder(default)]
+    pub issuers: Vec<String>,
+
+    /// How far the `iat` of a proof may be from now.
+    #[builder(default = Duration::from_secs(60))]
+    pub max_age: Duration,
+
+    #[builder(default)]
+    pub replay: ProofReplay,
+
+    /// The scheme, host and base path the clients call, when a proxy rewrites them.
+    #[builder(default)]
+    pub base_url: Option<String>,
+}
+
+impl Default for DpopValidator {
+    fn default() -> Self {
+        DpopValidator::builder().build()
+    }
+}
+
+impl DpopValidator {
+    /// `DPOP_ISSUERS`, comma separated, `DPOP_MAX_AGE` in seconds and `DPOP_BASE_URL`.
+    pub fn from_env(dynamo_db_client: &aws_sdk_dynamodb::Client) -> Result<Self, ApplicationError> {
+        let issuers = std::env::var("DPOP_ISSUERS")
+            .map(|issuers| {
+                issuers
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|issuer| !issuer.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let max_age = match std::env::var("DPOP_MAX_AGE") {
+            Ok(max_age) => Duration::from_secs(max_age.parse().map_err(|_| {
+                ApplicationError::InitError(format!(
+                    "Invalid DPOP_MAX_AGE {}, expected seconds",
+                    max_age
+                ))
+            })?),
+            Err(_) => Duration::from_secs(60),
+        };
+
+        Ok(DpopValidator::builder()
+            .issuers(issuers)
+            .max_age(max_age)
+            .replay(ProofReplay::from_env(dynamo_db_client))
+            .base_url(
+                std::env::var("DPOP_BASE_URL")
+                    .ok()
+                    .filter(|base_url| !base_url.trim().is_empty()),
+            )
+            .build())
+    }
+
+    pub fn is_required(
+        &self,
+        request: &DecisionRequest,
+        claims: &Claims,
+        route_bound: bool,
+    ) -> bool {
+        route_bound
+            || is_dpop_scheme(request)
+            || claims.cnf.as_ref().is_some_and(|cnf| cnf.jkt.is_some())
+            || claims
+                .iss
+                .as_ref()
+                .is_some_and(|iss| self.issuers.contains(iss))
+    }
+
+    /// Checks the proof of the request against the access token the claims were read from,
+    /// logging why it is rejected.
+    pub async fn validate(
+        &self,
+        request: &DecisionRequest,
+        access_token: Option<&str>,
+        claims: &Claims,
+    ) -> Result<bool, ApplicationError> {
+        match self.check(request, access_token, claims, SystemTime::now()) {
+            Ok(replay_key) => self.replay.first_use(&replay_key, self.max_age * 2).await,
+            Err(reason) => {
+                println!("invalid DPoP proof: {}", reason);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Returns the key that must not be replayed, `<jkt>#<jti>`.
+    fn check(
+        &self,
+        request: &DecisionRequest,
+        access_token: Option<&str>,
+        claims: &Claims,
+        now: SystemTime,
+    ) -> Result<String, String> {
+        let proof = request.header("dpop").ok_or("missing DPoP header")?;
+        let header = decode_header(proof).map_err(|e| e.to_string())?;
+        if header.typ.as_deref() != Some("dpop+jwt") {
+            return Err(format!("unexpected typ {:?}", header.typ));
+        }
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err("symmetric algorithm".to_string());
+        }
+        let jwk = header.jwk.ok_or("missing jwk")?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        let key = DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?;
+        let proof = decode::<ProofClaims>(proof, &key, &validation)
+            .map_err(|e| e.to_string())?
+            .claims;
+
+        if !proof.htm.eq_ignore_ascii_case(&request.method) {
+            return Err(format!("htm {} for {}", proof.htm, request.method));
+        }
+        let expected_htu = self
+            .expected_htu(request)
+            .ok_or("the request has no Host to match htu")?;
+        if !matches_htu(&proof.htu, &expected_htu) {
+            return Err(format!("htu {} for {}", proof.htu, expected_htu));
+        }
+        let now = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if now.abs_diff(proof.iat) > self.max_age.as_secs() {
+            return Err(format!("iat {} is not fresh", proof.iat));
+        }
+
+        let access_token = access_token.ok_or("missing access token")?;
+        if proof.ath.as_deref() != Some(hash(access_token.as_bytes()).as_str()) {
+            return Err("ath does not match the access token".to_string());
+        }
+
+        let jkt = thumbprint(&jwk)?;
+        let expected = claims.cnf.as_ref().and_then(|cnf| cnf.jkt.as_ref());
+        if expected != Some(&jkt) {
+            return Err("the token is not bound to the proof key".to_string());
+        }
+
+        Ok(format!("{}#{}", jkt, proof.jti))
+    }
+
+    /// The URL the client called: `base_url` followed by the request path when set,
+    /// otherwise the `Host` of the request and the path the client called, `None` without `Host`.
+    fn expected_htu(&self, request: &DecisionRequest) -> Option<String> {
+        if let Some(base_url) = &self.base_url {
+            return Some(format!(
+                "{}{}",
+                base_url.trim_end_matches('/'),
+                request.path
+            ));
+        }
+
+        let host = request.header("host")?.trim();
+        let scheme = request.header("x-forwarded-proto").unwrap_or("https");
+        let path = request.external_path.as_deref().unwrap_or(&request.path);
+        Some(format!("{}://{}{}", scheme.trim(), host, path))
+    }
+}
+
+fn is_dpop_scheme(request: &DecisionRequest) -> bool {
+    request
+        .header("authorization")
+        .and_then(|authorization| authorization.split_once(' '))
+        .is_some_and(|(scheme, _)| scheme.eq_ignore_ascii_case("dpop"))
+}
+
+/// RFC 9449 4.3: `htu` without its query and fragment is the URL of the request,
+/// the scheme and the host in any case and the path exactly.
+fn matches_htu(htu: &str, expected: &str) -> bool {
+    let htu = htu.split(['?', '#']).next().unwrap_or_default();
+    match (split_url(htu), split_url(expected)) {
+        (Some((scheme, host, path)), Some((expected_scheme, expected_host, expected_path))) => {
+            scheme.eq_ignore_ascii_case(expected_scheme)
+                && host.eq_ignore_ascii_case(expected_host)
+                && path == expected_path
+        }
+        _ => false,
+    }
+}
+
+/// The scheme, the authority and the path of a URL, `/` when it has none.
+fn split_url(url: &str) -> Option<(&str, &str, &str)> {
+    let (scheme, rest) = url.split_once("://")?;
+    match rest.find('/') {
+        Some(index) => Some((scheme, &rest[..index], &rest[index..])),
+        None => Some((scheme, rest, "/")),
+    }
+}
+
+/// The base64url SHA-256, like `ath` and `x5t#S256`.
+fn hash(value: &[u8]) -> String {
+    base64::encode_config(
+        digest::digest(&digest::SHA256, value).as_ref(),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+/// RFC 7638: the hash of the required members of the key, in lexicographic order.
+pub fn thumbprint(jwk: &Jwk) -> Result<String, String> {
+    let value = serde_json::to_value(jwk).map_err(|e| e.to_string())?;
+    let member = |name: &str| value.get(name).cloned().unwrap_or(Value::Null);
+    let required = match value.get("kty").and_then(Value::as_str) {
+        Some("EC") => {
+            json!({"crv": member("crv"), "kty": "EC", "x": member("x"), "y": member("y")})
+        }
+        Some("RSA") => json!({"e": member("e"), "kty": "RSA", "n": member("n")}),
+        Some("OKP") => json!({"crv": member("crv"), "kty": "OKP", "x": member("x")}),
+        kty => return Err(format!("unsupported kty {:?}", kty)),
+    };
+
+    // serde_json sorts the members of the objects
+    Ok(hash(required.to_string().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::authorizer::Confirmation;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use std::collections::HashMap;
+
+    struct ProofKey {
+        pkcs8: Vec<u8>,
+        jwk: Jwk,
+    }
+
+    fn get_key() -> ProofKey {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+                .unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+        // uncompressed point, 0x04 || x || y
+        let point = key_pair.public_key().as_ref();
+        let jwk = serde_json::from_value(json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": base64::encode_config(&point[1..33], base64::URL_SAFE_NO_PAD),
+            "y": base64::encode_config(&point[33..], base64::URL_SAFE_NO_PAD),
+        }))
+        .unwrap();
+
+        ProofKey {
+            pkcs8: pkcs8.as_ref().to_vec(),
+            jwk,
+        }
+    }
+
+    fn get_proof(key: &ProofKey, jti: &str, htu: &str, iat: u64) -> String {
+        let mut header = Header::new(Algorithm::ES256);
+        header.typ = Some("dpop+jwt".to_string());
+        header.jwk = Some(key.jwk.clone());
+        let claims = json!({
+            "jti": jti,
+            "htm": "GET",
+            "htu": htu,
+            "iat": iat,
+            "ath": hash(b"access-token"),
+        });
+
+        encode(&header, &claims, &EncodingKey::from_ec_der(&key.pkcs8)).unwrap()
+    }
+
+    fn get_request(proof: &str) -> DecisionRequest {
+        DecisionRequest::builder()
+            .method("GET")
+            .path("/one")
+            .headers(HashMap::from([
+                ("Authorization".to_string(), "DPoP access-token".to_string()),
+                ("DPoP".to_string(), proof.to_string()),
+                ("Host".to_string(), "api.example.com".to_string()),
+            ]))
+            .build()
+    }
+
+    fn get_claims(jkt: &str) -> Claims {
+        let mut claims: Claims = serde_json::from_value(json!({
+            "aud": "my-audience",
+            "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+            "email": "a@a.com",
+            "exp": 4102444800u64,
+        }))
+        .unwrap();
+        claims.cnf = Some(Confirmation {
+            jkt: Some(jkt.to_string()),
+            ..Default::default()
+        });
+        claims
+    }
+
+    #[test]
+    fn compute_the_rfc_7638_thumbprint() -> Result<(), String> {
+        // ARRANGE
+        let jwk: Jwk = serde_json::from_value(json!({
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29"
+        }))
+        .unwrap();
+
+        // ACT
+        let thumbprint = thumbprint(&jwk)?;
+
+        // ASSERT
+        assert_eq!(thumbprint, "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn accept_a_fresh_proof_once() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let key = get_key();
+        let claims = get_claims(&thumbprint(&key.jwk).unwrap());
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let proof = get_proof(&key, "first", "https://api.example.com/one", now);
+        let validator = DpopValidator::default();
+
+        // ACT
+        let first = validator
+            .validate(&get_request(&proof), Some("access-token"), &claims)
+            .await?;
+        let replayed = validator
+            .validate(&get_request(&proof), Some("access-token"), &claims)
+            .await?;
+
+        // ASSERT
+        assert!(validator.is_required(&get_request(&proof), &claims, false));
+        assert!(first);
+        assert!(!replayed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_a_proof_for_another_request_key_or_time() {
+        // ARRANGE
+        let key = get_key();
+        let claims = get_claims(&thumbprint(&key.jwk).unwrap());
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1665000000);
+        let validator = DpopValidator {
+            max_age: Duration::from_secs(60),
+            ..Default::default()
+        };
+
+        // ACT
+        let valid = validator.check(
+            &get_request(&get_proof(
+                &key,
+                "a",
+                "https://api.example.com/one",
+                1665000000,
+            )),
+            Some("access-token"),
+            &claims,
+            now,
+        );
+        let other_url = validator.check(
+            &get_request(&get_proof(
+                &key,
+                "b",
+                "https://api.example.com/two",
+                1665000000,
+            )),
+            Some("access-token"),
+            &claims,
+            now,
+        );
+        let stale = validator.check(
+            &get_request(&get_proof(
+                &key,
+                "c",
+                "https://api.example.com/one",
+                1664990000,
+            )),
+            Some("access-token"),
+            &claims,
+            now,
+        );
+        let other_key = validator.check(
+            &get_request(&get_proof(
+                &get_key(),
+                "d",
+                "https://api.example.com/one",
+                1665000000,
+            )),
+            Some("access-token"),
+            &claims,
+            now,
+        );
+
+        let proof = get_proof(&key, "e", "https://api.example.com/one", 1665000000);
+        let other_token = validator.check(&get_request(&proof), Some("other-token"), &claims, now);
+        let without_token = validator.check(&get_request(&proof), None, &claims, now);
+
+        // ASSERT
+        assert!(valid.is_ok());
+        assert!(other_url.is_err());
+        assert!(stale.is_err());
+        assert!(other_key.is_err());
+        assert!(other_token.is_err());
+        assert!(without_token.is_err());
+    }
+
+    #[test]
+    fn match_the_htu_exactly() {
+        // ARRANGE
+        let validator = DpopValidator::default();
+        let request = |path: &str, external_path: Option<&str>| {
+            let mut request = get_request("");
+            request.path = path.to_string();
+            request.external_path = external_path.map(str::to_string);
+            request
+        };
+        let expected = |request: &DecisionRequest| validator.expected_htu(request).unwrap();
+
+        // ACT
+        let same = matches_htu(
+            "HTTPS://API.example.com/one?page=2",
+            &expected(&request("/one", None)),
+        );
+        let suffix = matches_htu(
+            "https://api.example.com/x/one",
+            &expected(&request("/one", None)),
+        );
+        let stage = matches_htu(
+            "https://api.example.com/prod/one",
+            &expected(&request("/one", Some("/prod/one"))),
+        );
+        let without_stage = matches_htu(
+            "https://api.example.com/one",
+            &expected(&request("/one", Some("/prod/one"))),
+        );
+        let root = matches_htu("https://api.example.com", &expected(&request("/", None)));
+        let other_host = matches_htu(
+            "https://evil.example.com/one",
+            &expected(&request("/one", None)),
+        );
+        let other_scheme = matches_htu(
+            "http://api.example.com/one",
+            &expected(&request("/one", None)),
+        );
+
+        // ASSERT
+        assert!(same);
+        assert!(!suffix);
+        assert!(stage);
+        assert!(!without_stage);
+        assert!(root);
+        assert!(!other_host);
+        assert!(!other_scheme);
+    }
+
+    #[test]
+    fn build_the_htu_from_the_base_url_or_else_deny_without_host() {
+        // ARRANGE
+        let validator = DpopValidator {
+            base_url: Some("https://public.example.com/api/".to_string()),
+            ..Default::default()
+        };
+        let mut without_host = get_request("");
+        without_host.headers.remove("Host");
+
+        // ACT
+        let configured = validator.expected_htu(&get_request(""));
+        let missing = DpopValidator::default().expected_htu(&without_host);
+
+        // ASSERT
+        assert_eq!(
+            configured,
+            Some("https://public.example.com/api/one".to_string())
+        );
+        assert_eq!(missing, None);
+    }
+}
@@ -0,0 +1,451 @@
+use crate::utils::{
+    client_certificate::ClientCertificate,
+    decision::{Decision, DecisionEngine, DecisionRequest},
+    identity_headers::IdentityHeaders,
+    injections::jwt_di::JWTInitialisation,
+};
+use envoy_types::ext_authz::v3::{
+    pb::{Authorization, CheckRequest, CheckResponse, HeaderAppendAction, HttpStatusCode},
+    CheckRequestExt, CheckResponseExt, DeniedHttpResponseBuilder, OkHttpResponseBuilder,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+/// Envoy `envoy.service.auth.v3.Authorization/Check` backed by the same decision as the LambdaRequestAuthorizer.
+pub struct ExtAuthzService {
+    app_client: Arc<dyn JWTInitialisation>,
+    identity_headers: IdentityHeaders,
+}
+
+impl ExtAuthzService {
+    pub fn new(app_client: Arc<dyn JWTInitialisation>, identity_headers: IdentityHeaders) -> Self {
+        ExtAuthzService {
+            app_client,
+            identity_headers,
+        }
+    }
+
+    fn to_decision_request(request: &CheckRequest) -> Option<DecisionRequest> {
+        let http = request
+            .attributes
+            .as_ref()?
+            .request
+            .as_ref()?
+            .http
+            .as_ref()?;
+        let (path, query) = http.path.split_once('?').unwrap_or((&http.path, ""));
+        let mut headers = http.headers.clone();
+        // the host is in its own attribute, the DPoP `htu` is matched against it
+        if !http.host.is_empty() {
+            headers
+                .entry("host".to_string())
+                .or_insert_with(|| http.host.clone());
+        }
+
+        Some(DecisionRequest {
+            method: http.method.clone(),
+            // resolved like the upstream will, so `/public/../admin` is looked up as `/admin`
+            path: DecisionRequest::normalize_path(path)?,
+            external_path: None,
+            headers,
+            query: DecisionRequest::parse_query(query),
+            source_ip: request.get_client_address().cloned(),
+            // only with the mutual TLS listeners, Envoy has already checked the chain and the validity;
+            // without the issuer DN the certificate matches no grant, only the certificate-bound tokens
+            client_cert: request
+                .attributes
+                .as_ref()?
+                .source
+                .as_ref()
+                .filter(|source| !source.certificate.is_empty())
+                .map(|source| ClientCertificate {
+                    // URL-encoded
+                    pem: Some(source.certificate.clone()),
+                    subject_dn: Some(source.principal.clone()).filter(|x| !x.is_empty()),
+                    ..Default::default()
+                }),
+            api_key: http
+                .headers
+                .get("x-api-key")
+                .filter(|api_key| !api_key.is_empty())
+                .cloned(),
+            caller_arn: None,
+        })
+    }
+
+    fn to_check_response(&self, decision: &Decision) -> CheckResponse {
+        let mut response = CheckResponse::new();
+        if decision.is_allowed() {
+            let mut ok_response = OkHttpResponseBuilder::new();
+            let headers = self.identity_headers.headers(decision);
+            // Envoy removes after it sets, so the headers of the claims are overwritten
+            // and the ones the client sent for missing claims are removed
+            for name in self.identity_headers.names() {
+                if !headers.iter().any(|(header, _)| header == name) {
+                    ok_response.remove_header(name);
+                }
+            }
+            for (header, value) in headers {
+                ok_response.add_header(
+                    header,
+                    value,
+                    Some(HeaderAppendAction::OverwriteIfExistsOrAdd),
+                    false,
+                );
+            }
+            response
+                .set_status(Status::ok("authorized"))
+                .set_http_response(ok_response);
+            return response;
+        }
+
+        let (status, http_status) = if decision.is_unauthenticated() {
+            (
+                Status::unauthenticated("unauthenticated"),
+                HttpStatusCode::Unauthorized,
+            )
+        } else {
+            (
+                Status::permission_denied("permission denied"),
+                HttpStatusCode::Forbidden,
+            )
+        };
+        let mut denied_response = DeniedHttpResponseBuilder::new();
+        denied_response
+            .set_http_status(http_status)
+            .add_header("content-type", "application/json", None, false)
+            .set_body(json!({ "reasons": decision.reasons }).to_string());
+        response
+            .set_status(status)
+            .set_http_response(denied_response);
+
+        response
+    }
+}
+
+#[tonic::async_trait]
+impl Authorization for ExtAuthzService {
+    async fn check(
+        &self,
+        request: Request<CheckRequest>,
+    ) -> Result<Response<CheckResponse>, Status> {
+        let request = request.into_inner();
+        let decision_request = Self::to_decision_request(&request).ok_or_else(|| {
+            Status::invalid_argument(
+                "http request attributes not populated by envoy or path not valid UTF-8",
+            )
+        })?;
+
+        let decision = DecisionEngine::decide(self.app_client.as_ref(), &decision_request)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        println!("decision {:?}", decision);
+
+        Ok(Response::new(self.to_check_response(&decision)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{
+        authorizer::Claims, injections::jwt_di::MockJWTInitialisation, token_source::TokenSources,
+    };
+    use envoy_types::ext_authz::v3::pb::AuthorizationServer;
+    use envoy_types::pb::envoy::service::auth::v3::{
+        attribute_context::{HttpRequest, Request as AttributeRequest},
+        authorization_client::AuthorizationClient,
+        check_response::HttpResponse,
+        AttributeContext,
+    };
+    use envoy_types::pb::google::rpc::Code;
+    use shared::error::ApplicationError;
+    use std::collections::HashMap;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Channel, Server};
+
+    async fn start_server(mock: MockJWTInitialisation) -> AuthorizationClient<Channel> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let service = ExtAuthzService::new(Arc::new(mock), IdentityHeaders::default());
+        tokio::spawn(
+            Server::builder()
+                .add_service(AuthorizationServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let channel = Channel::from_shared(format!("http://{}", address))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        AuthorizationClient::new(channel)
+    }
+
+    fn get_check_request(headers: HashMap<String, String>) -> CheckRequest {
+        CheckRequest {
+            attributes: Some(AttributeContext {
+                request: Some(AttributeRequest {
+                    http: Some(HttpRequest {
+                        method: "GET".to_string(),
+                        path: "/one/?page=2".to_string(),
+                        headers,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn get_claims() -> Result<Option<Claims>, ApplicationError> {
+        let data = r#"
+                {
+          "exp": 1654242297,
+          "aud": "my-audience",
+          "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+          "scope": "my-audience.read",
+          "email": "a@a.com"
+        }"#;
+        Ok(Some(serde_json::from_str(data)?))
+    }
+
+    #[tokio::test]
+    async fn return_ok_with_identity_headers() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+        mock.expect_validate_token()
+            .withf(|token| token == "token")
+            .times(1)
+            .returning(|_| get_claims());
+        mock.expect_get_scope_query()
+            .withf(|method, path| method == "GET" && path == "/one/")
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let mut client = start_server(mock).await;
+        let request = get_check_request(HashMap::from([(
+            "authorization".to_string(),
+            "Bearer token".to_string(),
+        )]));
+
+        // ACT
+        let response = client.check(request).await.unwrap().into_inner();
+
+        // ASSERT
+        assert_eq!(response.status.unwrap().code, Code::Ok as i32);
+        let headers = match response.http_response {
+            Some(HttpResponse::OkResponse(ok_response)) => ok_response.headers,
+            _ => panic!("expected an ok response"),
+        };
+        let headers: HashMap<String, String> = headers
+            .into_iter()
+            .filter_map(|header| header.header)
+            .map(|header| (header.key, header.value))
+            .collect();
+        assert_eq!(
+            headers.get("x-auth-subject"),
+            Some(&"12408bde-207d-45a5-a143-6aa02f049df7".to_string())
+        );
+        assert_eq!(headers.get("x-auth-email"), Some(&"a@a.com".to_string()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remove_the_identity_headers_sent_for_missing_claims() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims());
+        mock.expect_get_scope_query()
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.read".to_string()].into())));
+        let service = ExtAuthzService::new(
+            Arc::new(mock),
+            IdentityHeaders::parse("x-auth-subject=sub,x-auth-client=client_id")?,
+        );
+        let request = get_check_request(HashMap::from([
+            ("authorization".to_string(), "Bearer token".to_string()),
+            ("x-auth-client".to_string(), "admin-console".to_string()),
+        ]));
+
+        // ACT
+        let response = service
+            .check(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner();
+
+        // ASSERT
+        let ok_response = match response.http_response {
+            Some(HttpResponse::OkResponse(ok_response)) => ok_response,
+            _ => panic!("expected an ok response"),
+        };
+        assert_eq!(
+            ok_response.headers_to_remove,
+            vec!["x-auth-client".to_string()]
+        );
+        let headers: Vec<String> = ok_response
+            .headers
+            .into_iter()
+            .filter_map(|header| header.header)
+            .map(|header| header.key)
+            .collect();
+        assert_eq!(headers, vec!["x-auth-subject".to_string()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_unauthenticated_when_token_is_missing() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_validate_token().times(0);
+        let mut client = start_server(mock).await;
+
+        // ACT
+        let response = client
+            .check(get_check_request(HashMap::new()))
+            .await
+            .unwrap()
+            .into_inner();
+
+        // ASSERT
+        assert_eq!(response.status.unwrap().code, Code::Unauthenticated as i32);
+        match response.http_response {
+            Some(HttpResponse::DeniedResponse(denied_response)) => {
+                assert_eq!(
+                    denied_response.status.unwrap().code,
+                    HttpStatusCode::Unauthorized as i32
+                );
+                assert_eq!(denied_response.body, r#"{"reasons":["missing_token"]}"#);
+            }
+            _ => panic!("expected a denied response"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_permission_denied_when_scope_does_not_match() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+        mock.expect_validate_token()
+            .times(1)
+            .returning(|_| get_claims());
+        mock.expect_get_scope_query()
+            .times(1)
+            .returning(|_, _| Ok(Some(vec!["my-audience.write".to_string()].into())));
+        let mut client = start_server(mock).await;
+        let request = get_check_request(HashMap::from([(
+            "authorization".to_string(),
+            "Bearer token".to_string(),
+        )]));
+
+        // ACT
+        let response = client.check(request).await.unwrap().into_inner();
+
+        // ASSERT
+        assert_eq!(response.status.unwrap().code, Code::PermissionDenied as i32);
+        match response.http_response {
+            Some(HttpResponse::DeniedResponse(denied_response)) => {
+                assert_eq!(
+                    denied_response.body,
+                    r#"{"reasons":["insufficient_scope"]}"#
+                );
+            }
+            _ => panic!("expected a denied response"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn look_up_the_rule_of_the_resolved_path() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut mock = MockJWTInitialisation::default();
+        mock.expect_token_sources()
+            .return_const(TokenSources::default());
+        mock.expect_get_principal_status()
+            .returning(|_| Ok(Vec::new()));
+        mock.expect_check_dpop().returning(|_, _, _, _| Ok(true));
+        mock.expect_validate_token()
+            .times(2)
+            .returning(|_| get_claims());
+        mock.expect_get_scope_query()
+            .withf(|method, path| method == "GET" && path == "/admin")
+            .times(2)
+            .returning(|_, _| Ok(Some(vec!["my-audience.admin".to_string()].into())));
+        let mut client = start_server(mock).await;
+        let request = |path: &str| {
+            let mut request = get_check_request(HashMap::from([(
+                "authorization".to_string(),
+                "Bearer token".to_string(),
+            )]));
+            let http = request
+                .attributes
+                .as_mut()
+                .and_then(|attributes| attributes.request.as_mut())
+                .and_then(|request| request.http.as_mut())
+                .unwrap();
+            http.path = path.to_string();
+            request
+        };
+
+        // ACT
+        let dot_segments = client
+            .check(request("/public/../admin"))
+            .await
+            .unwrap()
+            .into_inner();
+        let encoded = client
+            .check(request("/public/%2e%2e/admin?page=2"))
+            .await
+            .unwrap()
+            .into_inner();
+
+        // ASSERT
+        assert_eq!(
+            dot_segments.status.unwrap().code,
+            Code::PermissionDenied as i32
+        );
+        assert_eq!(encoded.status.unwrap().code, Code::PermissionDenied as i32);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_invalid_argument_without_http_attributes() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut client = start_server(MockJWTInitialisation::default()).await;
+
+        // ACT
+        let result = client.check(CheckRequest::default()).await;
+
+        // ASSERT
+        assert_eq!(result.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use graphql_parser::query::{parse_query, Definition, OperationDefinition};
+use shared::error::ApplicationError;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationType {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+impl fmt::Display for OperationType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OperationType::Query => write!(f, "QUERY"),
+            OperationType::Mutation => write!(f, "MUTATION"),
+            OperationType::Subscription => write!(f, "SUBSCRIPTION"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub operation_type: OperationType,
+    pub name: String,
+}
+
+pub struct GraphQL;
+
+impl GraphQL {
+    /// Finds the operation AppSync is going to execute.
+    /// When `operation_name` is set it selects the operation by name, otherwise the document must contain a single operation.
+    /// Anonymous operations are not supported because the rules are keyed by the operation name.
+    pub fn find_operation(
+        query: &str,
+        operation_name: Option<&str>,
+    ) -> Result<Option<Operation>, ApplicationError> {
+        let document = parse_query::<String>(query)
+            .map_err(|e| ApplicationError::ClientError(format!("Invalid GraphQL query {}", e)))?;
+
+        let operations: Vec<Operation> = document
+            .definitions
+            .into_iter()
+            .filter_map(|definition| match definition {
+                Definition::Operation(operation) => Some(operation),
+                Definition::Fragment(_) => None,
+            })
+            .filter_map(|operation| match operation {
+                OperationDefinition::Query(query) => query.name.map(|name| Operation {
+                    operation_type: OperationType::Query,
+                    name,
+                }),
+                OperationDefinition::Mutation(mutation) => mutation.name.map(|name| Operation {
+                    operation_type: OperationType::Mutation,
+                    name,
+                }),
+                OperationDefinition::Subscription(subscription) => {
+                    subscription.name.map(|name| Operation {
+                        operation_type: OperationType::Subscription,
+                        name,
+                    })
+                }
+                OperationDefinition::SelectionSet(_) => None,
+            })
+            .collect();
+
+        match operation_name {
+            Some(operation_name) => Ok(operations.into_iter().find(|x| x.name == operation_name)),
+            None if operations.len() == 1 => Ok(operations.into_iter().next()),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_the_named_operation() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let query = r#"
+            query getPost($id: ID!) { getPost(id: $id) { id title } }
+            mutation createPost($title: String!) { createPost(title: $title) { id } }
+        "#;
+
+        // ACT
+        let result = GraphQL::find_operation(query, Some("createPost"))?;
+
+        // ASSERT
+        assert_eq!(
+            result,
+            Some(Operation {
+                operation_type: OperationType::Mutation,
+                name: "createPost".to_string()
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_the_single_operation_without_operation_name() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let query = r#"
+            subscription onCreatePost { onCreatePost { id } }
+            fragment postFields on Post { id title }
+        "#;
+
+        // ACT
+        let result = GraphQL::find_operation(query, None)?;
+
+        // ASSERT
+        assert_eq!(
+            result,
+            Some(Operation {
+                operation_type: OperationType::Subscription,
+                name: "onCreatePost".to_string()
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_none_for_anonymous_operation() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let query = r#"{ listPosts { items { id } } }"#;
+
+        // ACT
+        let result = GraphQL::find_operation(query, None)?;
+
+        // ASSERT
+        assert!(result.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_for_invalid_query() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let query = r#"query getPost { getPost("#;
+
+        // ACT
+        let result = GraphQL::find_operation(query, None);
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
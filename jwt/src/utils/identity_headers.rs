@@ -0,0 +1,145 @@
+use crate::utils::decision::Decision;
+use serde_json::Value;
+use shared::error::ApplicationError;
+
+const DEFAULT_IDENTITY_HEADERS: &str = "x-auth-subject=sub,x-auth-email=email,x-auth-scope=scope";
+
+/// The headers injected in the upstream request when the decision is ALLOW,
+/// configured as a list of `header=claim` pairs like `x-auth-subject=sub,x-auth-email=email`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityHeaders {
+    headers: Vec<(String, String)>,
+}
+
+impl Default for IdentityHeaders {
+    fn default() -> Self {
+        IdentityHeaders::parse(DEFAULT_IDENTITY_HEADERS).unwrap()
+    }
+}
+
+impl IdentityHeaders {
+    pub fn parse(value: &str) -> Result<Self, ApplicationError> {
+        let headers = value
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((header, claim)) if !header.trim().is_empty() && !claim.trim().is_empty() => {
+                    Ok((header.trim().to_lowercase(), claim.trim().to_string()))
+                }
+                _ => Err(ApplicationError::InitError(format!(
+                    "Invalid identity header {}, expected header=claim",
+                    pair
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(IdentityHeaders { headers })
+    }
+
+    /// Reads `IDENTITY_HEADERS` and falls back to the default headers when it is not set.
+    pub fn from_env() -> Result<Self, ApplicationError> {
+        match std::env::var("IDENTITY_HEADERS") {
+            Ok(value) => IdentityHeaders::parse(&value),
+            Err(_) => Ok(IdentityHeaders::default()),
+        }
+    }
+
+    /// The names of all the configured headers, whichever claims the decision has.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.headers.iter().map(|(header, _)| header.as_str())
+    }
+
+    /// Returns the headers for the claims present in the decision; missing claims are skipped.
+    pub fn headers(&self, decision: &Decision) -> Vec<(String, String)> {
+        let claims = match decision.claims.as_ref().map(serde_json::to_value) {
+            Some(Ok(claims)) => claims,
+            _ => return Vec::new(),
+        };
+
+        self.headers
+            .iter()
+            .filter_map(|(header, claim)| {
+                let value = match claims.get(claim)? {
+                    Value::String(value) => value.clone(),
+                    Value::Array(values) => values
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    Value::Null => return None,
+                    value => value.to_string(),
+                };
+                Some((header.clone(), value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{authorizer::Claims, decision::Reason};
+
+    fn get_decision() -> Decision {
+        let claims: Claims = serde_json::from_str(
+            r#"{
+          "exp": 1654242297,
+          "aud": "my-audience",
+          "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+          "email": "a@a.com"
+        }"#,
+        )
+        .unwrap();
+        Decision::allow(claims, Reason::ScopeGranted)
+    }
+
+    #[test]
+    fn map_claims_to_headers() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let identity_headers =
+            IdentityHeaders::parse("X-User = sub, x-expires=exp, x-scope=scope")?;
+
+        // ACT
+        let headers = identity_headers.headers(&get_decision());
+
+        // ASSERT
+        assert_eq!(
+            headers,
+            vec![
+                (
+                    "x-user".to_string(),
+                    "12408bde-207d-45a5-a143-6aa02f049df7".to_string()
+                ),
+                ("x-expires".to_string(), "1654242297".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_no_headers_when_denied() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let identity_headers = IdentityHeaders::default();
+
+        // ACT
+        let headers = identity_headers.headers(&Decision::deny(Reason::InvalidToken));
+
+        // ASSERT
+        assert!(headers.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_when_configuration_is_not_valid() -> Result<(), ApplicationError> {
+        // ACT
+        let result = IdentityHeaders::parse("x-user");
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
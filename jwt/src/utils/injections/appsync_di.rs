@@ -0,0 +1,181 @@
+use crate::{
+    queries::{
+        get_operation_rule::{GetOperationRule, GetOperationRuleQuery, OperationRule},
+        principal_status::{GetPrincipalStatus, PrincipalStatus},
+    },
+    utils::{
+        authorizer::{Authorizer, Claims, JWTAuthorizer},
+        degradation::DegradationPolicy,
+        graphql::Operation,
+        injections::jwt_di::{self, JWTInitialisation},
+        route_rule::RoutePolicy,
+        token_source::{TokenSource, TokenSources},
+    },
+};
+use async_trait::async_trait;
+use aws_lambda_events::apigw::ApiGatewayCustomAuthorizerResponse;
+use shared::error::ApplicationError;
+use typed_builder::TypedBuilder as Builder;
+
+/// The AppSync authorizer decides with the `DecisionEngine`, its routes being the operation rules.
+#[async_trait]
+pub trait AppSyncInitialisation: JWTInitialisation {
+    async fn get_operation_rules(
+        &self,
+        operation: &Operation,
+    ) -> Result<Vec<OperationRule>, ApplicationError>;
+}
+
+#[derive(Debug, Clone, Builder)]
+pub struct AppSyncAppClient {
+    #[builder(setter(into))]
+    pub authorizer: Authorizer,
+
+    #[builder(setter(into))]
+    pub get_operation_rule_query: GetOperationRule,
+
+    /// Denies suspended principals and superseded tokens when set.
+    #[builder(default)]
+    pub principal_status: Option<GetPrincipalStatus>,
+}
+
+impl AppSyncAppClient {
+    /// Validates the tokens like the LambdaRequestAuthorizer, with the operation rules of `SCOPE_TABLE_NAME`.
+    pub async fn from_env(config: &aws_config::SdkConfig) -> Result<Self, ApplicationError> {
+        let dynamodb_client = jwt_di::dynamodb_client(config)?;
+        let secrets_client = aws_sdk_secretsmanager::Client::new(config);
+        let degradation_policy = DegradationPolicy::from_env()?;
+        let table_name = std::env::var("SCOPE_TABLE_NAME")
+            .map_err(|_| ApplicationError::InitError("SCOPE_TABLE_NAME must be set".to_string()))?;
+
+        Ok(AppSyncAppClient::builder()
+            .authorizer(
+                Authorizer::from_env(&dynamodb_client, &secrets_client, degradation_policy.keys)
+                    .await?,
+            )
+            .principal_status(GetPrincipalStatus::from_env(&dynamodb_client)?)
+            .get_operation_rule_query(
+                GetOperationRule::builder()
+                    .table_name(table_name)
+                    .dynamo_db_client(dynamodb_client)
+                    .build(),
+            )
+            .build())
+    }
+}
+
+#[async_trait]
+impl JWTInitialisation for AppSyncAppClient {
+    fn to_response(
+        &self,
+        effect: String,
+        principal: Option<String>,
+        method_arn: String,
+    ) -> ApiGatewayCustomAuthorizerResponse {
+        self.authorizer.to_response(effect, principal, method_arn)
+    }
+
+    /// AppSync sends the `authorizationToken` with or without its scheme, `validate_token` strips it.
+    fn token_sources(&self) -> TokenSources {
+        TokenSources(vec![TokenSource::Header {
+            name: "authorization".to_string(),
+            scheme: None,
+        }])
+    }
+
+    async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError> {
+        self.authorizer.validate_token(raw_token).await
+    }
+
+    /// The rule of the operation route, `QUERY` on `/getPost` being `QUERY/getPost`.
+    async fn get_scope_query(
+        &self,
+        method: &str,
+        path: &str,
+    ) -> Result<Option<RoutePolicy>, ApplicationError> {
+        Ok(self
+            .get_operation_rule_query
+            .execute(&format!("{}{}", method, path))
+            .await?
+            .map(|rule| RoutePolicy::from(rule.scopes)))
+    }
+
+    async fn get_principal_status(
+        &self,
+        claims: &Claims,
+    ) -> Result<Vec<PrincipalStatus>, ApplicationError> {
+        match &self.principal_status {
+            Some(principal_status) => principal_status.execute(claims).await,
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl AppSyncInitialisation for AppSyncAppClient {
+    /// Returns all the rules matching the operation, the type-wide rule (`QUERY/*`) first and the operation rule (`QUERY/getPost`) last.
+    async fn get_operation_rules(
+        &self,
+        operation: &Operation,
+    ) -> Result<Vec<OperationRule>, ApplicationError> {
+        let keys = [
+            format!("{}/*", operation.operation_type),
+            format!("{}/{}", operation.operation_type, operation.name),
+        ];
+
+        let mut rules = Vec::new();
+        for key in keys {
+            if let Some(rule) = self.get_operation_rule_query.execute(&key).await? {
+                rules.push(rule);
+            }
+        }
+
+        Ok(rules)
+    }
+}
+
+/// The operation rules already read, served to the `DecisionEngine` without reading them again.
+pub struct OperationRules<'a> {
+    pub app_client: &'a dyn AppSyncInitialisation,
+    pub rules: &'a [OperationRule],
+}
+
+#[async_trait]
+impl<'a> JWTInitialisation for OperationRules<'a> {
+    fn to_response(
+        &self,
+        effect: String,
+        principal: Option<String>,
+        method_arn: String,
+    ) -> ApiGatewayCustomAuthorizerResponse {
+        self.app_client.to_response(effect, principal, method_arn)
+    }
+
+    fn token_sources(&self) -> TokenSources {
+        self.app_client.token_sources()
+    }
+
+    async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError> {
+        self.app_client.validate_token(raw_token).await
+    }
+
+    async fn get_scope_query(
+        &self,
+        method: &str,
+        path: &str,
+    ) -> Result<Option<RoutePolicy>, ApplicationError> {
+        let pk = format!("{}{}", method, path);
+        Ok(self
+            .rules
+            .iter()
+            .find(|rule| rule.pk == pk)
+            .map(|rule| RoutePolicy::from(rule.scopes.clone())))
+    }
+
+    async fn get_principal_status(
+        &self,
+        claims: &Claims,
+    ) -> Result<Vec<PrincipalStatus>, ApplicationError> {
+        self.app_client.get_principal_status(claims).await
+    }
+}
@@ -1,17 +1,37 @@
 use crate::{
-    queries::get_scope::{GetScope, GetScopeQuery},
-    utils::authorizer::{Authorizer, Claims, JWTAuthorizer},
+    queries::{
+        api_keys::{ApiKeyCaller, GetApiKey},
+        certificate_grants::GetCertificateGrants,
+        get_scope::{GetScope, GetScopeQuery},
+        iam_grants::GetIamGrants,
+        principal_status::{GetPrincipalStatus, PrincipalStatus},
+        scope_store::ScopeStore,
+    },
+    utils::{
+        authorizer::{Authorizer, Claims, Confirmation, JWTAuthorizer},
+        client_certificate::{self, ClientCertificate},
+        decision::DecisionRequest,
+        degradation::DegradationPolicy,
+        dpop::DpopValidator,
+        route_rule::{route_key, RoutePolicy},
+        token_source::TokenSources,
+        webhook_signature::WebhookVerifier,
+    },
 };
 use async_trait::async_trait;
 use aws_lambda_events::apigw::ApiGatewayCustomAuthorizerResponse;
 #[cfg(test)]
 use mockall::{automock, predicate::*};
 use shared::error::ApplicationError;
+use std::{sync::Arc, time::SystemTime};
 use typed_builder::TypedBuilder as Builder;
 
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait JWTInitialisation: Send + Sync {
+    fn token_sources(&self) -> TokenSources {
+        TokenSources::default()
+    }
     async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError>;
     fn to_response(
         &self,
@@ -23,20 +43,138 @@ pub trait JWTInitialisation: Send + Sync {
         &self,
         method: &str,
         path: &str,
-    ) -> Result<Option<Vec<String>>, ApplicationError>;
+    ) -> Result<Option<RoutePolicy>, ApplicationError>;
+    async fn get_principal_status(
+        &self,
+        _claims: &Claims,
+    ) -> Result<Vec<PrincipalStatus>, ApplicationError> {
+        Ok(Vec::new())
+    }
+    async fn authenticate_certificate(
+        &self,
+        _certificate: &ClientCertificate,
+    ) -> Result<Option<Claims>, ApplicationError> {
+        Ok(None)
+    }
+    async fn authenticate_api_key(
+        &self,
+        _api_key: &str,
+    ) -> Result<Option<ApiKeyCaller>, ApplicationError> {
+        Ok(None)
+    }
+    async fn authenticate_iam_caller(
+        &self,
+        _caller_arn: &str,
+    ) -> Result<Option<Claims>, ApplicationError> {
+        Ok(None)
+    }
+    async fn authenticate_webhook(
+        &self,
+        _request: &DecisionRequest,
+    ) -> Result<Option<Claims>, ApplicationError> {
+        Ok(None)
+    }
+    /// The access token is the one the engine extracted, the `ath` of the proof being its hash.
+    async fn check_dpop(
+        &self,
+        _request: &DecisionRequest,
+        _access_token: Option<String>,
+        _claims: &Claims,
+        route_bound: bool,
+    ) -> Result<bool, ApplicationError> {
+        Ok(!route_bound)
+    }
 }
 
 #[derive(Debug, Clone, Builder)]
-pub struct JWTAppClient {
+pub struct JWTAppClient<Q = GetScope> {
     #[builder(setter(into))]
     pub authorizer: Authorizer,
 
-    #[builder(setter(into))]
-    pub get_scope_query: GetScope,
+    pub get_scope_query: Q,
+
+    /// Denies suspended principals and superseded tokens when set.
+    #[builder(default)]
+    pub principal_status: Option<GetPrincipalStatus>,
+
+    /// Authenticates the requests without token by their client certificate when set.
+    #[builder(default)]
+    pub certificate_grants: Option<GetCertificateGrants>,
+
+    /// Authenticates the requests without token by their API key when set.
+    #[builder(default)]
+    pub api_keys: Option<GetApiKey>,
+
+    /// Authenticates the SigV4 requests without token by the ARN of their IAM principal when set.
+    #[builder(default)]
+    pub iam_grants: Option<GetIamGrants>,
+
+    /// Authenticates the signed webhooks by the secret of their integration when set.
+    #[builder(default)]
+    pub webhooks: Option<WebhookVerifier>,
+
+    /// Validates the DPoP proofs when set, otherwise the DPoP-bound routes are denied.
+    #[builder(default)]
+    pub dpop: Option<DpopValidator>,
+
+    /// Where the requests carry their token.
+    #[builder(default)]
+    pub token_sources: TokenSources,
+}
+
+impl JWTAppClient<Arc<dyn GetScopeQuery + Send + Sync>> {
+    /// Wires every dependency from the environment, the same for all the binaries.
+    pub async fn from_env(config: &aws_config::SdkConfig) -> Result<Self, ApplicationError> {
+        let dynamodb_client = dynamodb_client(config)?;
+        let s3_client = aws_sdk_s3::Client::new(config);
+        let secrets_client = aws_sdk_secretsmanager::Client::new(config);
+        let degradation_policy = DegradationPolicy::from_env()?;
+
+        Ok(JWTAppClient::builder()
+            .authorizer(
+                Authorizer::from_env(&dynamodb_client, &secrets_client, degradation_policy.keys)
+                    .await?,
+            )
+            .principal_status(GetPrincipalStatus::from_env(&dynamodb_client)?)
+            .certificate_grants(GetCertificateGrants::from_env(&dynamodb_client))
+            .api_keys(GetApiKey::from_env(&dynamodb_client))
+            .iam_grants(GetIamGrants::from_env(
+                &dynamodb_client,
+                degradation_policy.grants,
+            ))
+            .dpop(Some(DpopValidator::from_env(&dynamodb_client)?))
+            .webhooks(WebhookVerifier::from_env(&dynamodb_client)?)
+            .token_sources(TokenSources::from_env()?)
+            .get_scope_query(
+                ScopeStore::from_env()?
+                    .build(dynamodb_client, s3_client, degradation_policy.rules)
+                    .await?,
+            )
+            .build())
+    }
+}
+
+/// The DynamoDB client of the tables, pointed to a local endpoint by `DYNAMODB_ENDPOINT`.
+pub(crate) fn dynamodb_client(
+    config: &aws_config::SdkConfig,
+) -> Result<aws_sdk_dynamodb::Client, ApplicationError> {
+    let mut dynamodb_config = aws_sdk_dynamodb::config::Builder::from(config);
+    if let Ok(endpoint) = std::env::var("DYNAMODB_ENDPOINT") {
+        let endpoint = endpoint.parse().map_err(|_| {
+            ApplicationError::InitError("DYNAMODB_ENDPOINT must be a uri".to_string())
+        })?;
+        dynamodb_config =
+            dynamodb_config.endpoint_resolver(aws_sdk_dynamodb::Endpoint::immutable(endpoint));
+    }
+
+    Ok(aws_sdk_dynamodb::Client::from_conf(dynamodb_config.build()))
 }
 
 #[async_trait]
-impl JWTInitialisation for JWTAppClient {
+impl<Q> JWTInitialisation for JWTAppClient<Q>
+where
+    Q: GetScopeQuery + Send + Sync,
+{
     fn to_response(
         &self,
         effect: String,
@@ -46,6 +184,10 @@ impl JWTInitialisation for JWTAppClient {
         self.authorizer.to_response(effect, principal, method_arn)
     }
 
+    fn token_sources(&self) -> TokenSources {
+        self.token_sources.clone()
+    }
+
     async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError> {
         self.authorizer.validate_token(raw_token).await
     }
@@ -54,12 +196,143 @@ impl JWTInitialisation for JWTAppClient {
         &self,
         method: &str,
         path: &str,
-    ) -> Result<Option<Vec<String>>, ApplicationError> {
-        let mut application_identity = path.to_string();
-        if !path.ends_with('/') {
-            application_identity = format!("{}/", &path);
+    ) -> Result<Option<RoutePolicy>, ApplicationError> {
+        self.get_scope_query
+            .execute(&route_key(&format!("{}{}", method, path)))
+            .await
+    }
+
+    async fn get_principal_status(
+        &self,
+        claims: &Claims,
+    ) -> Result<Vec<PrincipalStatus>, ApplicationError> {
+        match &self.principal_status {
+            Some(principal_status) => principal_status.execute(claims).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The claims of a certificate, with the scopes of all its grants; the engine checks its validity window first.
+    async fn authenticate_certificate(
+        &self,
+        certificate: &ClientCertificate,
+    ) -> Result<Option<Claims>, ApplicationError> {
+        let certificate_grants = match &self.certificate_grants {
+            Some(certificate_grants) => certificate_grants,
+            None => return Ok(None),
+        };
+        let grants = certificate_grants.execute(certificate).await?;
+        let sub = match certificate
+            .subject_dn
+            .clone()
+            .or_else(|| certificate.serial_number.clone())
+        {
+            Some(sub) if !grants.is_empty() => sub,
+            _ => return Ok(None),
+        };
+        let scopes: Vec<&str> = grants
+            .iter()
+            .flat_map(|grant| grant.scopes.iter().map(String::as_str))
+            .collect();
+        let exp = certificate
+            .expires_at()?
+            .and_then(|expires_at| expires_at.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(usize::MAX, |exp| exp.as_secs() as usize);
+        // a certificate caller is bound to its certificate
+        let cnf = match certificate.pem.as_deref() {
+            Some(pem) => Some(Confirmation {
+                x5t_s256: Some(client_certificate::thumbprint(pem)?),
+                ..Default::default()
+            }),
+            None => None,
+        };
+
+        Ok(Some(Claims {
+            exp,
+            cnf,
+            ..Claims::for_principal(
+                &self.authorizer.audience,
+                &sub,
+                grants.iter().find_map(|grant| grant.principal.as_deref()),
+                &scopes,
+            )
+        }))
+    }
+
+    /// The owner and the grants of an enabled API key that has not expired.
+    async fn authenticate_api_key(
+        &self,
+        api_key: &str,
+    ) -> Result<Option<ApiKeyCaller>, ApplicationError> {
+        let api_keys = match &self.api_keys {
+            Some(api_keys) => api_keys,
+            None => return Ok(None),
+        };
+
+        match api_keys.execute(api_key).await? {
+            Some(record) if record.is_active_at(SystemTime::now()) => {
+                Ok(Some(record.to_caller(api_key, &self.authorizer.audience)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The claims of an IAM principal, with the scopes of all the grants matching its ARN.
+    async fn authenticate_iam_caller(
+        &self,
+        caller_arn: &str,
+    ) -> Result<Option<Claims>, ApplicationError> {
+        let iam_grants = match &self.iam_grants {
+            Some(iam_grants) => iam_grants,
+            None => return Ok(None),
+        };
+        let grants = iam_grants.execute(caller_arn).await?;
+        if grants.is_empty() {
+            return Ok(None);
+        }
+        let scopes: Vec<&str> = grants
+            .iter()
+            .flat_map(|grant| grant.scopes.iter().map(String::as_str))
+            .collect();
+
+        Ok(Some(Claims::for_principal(
+            &self.authorizer.audience,
+            caller_arn,
+            grants.iter().find_map(|grant| grant.principal.as_deref()),
+            &scopes,
+        )))
+    }
+
+    /// The claims of the integration whose secret signed the webhook.
+    async fn authenticate_webhook(
+        &self,
+        request: &DecisionRequest,
+    ) -> Result<Option<Claims>, ApplicationError> {
+        let webhooks = match &self.webhooks {
+            Some(webhooks) => webhooks,
+            None => return Ok(None),
+        };
+
+        Ok(webhooks
+            .verify(request)
+            .await?
+            .map(|integration| integration.to_claims(&self.authorizer.audience)))
+    }
+
+    async fn check_dpop(
+        &self,
+        request: &DecisionRequest,
+        access_token: Option<String>,
+        claims: &Claims,
+        route_bound: bool,
+    ) -> Result<bool, ApplicationError> {
+        match &self.dpop {
+            Some(dpop) if dpop.is_required(request, claims, route_bound) => {
+                dpop.validate(request, access_token.as_deref(), claims)
+                    .await
+            }
+            Some(_) => Ok(true),
+            None => Ok(!route_bound),
         }
-        let api = format!("{}{}", method, application_identity);
-        self.get_scope_query.execute(&api).await
     }
 }
@@ -1 +1,2 @@
+pub mod appsync_di;
 pub mod jwt_di;
@@ -1,2 +1,18 @@
 pub mod authorizer;
-pub mod injections;
\ No newline at end of file
+pub mod circuit_breaker;
+pub mod client_certificate;
+pub mod csrf;
+pub mod decision;
+pub mod decision_service;
+pub mod degradation;
+pub mod dpop;
+pub mod ext_authz;
+pub mod graphql;
+pub mod identity_headers;
+pub mod injections;
+pub mod introspection;
+pub mod jwe;
+pub mod route_rule;
+pub mod scope_index;
+pub mod token_source;
+pub mod webhook_signature;
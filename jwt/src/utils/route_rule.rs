@@ -0,0 +1,207 @@
+use crate::utils::csrf::CsrfPolicy;
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde::{Deserialize, Serialize};
+use shared::{error::ApplicationError, utils::dynamodb::from_item};
+use std::collections::HashMap;
+
+/// The newest record layout this authorizer understands.
+pub const ROUTE_RULE_VERSION: u32 = 1;
+
+/// The key of a route in every scope store, `{METHOD}{path}/`.
+pub fn route_key(api: &str) -> String {
+    if api.ends_with('/') {
+        api.to_string()
+    } else {
+        format!("{}/", api)
+    }
+}
+
+/// An item of the scope table. Records without `version` are version 1.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RouteRule {
+    pub pk: String,
+
+    #[serde(default = "RouteRule::default_version")]
+    pub version: u32,
+
+    #[serde(flatten)]
+    pub policy: RoutePolicy,
+}
+
+/// What a route requires from the caller, served by the scope stores.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutePolicy {
+    pub scopes: Vec<String>,
+
+    /// RFC 8705: the `cnf.x5t#S256` of the token must match the client certificate of the request.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub certificate_bound: bool,
+
+    /// RFC 9449: the request must carry a DPoP proof of the key the token is bound to.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dpop_bound: bool,
+
+    /// The credentials the route accepts, the tokens by default.
+    #[serde(default, skip_serializing_if = "Credentials::is_jwt")]
+    pub accepts: Credentials,
+
+    /// The CSRF checks of the state-changing requests authenticated by a cookie.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csrf: Option<CsrfPolicy>,
+}
+
+/// The credentials of a route: JWTs, including the opaque tokens, API keys, either of them,
+/// the signed webhooks, the client certificates without token or the SigV4 signed IAM callers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Credentials {
+    #[default]
+    Jwt,
+    ApiKey,
+    Either,
+    Webhook,
+    Certificate,
+    Iam,
+}
+
+impl Credentials {
+    pub fn is_jwt(&self) -> bool {
+        *self == Credentials::Jwt
+    }
+
+    pub fn accepts(&self, credential: Credentials) -> bool {
+        match self {
+            Credentials::Either => matches!(credential, Credentials::Jwt | Credentials::ApiKey),
+            _ => *self == credential,
+        }
+    }
+}
+
+impl From<Vec<String>> for RoutePolicy {
+    fn from(scopes: Vec<String>) -> Self {
+        RoutePolicy {
+            scopes,
+            certificate_bound: false,
+            dpop_bound: false,
+            accepts: Credentials::Jwt,
+            csrf: None,
+        }
+    }
+}
+
+impl RouteRule {
+    fn default_version() -> u32 {
+        1
+    }
+
+    pub fn from_item(item: &HashMap<String, AttributeValue>) -> Result<Self, ApplicationError> {
+        let rule: RouteRule = from_item(item).map_err(|e| {
+            // the flattened fields are buffered and lose their path, the policy alone reports it
+            from_item::<RoutePolicy>(item).err().unwrap_or(e)
+        })?;
+        if rule.version > ROUTE_RULE_VERSION {
+            return Err(ApplicationError::InternalError(format!(
+                "Unsupported route rule version {} for {}",
+                rule.version, rule.pk
+            )));
+        }
+
+        Ok(rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_the_routes_with_a_trailing_slash() {
+        // ACT
+        let without = route_key("GET/one");
+        let with = route_key("GET/one/");
+
+        // ASSERT
+        assert_eq!(without, "GET/one/");
+        assert_eq!(with, "GET/one/");
+    }
+
+    #[test]
+    fn parse_a_record_without_version() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let item = HashMap::from([
+            ("pk".to_string(), AttributeValue::S("GET/one/".to_string())),
+            (
+                "scopes".to_string(),
+                AttributeValue::Ss(vec!["my-audience.read".to_string()]),
+            ),
+        ]);
+
+        // ACT
+        let rule = RouteRule::from_item(&item)?;
+
+        // ASSERT
+        assert_eq!(
+            rule,
+            RouteRule {
+                pk: "GET/one/".to_string(),
+                version: 1,
+                policy: vec!["my-audience.read".to_string()].into(),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_the_policy_of_a_record() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let item = HashMap::from([
+            ("pk".to_string(), AttributeValue::S("GET/one/".to_string())),
+            ("version".to_string(), AttributeValue::N("1".to_string())),
+            (
+                "scopes".to_string(),
+                AttributeValue::L(vec![AttributeValue::S("my-audience.read".to_string())]),
+            ),
+            ("certificate_bound".to_string(), AttributeValue::Bool(true)),
+            (
+                "accepts".to_string(),
+                AttributeValue::S("either".to_string()),
+            ),
+        ]);
+
+        // ACT
+        let rule = RouteRule::from_item(&item)?;
+
+        // ASSERT
+        assert_eq!(
+            rule.policy,
+            RoutePolicy {
+                scopes: vec!["my-audience.read".to_string()],
+                certificate_bound: true,
+                dpop_bound: false,
+                accepts: Credentials::Either,
+                csrf: None,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_for_a_newer_version() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let item = HashMap::from([
+            ("pk".to_string(), AttributeValue::S("GET/one/".to_string())),
+            ("version".to_string(), AttributeValue::N("2".to_string())),
+            ("scopes".to_string(), AttributeValue::L(vec![])),
+        ]);
+
+        // ACT
+        let result = RouteRule::from_item(&item);
+
+        // ASSERT
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
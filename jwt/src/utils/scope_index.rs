@@ -0,0 +1,62 @@
+use crate::utils::route_rule::{route_key, RoutePolicy};
+use std::collections::HashMap;
+
+/// In-memory index of the scope rules keyed like the table, `{METHOD}{path}/`.
+/// Keys are matched exactly, like a `GetItem` on the table, but unlike the table
+/// a rule key without its trailing slash is given one by `route_key`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScopeIndex {
+    exact: HashMap<String, RoutePolicy>,
+}
+
+impl ScopeIndex {
+    pub fn new(rules: impl IntoIterator<Item = (String, RoutePolicy)>) -> Self {
+        ScopeIndex {
+            exact: rules
+                .into_iter()
+                .map(|(pk, policy)| (route_key(&pk), policy))
+                .collect(),
+        }
+    }
+
+    pub fn find(&self, api: &str) -> Option<RoutePolicy> {
+        self.exact.get(&route_key(api)).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.exact.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_the_exact_rules_only() {
+        // ARRANGE
+        let index = ScopeIndex::new(vec![
+            ("GET/one".to_string(), vec!["read".to_string()].into()),
+            (
+                "GET/items/{id}/".to_string(),
+                vec!["items.read".to_string()].into(),
+            ),
+        ]);
+
+        // ACT
+        let one = index.find("GET/one/");
+        let item = index.find("GET/items/42/");
+        let literal = index.find("GET/items/{id}");
+        let missing = index.find("POST/one/");
+
+        // ASSERT
+        assert_eq!(one, Some(vec!["read".to_string()].into()));
+        assert_eq!(item, None);
+        assert_eq!(literal, Some(vec!["items.read".to_string()].into()));
+        assert_eq!(missing, None);
+    }
+}
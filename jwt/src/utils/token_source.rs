@@ -0,0 +1,212 @@
+use crate::utils::decision::DecisionRequest;
+use shared::error::ApplicationError;
+use std::str::FromStr;
+
+/// Where a request may carry its token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenSource {
+    /// A header, with the scheme of its value, case-insensitive, like `Authorization: Bearer <token>`.
+    Header {
+        name: String,
+        scheme: Option<String>,
+    },
+    Cookie(String),
+    Query(String),
+}
+
+impl TokenSource {
+    fn token<'a>(&self, request: &'a DecisionRequest) -> Option<&'a str> {
+        let token = match self {
+            TokenSource::Header { name, scheme: None } => request.header(name),
+            TokenSource::Header {
+                name,
+                scheme: Some(scheme),
+            } => request
+                .header(name)
+                .and_then(|value| value.trim().split_once(' '))
+                .filter(|(actual, _)| actual.eq_ignore_ascii_case(scheme))
+                .map(|(_, token)| token),
+            TokenSource::Cookie(name) => request.cookie(name),
+            TokenSource::Query(name) => request.query.get(name).map(String::as_str),
+        };
+
+        token.map(str::trim).filter(|token| !token.is_empty())
+    }
+}
+
+/// `header:<name>[:<scheme>]`, `cookie:<name>` or `query:<name>`.
+impl FromStr for TokenSource {
+    type Err = ApplicationError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = value.trim().splitn(3, ':').collect();
+        match parts.as_slice() {
+            ["header", name] => Ok(TokenSource::Header {
+                name: name.to_string(),
+                scheme: None,
+            }),
+            ["header", name, scheme] => Ok(TokenSource::Header {
+                name: name.to_string(),
+                scheme: Some(scheme.to_string()),
+            }),
+            ["cookie", name] => Ok(TokenSource::Cookie(name.to_string())),
+            ["query", name] => Ok(TokenSource::Query(name.to_string())),
+            _ => Err(ApplicationError::InitError(format!(
+                "Invalid token source {}, expected header:<name>[:<scheme>], cookie:<name> or query:<name>",
+                value
+            ))),
+        }
+    }
+}
+
+/// The ordered sources of the tokens, `Authorization` with the `Bearer` or `DPoP` scheme by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenSources(pub Vec<TokenSource>);
+
+impl Default for TokenSources {
+    fn default() -> Self {
+        TokenSources(vec![
+            TokenSource::Header {
+                name: "authorization".to_string(),
+                scheme: Some("Bearer".to_string()),
+            },
+            TokenSource::Header {
+                name: "authorization".to_string(),
+                scheme: Some("DPoP".to_string()),
+            },
+        ])
+    }
+}
+
+impl TokenSources {
+    /// `TOKEN_SOURCES`, comma separated, like `header:Authorization:Bearer,cookie:access_token`.
+    pub fn from_env() -> Result<Self, ApplicationError> {
+        match std::env::var("TOKEN_SOURCES") {
+            Ok(sources) => Ok(TokenSources(
+                sources
+                    .split(',')
+                    .filter(|source| !source.trim().is_empty())
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?,
+            )),
+            Err(_) => Ok(TokenSources::default()),
+        }
+    }
+
+    /// The distinct tokens of the request, in the order of the sources; more than one is a conflict.
+    pub fn tokens(&self, request: &DecisionRequest) -> Vec<String> {
+        let mut tokens: Vec<String> = Vec::new();
+        for token in self.0.iter().filter_map(|source| source.token(request)) {
+            if !tokens.iter().any(|x| x == token) {
+                tokens.push(token.to_string());
+            }
+        }
+
+        tokens
+    }
+
+    /// True when the token was only read from cookies, which a browser sends on cross-site requests too.
+    pub fn is_cookie_only(&self, request: &DecisionRequest) -> bool {
+        let mut sources = self
+            .0
+            .iter()
+            .filter(|source| source.token(request).is_some())
+            .peekable();
+
+        sources.peek().is_some() && sources.all(|source| matches!(source, TokenSource::Cookie(_)))
+    }
+
+    /// True when a token may be read from the header, case-insensitive.
+    pub fn reads_header(&self, header: &str) -> bool {
+        self.0.iter().any(|source| {
+            matches!(source, TokenSource::Header { name, .. } if name.eq_ignore_ascii_case(header))
+        })
+    }
+
+    /// True when a token may be read from the query parameter.
+    pub fn reads_query(&self, parameter: &str) -> bool {
+        self.0
+            .iter()
+            .any(|source| matches!(source, TokenSource::Query(name) if name == parameter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn get_sources() -> Result<TokenSources, ApplicationError> {
+        Ok(TokenSources(vec![
+            "header:Authorization:Bearer".parse()?,
+            "cookie:access_token".parse()?,
+            "header:X-Access-Token".parse()?,
+            "query:access_token".parse()?,
+        ]))
+    }
+
+    fn get_request(headers: &[(&str, &str)]) -> DecisionRequest {
+        DecisionRequest::builder()
+            .method("GET")
+            .path("/one")
+            .headers(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn read_the_token_from_any_source() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let sources = get_sources()?;
+        let mut query = get_request(&[]);
+        query.query = HashMap::from([("access_token".to_string(), "from-query".to_string())]);
+
+        // ACT
+        let bearer = sources.tokens(&get_request(&[("authorization", "bearer from-header")]));
+        let cookie = sources.tokens(&get_request(&[(
+            "Cookie",
+            "theme=dark; access_token=from-cookie",
+        )]));
+        let legacy = sources.tokens(&get_request(&[("X-Access-Token", "from-legacy")]));
+        let query = sources.tokens(&query);
+        let basic = sources.tokens(&get_request(&[("Authorization", "Basic dXNlcg==")]));
+
+        // ASSERT
+        assert_eq!(bearer, vec!["from-header".to_string()]);
+        assert_eq!(cookie, vec!["from-cookie".to_string()]);
+        assert!(sources.is_cookie_only(&get_request(&[("Cookie", "access_token=from-cookie")])));
+        assert!(!sources.is_cookie_only(&get_request(&[("X-Access-Token", "from-legacy")])));
+        assert_eq!(legacy, vec!["from-legacy".to_string()]);
+        assert_eq!(query, vec!["from-query".to_string()]);
+        assert!(basic.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_every_distinct_token() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let sources = get_sources()?;
+
+        // ACT
+        let same = sources.tokens(&get_request(&[
+            ("Authorization", "Bearer token"),
+            ("Cookie", "access_token=token"),
+        ]));
+        let conflicting = sources.tokens(&get_request(&[
+            ("Authorization", "Bearer token"),
+            ("X-Access-Token", "other"),
+        ]));
+
+        // ASSERT
+        assert_eq!(same.len(), 1);
+        assert_eq!(conflicting, vec!["token".to_string(), "other".to_string()]);
+        assert!("path:token".parse::<TokenSource>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+use crate::{
+    queries::{
+        proof_replay::ProofReplay,
+        webhook_integrations::{GetWebhookIntegration, WebhookIntegration},
+    },
+    utils::decision::DecisionRequest,
+};
+use ring::hmac;
+use shared::error::ApplicationError;
+use std::time::{Duration, SystemTime};
+use typed_builder::TypedBuilder as Builder;
+
+pub const WEBHOOK_ID_HEADER: &str = "x-webhook-id";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-webhook-signature";
+pub const WEBHOOK_DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+/// Verifies the webhooks signed with the secret of their integration.
+/// A REQUEST authorizer does not see the body, so `x-webhook-signature` is
+/// `v1=<hex HMAC-SHA256 of "<METHOD>\n<path>\n<timestamp>\n<delivery>">`, with the timestamp
+/// in `x-webhook-timestamp` and the unique id of the delivery in `x-webhook-delivery`.
+#[derive(Debug, Clone, Builder)]
+pub struct WebhookVerifier {
+    pub integrations: GetWebhookIntegration,
+
+    /// How far the timestamp of a webhook may be from now.
+    #[builder(default = Duration::from_secs(300))]
+    pub max_age: Duration,
+
+    #[builder(default)]
+    pub replay: ProofReplay,
+}
+
+impl WebhookVerifier {
+    /// Enabled by `WEBHOOK_TABLE_NAME`, with `WEBHOOK_MAX_AGE` in seconds and the deliveries remembered
+    /// across the instances by `WEBHOOK_REPLAY_TABLE_NAME`.
+    pub fn from_env(
+        dynamo_db_client: &aws_sdk_dynamodb::Client,
+    ) -> Result<Option<Self>, ApplicationError> {
+        let integrations = match GetWebhookIntegration::from_env(dynamo_db_client) {
+            Some(integrations) => integrations,
+            None => return Ok(None),
+        };
+        let max_age = match std::env::var("WEBHOOK_MAX_AGE") {
+            Ok(max_age) => Duration::from_secs(max_age.parse().map_err(|_| {
+                ApplicationError::InitError(format!(
+                    "Invalid WEBHOOK_MAX_AGE {}, expected seconds",
+                    max_age
+                ))
+            })?),
+            Err(_) => Duration::from_secs(300),
+        };
+
+        Ok(Some(
+            WebhookVerifier::builder()
+                .integrations(integrations)
+                .max_age(max_age)
+                .replay(ProofReplay::from_table_env(
+                    dynamo_db_client,
+                    "WEBHOOK_REPLAY_TABLE_NAME",
+                ))
+                .build(),
+        ))
+    }
+
+    /// The enabled integration that signed the request, each delivery accepted once.
+    pub async fn verify(
+        &self,
+        request: &DecisionRequest,
+    ) -> Result<Option<WebhookIntegration>, ApplicationError> {
+        let integration_id = match request.header(WEBHOOK_ID_HEADER) {
+            Some(integration_id) => integration_id.trim(),
+            None => return Ok(None),
+        };
+        let integration = match self.integrations.execute(integration_id).await? {
+            Some(integration) if integration.enabled => integration,
+            _ => return Ok(None),
+        };
+
+        match self.check(request, &integration, SystemTime::now()) {
+            Ok(replay_key) => {
+                let first_use = self.replay.first_use(&replay_key, self.max_age * 2).await?;
+                Ok(Some(integration).filter(|_| first_use))
+            }
+            Err(reason) => {
+                println!(
+                    "invalid webhook signature for {}: {}",
+                    integration.pk, reason
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the key that must not be replayed, `webhook#<integration>#<delivery>`.
+    fn check(
+        &self,
+        request: &DecisionRequest,
+        integration: &WebhookIntegration,
+        now: SystemTime,
+    ) -> Result<String, String> {
+        let timestamp = request
+            .header(WEBHOOK_TIMESTAMP_HEADER)
+            .ok_or("missing timestamp")?
+            .trim();
+        let signature = request
+            .header(WEBHOOK_SIGNATURE_HEADER)
+            .ok_or("missing signature")?
+            .trim();
+        let delivery = request
+            .header(WEBHOOK_DELIVERY_HEADER)
+            .map(str::trim)
+            .filter(|delivery| !delivery.is_empty())
+            .ok_or("missing delivery id")?;
+
+        let seconds: u64 = timestamp
+            .parse()
+            .map_err(|_| format!("invalid timestamp {}", timestamp))?;
+        let now = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if now.abs_diff(seconds) > self.max_age.as_secs() {
+            return Err(format!("timestamp {} is not fresh", timestamp));
+        }
+
+        let tag = signature
+            .strip_prefix("v1=")
+            .and_then(decode_hex)
+            .ok_or("expected v1=<hex>")?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, integration.secret.as_bytes());
+        hmac::verify(
+            &key,
+            message(&request.method, &request.path, timestamp, delivery).as_bytes(),
+            &tag,
+        )
+        .map_err(|_| "signature mismatch".to_string())?;
+
+        Ok(format!("webhook#{}#{}", integration.pk, delivery))
+    }
+}
+
+/// The `x-webhook-signature` a sender computes.
+pub fn sign(secret: &str, method: &str, path: &str, timestamp: &str, delivery: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, message(method, path, timestamp, delivery).as_bytes());
+    let hex: String = tag
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    format!("v1={}", hex)
+}
+
+fn message(method: &str, path: &str, timestamp: &str, delivery: &str) -> String {
+    format!(
+        "{}\n{}\n{}\n{}",
+        method.to_uppercase(),
+        path,
+        timestamp,
+        delivery
+    )
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queries::cached_batch_get::CachedBatchGet;
+    use aws_smithy_client::test_connection::TestConnection;
+    use aws_smithy_http::body::SdkBody;
+    use lambda_http::Response;
+    use shared::utils::unit_tests_helper::UnitTestHelper;
+
+    fn get_request(timestamp: &str, delivery: &str, signature: &str) -> DecisionRequest {
+        DecisionRequest::builder()
+            .method("POST")
+            .path("/webhooks/payments")
+            .headers(
+                [
+                    ("X-Webhook-Id", "acme-payments"),
+                    ("X-Webhook-Timestamp", timestamp),
+                    ("X-Webhook-Delivery", delivery),
+                    ("X-Webhook-Signature", signature),
+                ]
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            )
+            .build()
+    }
+
+    async fn get_verifier(conn: &TestConnection<SdkBody>) -> WebhookVerifier {
+        WebhookVerifier::builder()
+            .integrations(
+                GetWebhookIntegration::builder()
+                    .lookup(
+                        CachedBatchGet::builder()
+                            .table_name("webhooks")
+                            .dynamo_db_client(UnitTestHelper::dynamo_fake_client(conn).await)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build()
+    }
+
+    #[tokio::test]
+    async fn accept_a_fresh_signature_once() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let request = UnitTestHelper::dynamodb_request_builder()
+            .header("x-amz-target", "DynamoDB_20120810.BatchGetItem")
+            .body(SdkBody::empty())
+            .unwrap();
+        let response = Response::builder()
+            .status(200)
+            .body(SdkBody::from(
+                r#"{"Responses": {"webhooks": [
+                    {"pk": {"S": "acme-payments"}, "secret": {"S": "shared-secret"}, "scopes": {"SS": ["payments.webhook"]}}
+                ]}}"#,
+            ))
+            .unwrap();
+        let conn = TestConnection::new(vec![(request, response)]);
+        let verifier = get_verifier(&conn).await;
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+        let signature = sign(
+            "shared-secret",
+            "POST",
+            "/webhooks/payments",
+            &timestamp,
+            "delivery-1",
+        );
+        let later = (timestamp.parse::<u64>().unwrap() + 1).to_string();
+        let resigned = sign(
+            "shared-secret",
+            "POST",
+            "/webhooks/payments",
+            &later,
+            "delivery-1",
+        );
+
+        // ACT
+        let accepted = verifier
+            .verify(&get_request(&timestamp, "delivery-1", &signature))
+            .await?;
+        let replayed = verifier
+            .verify(&get_request(&timestamp, "delivery-1", &signature))
+            .await?;
+        let resigned = verifier
+            .verify(&get_request(&later, "delivery-1", &resigned))
+            .await?;
+
+        // ASSERT
+        assert_eq!(accepted.map(|x| x.pk), Some("acme-payments".to_string()));
+        assert_eq!(replayed, None);
+        assert_eq!(resigned, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_a_stale_or_wrong_signature() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let conn = TestConnection::new(vec![]);
+        let verifier = get_verifier(&conn).await;
+        let integration = WebhookIntegration {
+            pk: "acme-payments".to_string(),
+            secret: "shared-secret".to_string(),
+            scopes: vec!["payments.webhook".to_string()],
+            enabled: true,
+        };
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1665000000);
+        let sign_request = |secret: &str, path: &str, timestamp: &str, delivery: &str| {
+            get_request(
+                timestamp,
+                delivery,
+                &sign(secret, "POST", path, timestamp, delivery),
+            )
+        };
+        let valid = sign_request("shared-secret", "/webhooks/payments", "1665000000", "d-1");
+        let mut other_delivery = valid.clone();
+        other_delivery
+            .headers
+            .insert("X-Webhook-Delivery".to_string(), "d-2".to_string());
+        let mut without_delivery = valid.clone();
+        without_delivery.headers.remove("X-Webhook-Delivery");
+
+        // ACT
+        let fresh = verifier.check(&valid, &integration, now);
+        let stale = verifier.check(
+            &sign_request("shared-secret", "/webhooks/payments", "1664000000", "d-1"),
+            &integration,
+            now,
+        );
+        let other_secret = verifier.check(
+            &sign_request("other-secret", "/webhooks/payments", "1665000000", "d-1"),
+            &integration,
+            now,
+        );
+        let other_path = verifier.check(
+            &sign_request("shared-secret", "/webhooks/refunds", "1665000000", "d-1"),
+            &integration,
+            now,
+        );
+        let other_delivery = verifier.check(&other_delivery, &integration, now);
+        let without_delivery = verifier.check(&without_delivery, &integration, now);
+
+        // ASSERT
+        assert_eq!(fresh, Ok("webhook#acme-payments#d-1".to_string()));
+        assert!(stale.is_err());
+        assert!(other_secret.is_err());
+        assert!(other_path.is_err());
+        assert!(other_delivery.is_err());
+        assert!(without_delivery.is_err());
+
+        Ok(())
+    }
+}
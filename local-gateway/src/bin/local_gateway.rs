@@ -0,0 +1,81 @@
+use hyper::{
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    Server,
+};
+use lambda_request_authorizer::utils::injections::jwt_di::JWTAppClient;
+use local_gateway::{
+    gateway::{Api, ApiHandler, LocalGateway},
+    template::{ApiTemplate, BasePathMapping},
+};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+fn read_template(variable: &str, default: &str) -> String {
+    let path = std::env::var(variable).unwrap_or_else(|_| default.to_string());
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tracing_subscriber::fmt()
+        .with_ansi(false)
+        .without_time()
+        .with_max_level(tracing_subscriber::filter::LevelFilter::INFO)
+        .init();
+
+    let stage = std::env::var("STAGE").unwrap_or_else(|_| "test".to_string());
+    let address: SocketAddr = std::env::var("LISTEN_ADDRESS")
+        .unwrap_or_else(|_| "127.0.0.1:3000".to_string())
+        .parse()
+        .expect("LISTEN_ADDRESS must be a socket address");
+
+    let config = aws_config::load_from_env().await;
+    let app_client = JWTAppClient::from_env(&config)
+        .await
+        .expect("the authorizer must be configured");
+
+    let mappings = BasePathMapping::parse(&read_template(
+        "CUSTOM_DOMAIN_TEMPLATE",
+        "custom-domain.yml",
+    ))
+    .expect("CUSTOM_DOMAIN_TEMPLATE must be valid");
+    let apis = mappings
+        .into_iter()
+        .map(|mapping| {
+            let (template, handler): (String, ApiHandler) =
+                match mapping.rest_api_parameter.as_str() {
+                    "ApiOneId" => (
+                        read_template("API_ONE_TEMPLATE", "api-one/template.yml"),
+                        Arc::new(|request| Box::pin(api_one::execute(request))),
+                    ),
+                    "ApiTwoId" => (
+                        read_template("API_TWO_TEMPLATE", "api-two/template.yml"),
+                        Arc::new(|request| Box::pin(api_two::execute(request))),
+                    ),
+                    parameter => panic!("no handler registered for {}", parameter),
+                };
+            Api {
+                id: mapping.rest_api_parameter,
+                base_path: mapping.base_path,
+                template: ApiTemplate::parse(&template).expect("api template must be valid"),
+                handler,
+            }
+        })
+        .collect();
+
+    let gateway = Arc::new(LocalGateway::new(Arc::new(app_client), stage, apis));
+    let make_service = make_service_fn(move |connection: &AddrStream| {
+        let gateway = gateway.clone();
+        let remote_address = connection.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let gateway = gateway.clone();
+                async move { gateway.handle(request, Some(remote_address)).await }
+            }))
+        }
+    });
+
+    println!("local gateway listening on {}", address);
+    Server::bind(&address).serve(make_service).await?;
+    Ok(())
+}
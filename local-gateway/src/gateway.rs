@@ -0,0 +1,602 @@
+use crate::template::{ApiTemplate, RequestAuthorizer, Route};
+use aws_lambda_events::apigw::{
+    ApiGatewayCustomAuthorizerRequestTypeRequest,
+    ApiGatewayCustomAuthorizerRequestTypeRequestContext,
+    ApiGatewayCustomAuthorizerRequestTypeRequestIdentity, ApiGatewayCustomAuthorizerResponse,
+};
+use hyper::{Body, StatusCode};
+use lambda_http::{
+    http::{HeaderMap, Method},
+    Context, Error, RequestExt, Response,
+};
+use lambda_request_authorizer::{handler, utils::injections::jwt_di::JWTInitialisation};
+use lambda_runtime::LambdaEvent;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const ACCOUNT_ID: &str = "123456789012";
+
+pub type ApiHandler = Arc<
+    dyn Fn(
+            lambda_http::Request,
+        ) -> Pin<Box<dyn Future<Output = Result<Response<String>, Error>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// A REST API mounted on the custom domain under its base path.
+pub struct Api {
+    pub id: String,
+    pub base_path: String,
+    pub template: ApiTemplate,
+    pub handler: ApiHandler,
+}
+
+/// Emulates the custom domain and the REST APIs in front of the api handlers:
+/// base path mapping, route matching, REQUEST authorizer with its result cache and policy evaluation.
+pub struct LocalGateway {
+    app_client: Arc<dyn JWTInitialisation>,
+    stage: String,
+    apis: Vec<Api>,
+    cache: Mutex<HashMap<String, (Instant, ApiGatewayCustomAuthorizerResponse)>>,
+}
+
+struct MatchedRoute<'a> {
+    api: &'a Api,
+    route: &'a Route,
+    path_parameters: HashMap<String, String>,
+}
+
+impl LocalGateway {
+    pub fn new(app_client: Arc<dyn JWTInitialisation>, stage: String, apis: Vec<Api>) -> Self {
+        LocalGateway {
+            app_client,
+            stage,
+            apis,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn handle(
+        &self,
+        request: hyper::Request<Body>,
+        remote_address: Option<SocketAddr>,
+    ) -> Result<hyper::Response<Body>, Infallible> {
+        let (parts, body) = request.into_parts();
+        let method = parts.method.to_string();
+        let path = parts.uri.path().to_string();
+        let query: HashMap<String, String> = parts
+            .uri
+            .query()
+            .map(|query| {
+                query
+                    .split('&')
+                    .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+                    .filter(|(key, _)| !key.is_empty())
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let source_ip = remote_address.map(|address| address.ip().to_string());
+
+        let (base_path, api_path) = split_base_path(&path);
+        let api = match self.apis.iter().find(|api| api.base_path == base_path) {
+            Some(api) => api,
+            None => return Ok(error_response(StatusCode::FORBIDDEN, "Forbidden")),
+        };
+        let matched = match Self::match_route(api, &method, &api_path) {
+            Some(matched) => matched,
+            None => {
+                return Ok(error_response(
+                    StatusCode::FORBIDDEN,
+                    "Missing Authentication Token",
+                ))
+            }
+        };
+
+        if let Some(name) = &matched.route.authorizer {
+            // fails closed, the templates are checked when they are parsed
+            let authorizer = match api.template.authorizer(name) {
+                Some(authorizer) => authorizer,
+                None => {
+                    println!("authorizer {} is not a REQUEST authorizer", name);
+                    return Ok(error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Internal server error",
+                    ));
+                }
+            };
+            let payload = self.to_authorizer_request(
+                &matched,
+                &method,
+                &path,
+                &parts.headers,
+                &query,
+                source_ip.clone(),
+            );
+            let response = match self.authorize(authorizer, &matched, payload).await {
+                Ok(Some(response)) => response,
+                Ok(None) => return Ok(error_response(StatusCode::UNAUTHORIZED, "Unauthorized")),
+                Err(e) => {
+                    println!("authorizer error {:?}", e);
+                    return Ok(error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Internal server error",
+                    ));
+                }
+            };
+            if !is_allowed(&response, &self.method_arn(&matched, &method)) {
+                return Ok(error_response(
+                    StatusCode::FORBIDDEN,
+                    "User is not authorized to access this resource with an explicit deny",
+                ));
+            }
+        }
+
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(body) => body,
+            Err(_) => return Ok(error_response(StatusCode::BAD_REQUEST, "Bad request")),
+        };
+        let body = match String::from_utf8(body.to_vec()) {
+            Ok(text) if text.is_empty() => lambda_http::Body::Empty,
+            Ok(text) => lambda_http::Body::Text(text),
+            Err(e) => lambda_http::Body::Binary(e.into_bytes()),
+        };
+
+        let mut request = lambda_http::Request::new(body);
+        *request.method_mut() = parts.method.clone();
+        *request.uri_mut() = parts.uri.clone();
+        *request.headers_mut() = parts.headers.clone();
+        let request = request
+            .with_query_string_parameters(query)
+            .with_path_parameters(matched.path_parameters.clone())
+            .with_lambda_context(Context::default());
+
+        match (api.handler)(request).await {
+            Ok(response) => {
+                let (parts, body) = response.into_parts();
+                Ok(hyper::Response::from_parts(parts, Body::from(body)))
+            }
+            Err(e) => {
+                println!("handler error {:?}", e);
+                Ok(error_response(
+                    StatusCode::BAD_GATEWAY,
+                    "Internal server error",
+                ))
+            }
+        }
+    }
+
+    fn match_route<'a>(api: &'a Api, method: &str, path: &str) -> Option<MatchedRoute<'a>> {
+        api.template
+            .routes
+            .iter()
+            .filter(|route| route.method == method || route.method == "ANY")
+            .find_map(|route| {
+                match_path(&route.path, path).map(|path_parameters| MatchedRoute {
+                    api,
+                    route,
+                    path_parameters,
+                })
+            })
+    }
+
+    fn method_arn(&self, matched: &MatchedRoute, method: &str) -> String {
+        format!(
+            "arn:aws:execute-api:local:{}:{}/{}/{}{}",
+            ACCOUNT_ID, matched.api.id, self.stage, method, matched.route.path
+        )
+    }
+
+    fn to_authorizer_request(
+        &self,
+        matched: &MatchedRoute,
+        method: &str,
+        path: &str,
+        headers: &HeaderMap,
+        query: &HashMap<String, String>,
+        source_ip: Option<String>,
+    ) -> ApiGatewayCustomAuthorizerRequestTypeRequest {
+        let http_method = Method::from_bytes(method.as_bytes()).ok();
+        ApiGatewayCustomAuthorizerRequestTypeRequest {
+            type_: Some("REQUEST".to_string()),
+            method_arn: Some(self.method_arn(matched, method)),
+            resource: Some(matched.route.path.clone()),
+            path: Some(path.to_string()),
+            http_method: http_method.clone(),
+            headers: headers.clone(),
+            query_string_parameters: query.clone().into(),
+            path_parameters: matched.path_parameters.clone(),
+            request_context: ApiGatewayCustomAuthorizerRequestTypeRequestContext {
+                path: Some(path.to_string()),
+                account_id: Some(ACCOUNT_ID.to_string()),
+                resource_id: Some(matched.route.path.clone()),
+                stage: Some(self.stage.clone()),
+                identity: Some(ApiGatewayCustomAuthorizerRequestTypeRequestIdentity {
+                    source_ip,
+                    ..Default::default()
+                }),
+                resource_path: Some(matched.route.path.clone()),
+                http_method,
+                apiid: Some(matched.api.id.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Returns `None` when an identity source is missing, like API Gateway does before invoking the authorizer.
+    async fn authorize(
+        &self,
+        authorizer: &RequestAuthorizer,
+        matched: &MatchedRoute<'_>,
+        payload: ApiGatewayCustomAuthorizerRequestTypeRequest,
+    ) -> Result<Option<ApiGatewayCustomAuthorizerResponse>, Error> {
+        let cache_key = match Self::cache_key(authorizer, matched, &payload) {
+            Some(cache_key) => cache_key,
+            None => return Ok(None),
+        };
+        let ttl = Duration::from_secs(authorizer.reauthorize_every);
+        if let Some((cached_at, response)) = self.cache.lock().unwrap().get(&cache_key) {
+            if cached_at.elapsed() < ttl {
+                return Ok(Some(response.clone()));
+            }
+        }
+
+        let response = handler::execute(
+            self.app_client.as_ref(),
+            LambdaEvent::new(payload.into(), Context::default()),
+        )
+        .await?;
+        if !ttl.is_zero() {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(cache_key, (Instant::now(), response.clone()));
+        }
+
+        Ok(Some(response))
+    }
+
+    fn cache_key(
+        authorizer: &RequestAuthorizer,
+        matched: &MatchedRoute,
+        payload: &ApiGatewayCustomAuthorizerRequestTypeRequest,
+    ) -> Option<String> {
+        let mut values = vec![matched.api.id.clone(), authorizer.name.clone()];
+        for header in &authorizer.headers {
+            let value = payload.headers.get(header.as_str())?.to_str().ok()?;
+            values.push(value.to_string());
+        }
+        for query_string in &authorizer.query_strings {
+            values.push(
+                payload
+                    .query_string_parameters
+                    .first(query_string)?
+                    .to_string(),
+            );
+        }
+        for context in &authorizer.context {
+            let value = match context.as_str() {
+                "httpMethod" => payload.http_method.as_ref().map(Method::to_string),
+                "resourceId" | "resourcePath" => matched.route.path.clone().into(),
+                "stage" => payload.request_context.stage.clone(),
+                _ => None,
+            }?;
+            values.push(value);
+        }
+
+        Some(values.join("|"))
+    }
+}
+
+fn split_base_path(path: &str) -> (String, String) {
+    let path = path.trim_start_matches('/');
+    match path.split_once('/') {
+        Some((base_path, rest)) => (base_path.to_string(), format!("/{}", rest)),
+        None => (path.to_string(), "/".to_string()),
+    }
+}
+
+/// Matches `/items/{id}` and `/{proxy+}` resources, returning the path parameters.
+fn match_path(resource: &str, path: &str) -> Option<HashMap<String, String>> {
+    let resource_segments: Vec<&str> = resource.split('/').filter(|x| !x.is_empty()).collect();
+    let path_segments: Vec<&str> = path.split('/').filter(|x| !x.is_empty()).collect();
+    let mut path_parameters = HashMap::new();
+
+    for (index, segment) in resource_segments.iter().enumerate() {
+        if let Some(name) = segment.strip_prefix('{').and_then(|x| x.strip_suffix("+}")) {
+            if index >= path_segments.len() {
+                return None;
+            }
+            path_parameters.insert(name.to_string(), path_segments[index..].join("/"));
+            return Some(path_parameters);
+        }
+        let value = path_segments.get(index)?;
+        match segment.strip_prefix('{').and_then(|x| x.strip_suffix('}')) {
+            Some(name) => {
+                path_parameters.insert(name.to_string(), value.to_string());
+            }
+            None if segment != value => return None,
+            None => {}
+        }
+    }
+
+    (resource_segments.len() == path_segments.len()).then_some(path_parameters)
+}
+
+/// An explicit DENY wins over any ALLOW, and resources can use the `*` and `?` wildcards.
+fn is_allowed(response: &ApiGatewayCustomAuthorizerResponse, method_arn: &str) -> bool {
+    let matches = |effect: &str| {
+        response.policy_document.statement.iter().any(|statement| {
+            statement
+                .effect
+                .as_deref()
+                .is_some_and(|x| x.eq_ignore_ascii_case(effect))
+                && statement
+                    .resource
+                    .iter()
+                    .any(|resource| wildcard_match(resource, method_arn))
+        })
+    };
+
+    !matches("deny") && matches("allow")
+}
+
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let (mut star, mut star_v) = (None, 0);
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some(p);
+            star_v = v;
+            p += 1;
+        } else if let Some(star) = star {
+            p = star + 1;
+            star_v += 1;
+            v = star_v;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+fn error_response(status_code: StatusCode, message: &str) -> hyper::Response<Body> {
+    let response = shared::utils::api_helper::ApiHelper::response(
+        status_code,
+        json!({ "message": message }).to_string(),
+        "application/json".to_string(),
+    );
+    let (parts, body) = response.into_parts();
+    hyper::Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use lambda_request_authorizer::utils::{
+        authorizer::{Authorizer, Claims},
+        route_rule::RoutePolicy,
+    };
+    use mockall::mock;
+    use shared::{error::ApplicationError, utils::api_helper::ApiHelper};
+
+    mock! {
+        pub JWTAppClient {}
+        #[async_trait]
+        impl JWTInitialisation for JWTAppClient {
+            async fn validate_token(&self, raw_token: String) -> Result<Option<Claims>, ApplicationError>;
+            fn to_response(
+                &self,
+                effect: String,
+                principal: Option<String>,
+                method_arn: String,
+            ) -> ApiGatewayCustomAuthorizerResponse;
+            async fn get_scope_query(
+                &self,
+                method: &str,
+                path: &str,
+            ) -> Result<Option<RoutePolicy>, ApplicationError>;
+        }
+    }
+
+    fn get_gateway(mock: MockJWTAppClient) -> Result<LocalGateway, ApplicationError> {
+        let handler: ApiHandler = Arc::new(|_| {
+            Box::pin(async {
+                Ok(ApiHelper::response(
+                    StatusCode::OK,
+                    json!({ "message": "authorized" }).to_string(),
+                    "application/json".to_string(),
+                ))
+            })
+        });
+        let api = Api {
+            id: "ApiOneId".to_string(),
+            base_path: "one".to_string(),
+            template: ApiTemplate::parse(include_str!("../../api-one/template.yml"))?,
+            handler,
+        };
+
+        Ok(LocalGateway::new(
+            Arc::new(mock),
+            "test".to_string(),
+            vec![api],
+        ))
+    }
+
+    fn get_mock(scope: &'static str, times: usize) -> MockJWTAppClient {
+        let mut mock = MockJWTAppClient::new();
+        mock.expect_validate_token()
+            .withf(|token| token == "token")
+            .times(times)
+            .returning(|_| {
+                let data = r#"
+                {
+          "exp": 1654242297,
+          "aud": "my-audience",
+          "sub": "12408bde-207d-45a5-a143-6aa02f049df7",
+          "scope": "my-audience.read",
+          "email": "a@a.com"
+        }"#;
+                Ok(Some(serde_json::from_str(data)?))
+            });
+        mock.expect_get_scope_query()
+            .withf(|method, path| method == "GET" && path == "/one/")
+            .times(times)
+            .returning(move |_, _| Ok(Some(vec![scope.to_string()].into())));
+        mock.expect_to_response()
+            .withf(|_, _, method_arn| {
+                method_arn == "arn:aws:execute-api:local:123456789012:ApiOneId/test/GET/"
+            })
+            .times(times)
+            .returning(|effect, principal, method_arn| {
+                Authorizer::default().to_response(effect, principal, method_arn)
+            });
+        mock
+    }
+
+    fn get_request(path: &str, authorization: Option<&str>) -> hyper::Request<Body> {
+        let mut request = hyper::Request::builder().method("GET").uri(path);
+        if let Some(authorization) = authorization {
+            request = request.header("Authorization", authorization);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn call_the_api_and_cache_the_authorizer_response() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let gateway = get_gateway(get_mock("my-audience.read", 1))?;
+
+        // ACT
+        let first = gateway
+            .handle(get_request("/one/", Some("Bearer token")), None)
+            .await
+            .unwrap();
+        let second = gateway
+            .handle(get_request("/one", Some("Bearer token")), None)
+            .await
+            .unwrap();
+
+        // ASSERT
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(first.into_body()).await.unwrap();
+        assert_eq!(body, r#"{"message":"authorized"}"#);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_forbidden_when_the_policy_denies() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let gateway = get_gateway(get_mock("my-audience.write", 1))?;
+
+        // ACT
+        let response = gateway
+            .handle(get_request("/one/", Some("Bearer token")), None)
+            .await
+            .unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_unauthorized_when_the_identity_source_is_missing(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let gateway = get_gateway(get_mock("my-audience.read", 0))?;
+
+        // ACT
+        let response = gateway
+            .handle(get_request("/one/", None), None)
+            .await
+            .unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_forbidden_for_unknown_routes_and_base_paths() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let gateway = get_gateway(get_mock("my-audience.read", 0))?;
+
+        // ACT
+        let unknown_route = gateway
+            .handle(get_request("/one/list", Some("Bearer token")), None)
+            .await
+            .unwrap();
+        let unknown_base_path = gateway
+            .handle(get_request("/three/", Some("Bearer token")), None)
+            .await
+            .unwrap();
+
+        // ASSERT
+        assert_eq!(unknown_route.status(), StatusCode::FORBIDDEN);
+        let body = hyper::body::to_bytes(unknown_route.into_body())
+            .await
+            .unwrap();
+        assert_eq!(body, r#"{"message":"Missing Authentication Token"}"#);
+        assert_eq!(unknown_base_path.status(), StatusCode::FORBIDDEN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_internal_server_error_when_the_route_authorizer_is_unknown(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let mut gateway = get_gateway(get_mock("my-audience.read", 0))?;
+        gateway.apis[0].template.routes[0].authorizer = Some("cognito".to_string());
+
+        // ACT
+        let response = gateway
+            .handle(get_request("/one/", Some("Bearer token")), None)
+            .await
+            .unwrap();
+
+        // ASSERT
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        Ok(())
+    }
+
+    #[test]
+    fn match_path_parameters() {
+        assert_eq!(
+            match_path("/items/{id}", "/items/1"),
+            Some(HashMap::from([("id".to_string(), "1".to_string())]))
+        );
+        assert_eq!(
+            match_path("/{proxy+}", "/a/b"),
+            Some(HashMap::from([("proxy".to_string(), "a/b".to_string())]))
+        );
+        assert_eq!(match_path("/items/{id}", "/items"), None);
+        assert!(wildcard_match(
+            "arn:aws:execute-api:local:*:ApiOneId/test/GET/*",
+            "arn:aws:execute-api:local:123456789012:ApiOneId/test/GET/"
+        ));
+    }
+}
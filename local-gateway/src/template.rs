@@ -0,0 +1,323 @@
+use serde_yaml::Value;
+use shared::error::ApplicationError;
+
+/// API Gateway caches the authorizer result for 300 seconds when `ReauthorizeEvery` is not set.
+const DEFAULT_REAUTHORIZE_EVERY: u64 = 300;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub path: String,
+    pub method: String,
+    pub authorizer: Option<String>,
+}
+
+/// A REQUEST authorizer and the identity sources API Gateway uses as its cache key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestAuthorizer {
+    pub name: String,
+    pub headers: Vec<String>,
+    pub query_strings: Vec<String>,
+    pub context: Vec<String>,
+    pub reauthorize_every: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApiTemplate {
+    pub routes: Vec<Route>,
+    pub authorizers: Vec<RequestAuthorizer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasePathMapping {
+    pub base_path: String,
+    /// The template parameter holding the RestApiId, like `ApiOneId`.
+    pub rest_api_parameter: String,
+}
+
+impl ApiTemplate {
+    /// Reads the `AWS::Serverless::Api` authorizers and the `Api` events of the `AWS::Serverless::Function`s of a SAM template.
+    pub fn parse(yaml: &str) -> Result<Self, ApplicationError> {
+        let template: Value = serde_yaml::from_str(yaml)
+            .map_err(|e| ApplicationError::InitError(format!("Invalid template {}", e)))?;
+        let resources = resources(&template);
+
+        let mut authorizers = Vec::new();
+        let mut default_authorizer = None;
+        for api in resources_of_type(&resources, "AWS::Serverless::Api") {
+            let auth = &api["Properties"]["Auth"];
+            default_authorizer = auth["DefaultAuthorizer"].as_str().map(str::to_string);
+            if let Some(configurations) = auth["Authorizers"].as_mapping() {
+                for (name, configuration) in configurations {
+                    if configuration["FunctionPayloadType"].as_str() != Some("REQUEST") {
+                        continue;
+                    }
+                    let identity = &configuration["Identity"];
+                    authorizers.push(RequestAuthorizer {
+                        name: name.as_str().unwrap_or_default().to_string(),
+                        headers: strings(&identity["Headers"]),
+                        query_strings: strings(&identity["QueryStrings"]),
+                        context: strings(&identity["Context"]),
+                        reauthorize_every: identity["ReauthorizeEvery"]
+                            .as_u64()
+                            .unwrap_or(DEFAULT_REAUTHORIZE_EVERY),
+                    });
+                }
+            }
+        }
+
+        let mut routes = Vec::new();
+        for function in resources_of_type(&resources, "AWS::Serverless::Function") {
+            if let Some(events) = function["Properties"]["Events"].as_mapping() {
+                for event in events.values() {
+                    if event["Type"].as_str() != Some("Api") {
+                        continue;
+                    }
+                    let properties = &event["Properties"];
+                    let authorizer = properties["Auth"]["Authorizer"]
+                        .as_str()
+                        .map(str::to_string)
+                        .or_else(|| default_authorizer.clone())
+                        .filter(|authorizer| authorizer != "NONE");
+                    routes.push(Route {
+                        path: properties["Path"].as_str().unwrap_or("/").to_string(),
+                        method: properties["Method"]
+                            .as_str()
+                            .unwrap_or("ANY")
+                            .to_uppercase(),
+                        authorizer,
+                    });
+                }
+            }
+        }
+
+        // a route served without its authorizer would be open, TOKEN and COGNITO authorizers are not emulated
+        if let Some(route) = routes.iter().find(|route| {
+            route.authorizer.as_ref().is_some_and(|name| {
+                !authorizers
+                    .iter()
+                    .any(|authorizer| &authorizer.name == name)
+            })
+        }) {
+            return Err(ApplicationError::InitError(format!(
+                "The route {} {} uses {:?}, which is not a REQUEST authorizer of the template",
+                route.method, route.path, route.authorizer
+            )));
+        }
+
+        Ok(ApiTemplate {
+            routes,
+            authorizers,
+        })
+    }
+
+    pub fn authorizer(&self, name: &str) -> Option<&RequestAuthorizer> {
+        self.authorizers.iter().find(|x| x.name == name)
+    }
+}
+
+impl BasePathMapping {
+    /// Reads the `AWS::ApiGateway::BasePathMapping`s of the custom domain template.
+    pub fn parse(yaml: &str) -> Result<Vec<Self>, ApplicationError> {
+        let template: Value = serde_yaml::from_str(yaml)
+            .map_err(|e| ApplicationError::InitError(format!("Invalid template {}", e)))?;
+        let resources = resources(&template);
+
+        Ok(
+            resources_of_type(&resources, "AWS::ApiGateway::BasePathMapping")
+                .filter_map(|mapping| {
+                    let properties = &mapping["Properties"];
+                    Some(BasePathMapping {
+                        base_path: properties["BasePath"].as_str()?.to_string(),
+                        rest_api_parameter: reference(&properties["RestApiId"])?,
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+fn resources(template: &Value) -> Vec<Value> {
+    template["Resources"]
+        .as_mapping()
+        .map(|resources| resources.values().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn resources_of_type<'a>(
+    resources: &'a [Value],
+    resource_type: &'a str,
+) -> impl Iterator<Item = &'a Value> {
+    resources
+        .iter()
+        .filter(move |resource| resource["Type"].as_str() == Some(resource_type))
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_sequence()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads `!Ref Name` and `{ "Ref": "Name" }`.
+fn reference(value: &Value) -> Option<String> {
+    match value {
+        Value::Tagged(tagged) if tagged.tag == "!Ref" => tagged.value.as_str().map(str::to_string),
+        _ => value["Ref"].as_str().map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_the_api_template() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let yaml = include_str!("../../api-one/template.yml");
+
+        // ACT
+        let template = ApiTemplate::parse(yaml)?;
+
+        // ASSERT
+        assert_eq!(
+            template.routes,
+            vec![Route {
+                path: "/".to_string(),
+                method: "GET".to_string(),
+                authorizer: Some("jwt".to_string()),
+            }]
+        );
+        assert_eq!(
+            template.authorizer("jwt"),
+            Some(&RequestAuthorizer {
+                name: "jwt".to_string(),
+                headers: vec!["Authorization".to_string()],
+                query_strings: vec![],
+                context: vec!["httpMethod".to_string(), "resourceId".to_string()],
+                reauthorize_every: 300,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_the_route_without_authorizer() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let yaml = r#"
+Resources:
+  MyApi:
+    Type: AWS::Serverless::Api
+    Properties:
+      Auth:
+        DefaultAuthorizer: jwt
+        Authorizers:
+          jwt:
+            FunctionPayloadType: REQUEST
+            Identity:
+              Headers:
+                - Authorization
+              ReauthorizeEvery: 0
+  HealthFunction:
+    Type: AWS::Serverless::Function
+    Properties:
+      Events:
+        ApiEvents:
+          Type: Api
+          Properties:
+            Path: /health
+            Method: get
+            Auth:
+              Authorizer: NONE
+"#;
+
+        // ACT
+        let template = ApiTemplate::parse(yaml)?;
+
+        // ASSERT
+        assert_eq!(template.routes[0].authorizer, None);
+        assert_eq!(template.routes[0].method, "GET");
+        assert_eq!(template.authorizer("jwt").unwrap().reauthorize_every, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_when_a_route_authorizer_is_not_a_request_authorizer(
+    ) -> Result<(), ApplicationError> {
+        // ARRANGE
+        let yaml = |authorizer: &str| {
+            format!(
+                r#"
+Resources:
+  MyApi:
+    Type: AWS::Serverless::Api
+    Properties:
+      Auth:
+        Authorizers:
+          jwt:
+            FunctionPayloadType: REQUEST
+          cognito:
+            UserPoolArn: arn:aws:cognito-idp:eu-central-1:123456789012:userpool/pool
+  OneFunction:
+    Type: AWS::Serverless::Function
+    Properties:
+      Events:
+        ApiEvents:
+          Type: Api
+          Properties:
+            Path: /
+            Method: get
+            Auth:
+              Authorizer: {}
+"#,
+                authorizer
+            )
+        };
+
+        // ACT
+        let cognito = ApiTemplate::parse(&yaml("cognito"));
+        let missing = ApiTemplate::parse(&yaml("missing"));
+        let request = ApiTemplate::parse(&yaml("jwt"));
+
+        // ASSERT
+        assert!(cognito.is_err());
+        assert!(missing.is_err());
+        assert!(request.is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_the_custom_domain_base_path_mappings() -> Result<(), ApplicationError> {
+        // ARRANGE
+        let yaml = include_str!("../../custom-domain.yml");
+
+        // ACT
+        let mut mappings = BasePathMapping::parse(yaml)?;
+        mappings.sort_by(|a, b| a.base_path.cmp(&b.base_path));
+
+        // ASSERT
+        assert_eq!(
+            mappings,
+            vec![
+                BasePathMapping {
+                    base_path: "one".to_string(),
+                    rest_api_parameter: "ApiOneId".to_string(),
+                },
+                BasePathMapping {
+                    base_path: "two".to_string(),
+                    rest_api_parameter: "ApiTwoId".to_string(),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+}
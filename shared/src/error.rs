@@ -38,6 +38,12 @@ impl From<&aws_sdk_dynamodb::model::AttributeValue> for ApplicationError {
     }
 }
 
+impl From<crate::utils::dynamodb::Error> for ApplicationError {
+    fn from(value: crate::utils::dynamodb::Error) -> ApplicationError {
+        ApplicationError::InternalError(format!("Invalid item {}", value))
+    }
+}
+
 impl<E> From<SdkError<E>> for ApplicationError
 where
     E: error::Error,
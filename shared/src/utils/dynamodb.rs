@@ -1,11 +1,35 @@
-use aws_sdk_dynamodb::model::AttributeValue;
-use std::collections::HashMap;
+use aws_sdk_dynamodb::{model::AttributeValue, types::Blob};
+use aws_smithy_types::date_time::{DateTime, Format};
+use serde::{
+    de::{
+        self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+        SeqAccess, VariantAccess, Visitor,
+    },
+    ser::{self, Serialize, Serializer},
+    Deserialize, Deserializer,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    time::SystemTime,
+};
+
+const STRING_SET: &str = "$dynamodb::StringSet";
+const NUMBER_SET: &str = "$dynamodb::NumberSet";
+const BINARY_SET: &str = "$dynamodb::BinarySet";
 
 pub trait AttributeValuesExt {
     fn get_string(&self, key: &str) -> Option<String>;
     fn get_number(&self, key: &str) -> Option<u64>;
     fn get_bool(&self, key: &str) -> Option<bool>;
     fn get_array_string(&self, key: &str) -> Option<Vec<String>>;
+    fn get_i64(&self, key: &str) -> Option<i64>;
+    fn get_f64(&self, key: &str) -> Option<f64>;
+    fn get_map(&self, key: &str) -> Option<HashMap<String, AttributeValue>>;
+    fn get_string_set(&self, key: &str) -> Option<BTreeSet<String>>;
+    fn get_number_set(&self, key: &str) -> Option<Vec<f64>>;
+    fn get_binary(&self, key: &str) -> Option<Vec<u8>>;
+    fn get_timestamp(&self, key: &str) -> Option<SystemTime>;
 }
 
 impl AttributeValuesExt for HashMap<String, AttributeValue> {
@@ -31,6 +55,937 @@ impl AttributeValuesExt for HashMap<String, AttributeValue> {
     fn get_bool(&self, key: &str) -> Option<bool> {
         Some(self.get(key)?.as_bool().ok()?.to_owned())
     }
+
+    fn get_i64(&self, key: &str) -> Option<i64> {
+        self.get(key)?.as_n().ok()?.parse().ok()
+    }
+
+    fn get_f64(&self, key: &str) -> Option<f64> {
+        self.get(key)?.as_n().ok()?.parse().ok()
+    }
+
+    fn get_map(&self, key: &str) -> Option<HashMap<String, AttributeValue>> {
+        Some(self.get(key)?.as_m().ok()?.to_owned())
+    }
+
+    fn get_string_set(&self, key: &str) -> Option<BTreeSet<String>> {
+        Some(self.get(key)?.as_ss().ok()?.iter().cloned().collect())
+    }
+
+    fn get_number_set(&self, key: &str) -> Option<Vec<f64>> {
+        self.get(key)?
+            .as_ns()
+            .ok()?
+            .iter()
+            .map(|number| number.parse().ok())
+            .collect()
+    }
+
+    fn get_binary(&self, key: &str) -> Option<Vec<u8>> {
+        Some(self.get(key)?.as_b().ok()?.as_ref().to_vec())
+    }
+
+    /// Reads epoch seconds (`N`) or an RFC 3339 date time (`S`).
+    fn get_timestamp(&self, key: &str) -> Option<SystemTime> {
+        let date_time = match self.get(key)? {
+            AttributeValue::N(value) => DateTime::from_secs_f64(value.parse().ok()?),
+            AttributeValue::S(value) => DateTime::from_str(value, Format::DateTime).ok()?,
+            _ => return None,
+        };
+        SystemTime::try_from(date_time).ok()
+    }
+}
+
+/// Deserializes an item into a typed struct, reporting the path of the invalid attribute like `rules[1].scopes`.
+pub fn from_item<T: DeserializeOwned>(item: &HashMap<String, AttributeValue>) -> Result<T, Error> {
+    T::deserialize(ItemDeserializer(item))
+}
+
+pub fn from_attribute_value<T: DeserializeOwned>(value: &AttributeValue) -> Result<T, Error> {
+    T::deserialize(AttributeValueDeserializer(value))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    path: Vec<String>,
+    message: String,
+    absent: bool,
+}
+
+impl Error {
+    fn custom_at(segment: String, message: String) -> Self {
+        Error {
+            path: vec![segment],
+            message,
+            absent: false,
+        }
+    }
+
+    /// Raised by `None` so that the enclosing map leaves the attribute out.
+    fn absent() -> Self {
+        Error {
+            path: Vec::new(),
+            message: "a none value has no attribute".to_string(),
+            absent: true,
+        }
+    }
+
+    fn at(mut self, segment: String) -> Self {
+        self.path.insert(0, segment);
+        self
+    }
+
+    pub fn path(&self) -> String {
+        self.path
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                if index == 0 || segment.starts_with('[') {
+                    segment.clone()
+                } else {
+                    format!(".{}", segment)
+                }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error {
+            path: Vec::new(),
+            message: msg.to_string(),
+            absent: false,
+        }
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error {
+            path: Vec::new(),
+            message: msg.to_string(),
+            absent: false,
+        }
+    }
+}
+
+struct ItemDeserializer<'a>(&'a HashMap<String, AttributeValue>);
+
+impl<'de, 'a> de::Deserializer<'de> for ItemDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(ItemMapAccess {
+            entries: self.0.iter(),
+            value: None,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct ItemMapAccess<'a, I: Iterator<Item = (&'a String, &'a AttributeValue)>> {
+    entries: I,
+    value: Option<(&'a String, &'a AttributeValue)>,
+}
+
+impl<'de, 'a, I> MapAccess<'de> for ItemMapAccess<'a, I>
+where
+    I: Iterator<Item = (&'a String, &'a AttributeValue)>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some((key, value));
+                seed.deserialize(key.as_str().into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value requested before key"))?;
+        seed.deserialize(AttributeValueDeserializer(value))
+            .map_err(|e| e.at(key.clone()))
+    }
+}
+
+struct AttributeValueDeserializer<'a>(&'a AttributeValue);
+
+impl<'a> AttributeValueDeserializer<'a> {
+    fn type_name(&self) -> &'static str {
+        match self.0 {
+            AttributeValue::B(_) => "binary (B)",
+            AttributeValue::Bool(_) => "boolean (BOOL)",
+            AttributeValue::Bs(_) => "binary set (BS)",
+            AttributeValue::L(_) => "list (L)",
+            AttributeValue::M(_) => "map (M)",
+            AttributeValue::N(_) => "number (N)",
+            AttributeValue::Ns(_) => "number set (NS)",
+            AttributeValue::Null(_) => "null (NULL)",
+            AttributeValue::S(_) => "string (S)",
+            AttributeValue::Ss(_) => "string set (SS)",
+            _ => "unknown attribute value",
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        de::Error::custom(format!("expected {}, found {}", expected, self.type_name()))
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for AttributeValueDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            AttributeValue::S(value) => visitor.visit_str(value),
+            AttributeValue::N(value) => visit_number(value, visitor),
+            AttributeValue::Bool(value) => visitor.visit_bool(*value),
+            AttributeValue::Null(_) => visitor.visit_unit(),
+            AttributeValue::B(value) => visitor.visit_bytes(value.as_ref()),
+            AttributeValue::L(values) => visitor.visit_seq(ListAccess {
+                values: values.iter().map(Element::Value).enumerate(),
+            }),
+            AttributeValue::Ss(values) => visitor.visit_seq(ListAccess {
+                values: values.iter().map(Element::String).enumerate(),
+            }),
+            AttributeValue::Ns(values) => visitor.visit_seq(ListAccess {
+                values: values.iter().map(Element::Number).enumerate(),
+            }),
+            AttributeValue::Bs(values) => visitor.visit_seq(ListAccess {
+                values: values
+                    .iter()
+                    .map(|x| Element::Bytes(x.as_ref()))
+                    .enumerate(),
+            }),
+            AttributeValue::M(values) => visitor.visit_map(ItemMapAccess {
+                entries: values.iter(),
+                value: None,
+            }),
+            _ => Err(self.unexpected("a known attribute value")),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            AttributeValue::Null(_) => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            AttributeValue::S(value) => visitor.visit_enum(value.as_str().into_deserializer()),
+            AttributeValue::M(values) if values.len() == 1 => {
+                let (variant, value) = values.iter().next().unwrap();
+                visitor.visit_enum(MapEnumAccess { variant, value })
+            }
+            _ => Err(self.unexpected("a string (S) or a map (M) with a single key")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+fn visit_number<'de, V: Visitor<'de>>(value: &str, visitor: V) -> Result<V::Value, Error> {
+    if let Ok(number) = value.parse::<u64>() {
+        visitor.visit_u64(number)
+    } else if let Ok(number) = value.parse::<i64>() {
+        visitor.visit_i64(number)
+    } else if let Ok(number) = value.parse::<f64>() {
+        visitor.visit_f64(number)
+    } else {
+        Err(de::Error::custom(format!("invalid number {}", value)))
+    }
+}
+
+enum Element<'a> {
+    Value(&'a AttributeValue),
+    String(&'a String),
+    Number(&'a String),
+    Bytes(&'a [u8]),
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Element<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Element::Value(value) => AttributeValueDeserializer(value).deserialize_any(visitor),
+            Element::String(value) => visitor.visit_str(value),
+            Element::Number(value) => visit_number(value, visitor),
+            Element::Bytes(value) => visitor.visit_bytes(value),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Element::Value(value) => AttributeValueDeserializer(value).deserialize_option(visitor),
+            element => visitor.visit_some(element),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Element::Value(value) => {
+                AttributeValueDeserializer(value).deserialize_enum(name, variants, visitor)
+            }
+            Element::String(value) => visitor.visit_enum(value.as_str().into_deserializer()),
+            _ => Err(de::Error::custom("expected a string")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct ListAccess<I> {
+    values: I,
+}
+
+impl<'de, 'a, I> SeqAccess<'de> for ListAccess<I>
+where
+    I: Iterator<Item = (usize, Element<'a>)>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.values.next() {
+            Some((index, element)) => seed
+                .deserialize(element)
+                .map(Some)
+                .map_err(|e| e.at(format!("[{}]", index))),
+            None => Ok(None),
+        }
+    }
+}
+
+struct MapEnumAccess<'a> {
+    variant: &'a String,
+    value: &'a AttributeValue,
+}
+
+impl<'de, 'a> EnumAccess<'de> for MapEnumAccess<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(self.variant.as_str().into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for MapEnumAccess<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(AttributeValueDeserializer(self.value))
+            .map_err(|e| e.at(self.variant.clone()))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(AttributeValueDeserializer(self.value), visitor)
+            .map_err(|e| e.at(self.variant.clone()))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(AttributeValueDeserializer(self.value), visitor)
+            .map_err(|e| e.at(self.variant.clone()))
+    }
+}
+
+/// Serializes a struct or a map into an item.
+pub fn to_item<T: Serialize + ?Sized>(value: &T) -> Result<HashMap<String, AttributeValue>, Error> {
+    match to_attribute_value(value)? {
+        AttributeValue::M(item) => Ok(item),
+        _ => Err(ser::Error::custom("an item must serialize to a map")),
+    }
+}
+
+pub fn to_attribute_value<T: Serialize + ?Sized>(value: &T) -> Result<AttributeValue, Error> {
+    or_null(value.serialize(AttributeValueSerializer))
+}
+
+/// Outside of a map there is no attribute to leave out, so `None` is written as `NULL`.
+fn or_null(value: Result<AttributeValue, Error>) -> Result<AttributeValue, Error> {
+    match value {
+        Err(e) if e.absent => Ok(AttributeValue::Null(true)),
+        value => value,
+    }
+}
+
+/// Written as a string set (`SS`) instead of a list.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct StringSet(pub BTreeSet<String>);
+
+impl Serialize for StringSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(STRING_SET, &self.0)
+    }
+}
+
+/// Written as a number set (`NS`) instead of a list.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct NumberSet<T>(pub Vec<T>);
+
+impl<T: Serialize> Serialize for NumberSet<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(NUMBER_SET, &self.0)
+    }
+}
+
+/// Written as a binary set (`BS`) instead of a list.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct BinarySet(pub Vec<Binary>);
+
+impl Serialize for BinarySet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(BINARY_SET, &self.0)
+    }
+}
+
+/// Written as binary (`B`) instead of a list of numbers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Binary(pub Vec<u8>);
+
+impl Serialize for Binary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Binary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BinaryVisitor;
+
+        impl<'de> Visitor<'de> for BinaryVisitor {
+            type Value = Binary;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "binary")
+            }
+
+            fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Binary, E> {
+                Ok(Binary(value.to_vec()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Binary, A::Error> {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(Binary(bytes))
+            }
+        }
+
+        deserializer.deserialize_bytes(BinaryVisitor)
+    }
+}
+
+/// Written as whole epoch seconds (`N`), the format of the DynamoDB TTL, so the sub-second part is
+/// dropped; fractional seconds and RFC 3339 strings are read too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub SystemTime);
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(DateTime::from(self.0).secs())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TimestampVisitor;
+
+        impl TimestampVisitor {
+            fn to_timestamp<E: de::Error>(date_time: DateTime) -> Result<Timestamp, E> {
+                SystemTime::try_from(date_time)
+                    .map(Timestamp)
+                    .map_err(|e| E::custom(format!("invalid timestamp {}", e)))
+            }
+        }
+
+        impl<'de> Visitor<'de> for TimestampVisitor {
+            type Value = Timestamp;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "epoch seconds or an RFC 3339 date time")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Timestamp, E> {
+                Self::to_timestamp(DateTime::from_secs(value as i64))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Timestamp, E> {
+                Self::to_timestamp(DateTime::from_secs(value))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Timestamp, E> {
+                Self::to_timestamp(DateTime::from_secs_f64(value))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Timestamp, E> {
+                let date_time = DateTime::from_str(value, Format::DateTime)
+                    .map_err(|e| E::custom(format!("invalid timestamp {}", e)))?;
+                Self::to_timestamp(date_time)
+            }
+        }
+
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+}
+
+struct AttributeValueSerializer;
+
+impl Serializer for AttributeValueSerializer {
+    type Ok = AttributeValue;
+    type Error = Error;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = VariantSerializer<ListSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, value: bool) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::Bool(value))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<AttributeValue, Error> {
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<AttributeValue, Error> {
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<AttributeValue, Error> {
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::N(value.to_string()))
+    }
+
+    fn serialize_i128(self, value: i128) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::N(value.to_string()))
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<AttributeValue, Error> {
+        self.serialize_u64(value as u64)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<AttributeValue, Error> {
+        self.serialize_u64(value as u64)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<AttributeValue, Error> {
+        self.serialize_u64(value as u64)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::N(value.to_string()))
+    }
+
+    fn serialize_u128(self, value: u128) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::N(value.to_string()))
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<AttributeValue, Error> {
+        self.serialize_f64(value as f64)
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<AttributeValue, Error> {
+        if !value.is_finite() {
+            return Err(ser::Error::custom(format!(
+                "{} is not a valid number",
+                value
+            )));
+        }
+        Ok(AttributeValue::N(value.to_string()))
+    }
+
+    fn serialize_char(self, value: char) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::S(value.to_string()))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::S(value.to_string()))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::B(Blob::new(value)))
+    }
+
+    fn serialize_none(self) -> Result<AttributeValue, Error> {
+        Err(Error::absent())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<AttributeValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::Null(true))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<AttributeValue, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<AttributeValue, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<AttributeValue, Error> {
+        let value = value.serialize(self)?;
+        match name {
+            STRING_SET => {
+                to_set(value, "string", |x| x.as_s().ok().cloned()).map(AttributeValue::Ss)
+            }
+            NUMBER_SET => {
+                to_set(value, "number", |x| x.as_n().ok().cloned()).map(AttributeValue::Ns)
+            }
+            BINARY_SET => {
+                to_set(value, "binary", |x| x.as_b().ok().cloned()).map(AttributeValue::Bs)
+            }
+            _ => Ok(value),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<AttributeValue, Error> {
+        let value = or_null(value.serialize(AttributeValueSerializer))
+            .map_err(|e| e.at(variant.to_string()))?;
+        Ok(AttributeValue::M(HashMap::from([(
+            variant.to_string(),
+            value,
+        )])))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ListSerializer, Error> {
+        Ok(ListSerializer(Vec::with_capacity(len.unwrap_or_default())))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ListSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<ListSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<ListSerializer>, Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: ListSerializer(Vec::with_capacity(len)),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer::default())
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<VariantSerializer<MapSerializer>, Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: MapSerializer::default(),
+        })
+    }
+}
+
+fn to_set<T: PartialEq>(
+    value: AttributeValue,
+    kind: &str,
+    element: impl Fn(&AttributeValue) -> Option<T>,
+) -> Result<Vec<T>, Error> {
+    let values = match value {
+        AttributeValue::L(values) => values,
+        _ => {
+            return Err(ser::Error::custom(format!(
+                "a {} set must be a sequence",
+                kind
+            )))
+        }
+    };
+    if values.is_empty() {
+        return Err(ser::Error::custom(format!(
+            "a {} set cannot be empty",
+            kind
+        )));
+    }
+
+    let mut set = Vec::with_capacity(values.len());
+    for (index, value) in values.iter().enumerate() {
+        let value = element(value).ok_or_else(|| {
+            Error::custom_at(format!("[{}]", index), format!("expected a {}", kind))
+        })?;
+        if !set.contains(&value) {
+            set.push(value);
+        }
+    }
+
+    Ok(set)
+}
+
+struct ListSerializer(Vec<AttributeValue>);
+
+impl ListSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let index = self.0.len();
+        let value = or_null(value.serialize(AttributeValueSerializer))
+            .map_err(|e| e.at(format!("[{}]", index)))?;
+        self.0.push(value);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = AttributeValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::L(self.0))
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = AttributeValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::L(self.0))
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = AttributeValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::L(self.0))
+    }
+}
+
+#[derive(Default)]
+struct MapSerializer {
+    values: HashMap<String, AttributeValue>,
+    key: Option<String>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        match value.serialize(AttributeValueSerializer) {
+            Ok(value) => {
+                self.values.insert(key, value);
+                Ok(())
+            }
+            Err(e) if e.absent => Ok(()),
+            Err(e) => Err(e.at(key)),
+        }
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = AttributeValue;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let key = match or_null(key.serialize(AttributeValueSerializer))? {
+            AttributeValue::S(key) => key,
+            AttributeValue::N(key) => key,
+            _ => return Err(ser::Error::custom("map keys must be strings or numbers")),
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ser::Error::custom("value serialized before key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::M(self.values))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = AttributeValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<AttributeValue, Error> {
+        Ok(AttributeValue::M(self.values))
+    }
+}
+
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn wrap(variant: &'static str, value: AttributeValue) -> AttributeValue {
+        AttributeValue::M(HashMap::from([(variant.to_string(), value)]))
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<ListSerializer> {
+    type Ok = AttributeValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let variant = self.variant;
+        self.inner
+            .push(value)
+            .map_err(|e| e.at(variant.to_string()))
+    }
+
+    fn end(self) -> Result<AttributeValue, Error> {
+        Ok(Self::wrap(self.variant, AttributeValue::L(self.inner.0)))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = AttributeValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let variant = self.variant;
+        self.inner
+            .insert(key.to_string(), value)
+            .map_err(|e| e.at(variant.to_string()))
+    }
+
+    fn end(self) -> Result<AttributeValue, Error> {
+        Ok(Self::wrap(
+            self.variant,
+            AttributeValue::M(self.inner.values),
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -62,7 +1017,7 @@ mod tests {
         let result = value.get_bool("my-bool").unwrap();
 
         // ASSERT
-        assert_eq!(true, result);
+        assert!(result);
         Ok(())
     }
 
@@ -79,4 +1034,349 @@ mod tests {
         assert_eq!(1, result);
         Ok(())
     }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Effect {
+        Allow,
+        Deny,
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Rule {
+        pk: String,
+        scopes: Vec<String>,
+        effect: Effect,
+        weight: Option<u32>,
+        ratio: f64,
+        tags: HashMap<String, String>,
+        #[serde(default)]
+        enabled: bool,
+    }
+
+    fn get_item() -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            ("pk".to_string(), AttributeValue::S("GET/one/".to_string())),
+            (
+                "scopes".to_string(),
+                AttributeValue::L(vec![AttributeValue::S("my-audience.read".to_string())]),
+            ),
+            ("effect".to_string(), AttributeValue::S("allow".to_string())),
+            ("weight".to_string(), AttributeValue::Null(true)),
+            ("ratio".to_string(), AttributeValue::N("0.5".to_string())),
+            (
+                "tags".to_string(),
+                AttributeValue::M(HashMap::from([(
+                    "team".to_string(),
+                    AttributeValue::S("payments".to_string()),
+                )])),
+            ),
+        ])
+    }
+
+    #[test]
+    fn deserialize_an_item() -> std::result::Result<(), Error> {
+        // ARRANGE
+        let mut item = get_item();
+        item.insert(
+            "scopes".to_string(),
+            AttributeValue::Ss(vec!["my-audience.read".to_string()]),
+        );
+
+        // ACT
+        let rule: Rule = from_item(&item)?;
+
+        // ASSERT
+        assert_eq!(
+            rule,
+            Rule {
+                pk: "GET/one/".to_string(),
+                scopes: vec!["my-audience.read".to_string()],
+                effect: Effect::Allow,
+                weight: None,
+                ratio: 0.5,
+                tags: HashMap::from([("team".to_string(), "payments".to_string())]),
+                enabled: false,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn report_the_path_of_the_invalid_attribute() -> std::result::Result<(), Error> {
+        // ARRANGE
+        let mut item = get_item();
+        item.insert(
+            "scopes".to_string(),
+            AttributeValue::L(vec![
+                AttributeValue::S("my-audience.read".to_string()),
+                AttributeValue::N("1".to_string()),
+            ]),
+        );
+
+        // ACT
+        let error = from_item::<Rule>(&item).unwrap_err();
+
+        // ASSERT
+        assert_eq!(error.path(), "scopes[1]");
+        assert!(error.to_string().starts_with("scopes[1]: invalid type"));
+        Ok(())
+    }
+
+    #[test]
+    fn report_the_missing_field() -> std::result::Result<(), Error> {
+        // ARRANGE
+        let mut item = get_item();
+        item.remove("scopes");
+
+        // ACT
+        let error = from_item::<Rule>(&item).unwrap_err();
+
+        // ASSERT
+        assert_eq!(error.to_string(), "missing field `scopes`");
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Grant {
+        Scope(String),
+        Range { from: i64, to: i64 },
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Record {
+        pk: String,
+        offset: i64,
+        ratio: f64,
+        tags: HashMap<String, String>,
+        scopes: StringSet,
+        weights: NumberSet<i64>,
+        secret: Binary,
+        expires_at: Timestamp,
+        grants: Vec<Grant>,
+        note: Option<String>,
+    }
+
+    fn get_record() -> Record {
+        Record {
+            pk: "GET/one/".to_string(),
+            offset: -42,
+            ratio: 0.25,
+            tags: HashMap::from([("team".to_string(), "payments".to_string())]),
+            scopes: StringSet(BTreeSet::from([
+                "my-audience.read".to_string(),
+                "my-audience.write".to_string(),
+            ])),
+            weights: NumberSet(vec![1, -2]),
+            secret: Binary(vec![0, 159, 146, 150]),
+            expires_at: Timestamp(
+                SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_665_000_000),
+            ),
+            grants: vec![
+                Grant::Scope("admin".to_string()),
+                Grant::Range { from: 1, to: 5 },
+            ],
+            note: None,
+        }
+    }
+
+    #[test]
+    fn serialize_and_read_back_an_item() -> std::result::Result<(), Error> {
+        // ARRANGE
+        let record = get_record();
+
+        // ACT
+        let item = to_item(&record)?;
+        let result: Record = from_item(&item)?;
+
+        // ASSERT
+        assert_eq!(result, record);
+        assert_eq!(item.get_i64("offset"), Some(-42));
+        assert_eq!(item.get_f64("ratio"), Some(0.25));
+        assert_eq!(
+            item.get_map("tags").unwrap().get_string("team"),
+            Some("payments".to_string())
+        );
+        assert_eq!(item.get_string_set("scopes"), Some(record.scopes.0));
+        assert_eq!(item.get_number_set("weights"), Some(vec![1.0, -2.0]));
+        assert_eq!(item.get_binary("secret"), Some(vec![0, 159, 146, 150]));
+        assert_eq!(item.get_timestamp("expires_at"), Some(record.expires_at.0));
+        assert_eq!(
+            item.get("expires_at"),
+            Some(&AttributeValue::N("1665000000".to_string()))
+        );
+        assert_eq!(item.get("note"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn write_the_timestamp_in_whole_seconds() -> std::result::Result<(), Error> {
+        // ARRANGE
+        let mut record = get_record();
+        record.expires_at =
+            Timestamp(SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(1_665_000_000_999));
+
+        // ACT
+        let item = to_item(&record)?;
+        let result: Record = from_item(&item)?;
+
+        // ASSERT
+        assert_eq!(
+            item.get("expires_at"),
+            Some(&AttributeValue::N("1665000000".to_string()))
+        );
+        assert_eq!(
+            result.expires_at.0,
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_665_000_000)
+        );
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Point(i64, i64),
+        Rectangle { width: u32, height: u32 },
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Nested {
+        label: Option<String>,
+        children: HashMap<String, Nested>,
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Everything {
+        small: i8,
+        large: i64,
+        unsigned: u64,
+        float: f32,
+        double: f64,
+        numbers: NumberSet<f64>,
+        blobs: BinarySet,
+        shapes: Vec<Shape>,
+        tree: Nested,
+        optional: Vec<Option<i64>>,
+    }
+
+    #[test]
+    fn serialize_and_read_back_every_kind_of_value() -> std::result::Result<(), Error> {
+        // ARRANGE
+        let everything = Everything {
+            small: -128,
+            large: i64::MIN,
+            unsigned: u64::MAX,
+            float: -1.5,
+            double: 1e-7,
+            numbers: NumberSet(vec![-0.5, 3.0, 1e21]),
+            blobs: BinarySet(vec![Binary(vec![1, 2]), Binary(vec![255])]),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(2.5),
+                Shape::Point(-1, 1),
+                Shape::Rectangle {
+                    width: 3,
+                    height: 4,
+                },
+            ],
+            tree: Nested {
+                label: Some("root".to_string()),
+                children: HashMap::from([(
+                    "leaf".to_string(),
+                    Nested {
+                        label: None,
+                        children: HashMap::new(),
+                    },
+                )]),
+            },
+            optional: vec![Some(1), None],
+        };
+
+        // ACT
+        let item = to_item(&everything)?;
+        let result: Everything = from_item(&item)?;
+
+        // ASSERT
+        assert_eq!(result, everything);
+        assert_eq!(item.get_i64("large"), Some(i64::MIN));
+        assert_eq!(
+            item.get("unsigned"),
+            Some(&AttributeValue::N(u64::MAX.to_string()))
+        );
+        assert_eq!(item.get_number_set("numbers"), Some(vec![-0.5, 3.0, 1e21]));
+        assert_eq!(
+            item.get("blobs"),
+            Some(&AttributeValue::Bs(vec![
+                Blob::new(vec![1, 2]),
+                Blob::new(vec![255])
+            ]))
+        );
+        let shapes = item.get("shapes").unwrap().as_l().unwrap();
+        assert_eq!(shapes[0], AttributeValue::S("Empty".to_string()));
+        assert_eq!(
+            shapes[3]
+                .as_m()
+                .unwrap()
+                .get_map("Rectangle")
+                .unwrap()
+                .get_i64("width"),
+            Some(3)
+        );
+        let leaf = item
+            .get_map("tree")
+            .unwrap()
+            .get_map("children")
+            .unwrap()
+            .get_map("leaf")
+            .unwrap();
+        assert!(!leaf.contains_key("label"));
+        assert_eq!(
+            item.get("optional"),
+            Some(&AttributeValue::L(vec![
+                AttributeValue::N("1".to_string()),
+                AttributeValue::Null(true)
+            ]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn read_a_timestamp_written_as_a_date_time() -> std::result::Result<(), Error> {
+        // ARRANGE
+        let item = HashMap::from([(
+            "expires_at".to_string(),
+            AttributeValue::S("2022-10-05T20:00:00Z".to_string()),
+        )]);
+
+        // ACT
+        let timestamp: Timestamp = from_attribute_value(item.get("expires_at").unwrap())?;
+
+        // ASSERT
+        let expected = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_665_000_000);
+        assert_eq!(timestamp.0, expected);
+        assert_eq!(item.get_timestamp("expires_at"), Some(expected));
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_when_the_value_is_not_an_item() -> std::result::Result<(), Error> {
+        // ARRANGE
+        let mut record = get_record();
+        record.scopes = StringSet::default();
+
+        // ACT
+        let not_a_map = to_item(&vec!["GET/one/"]).unwrap_err();
+        let empty_set = to_item(&record).unwrap_err();
+        let empty_binary_set = to_attribute_value(&BinarySet::default()).unwrap_err();
+
+        // ASSERT
+        assert_eq!(not_a_map.to_string(), "an item must serialize to a map");
+        assert_eq!(
+            empty_set.to_string(),
+            "scopes: a string set cannot be empty"
+        );
+        assert_eq!(empty_binary_set.to_string(), "a binary set cannot be empty");
+        Ok(())
+    }
 }
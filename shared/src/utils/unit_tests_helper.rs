@@ -2,6 +2,7 @@ use aws_config;
 use aws_sdk_dynamodb;
 use aws_smithy_client::{erase::DynConnector, test_connection::TestConnection};
 use aws_smithy_http::body::SdkBody;
+use aws_smithy_types::retry::RetryConfig;
 use http::Request;
 use lambda_http::Response;
 
@@ -66,4 +67,67 @@ impl UnitTestHelper {
             DynConnector::new(conn.clone()),
         )
     }
+
+    /// A client in `region` that fails on the first error, for failover tests.
+    pub async fn dynamo_fake_client_in_region(
+        conn: &TestConnection<SdkBody>,
+        region: &'static str,
+    ) -> aws_sdk_dynamodb::Client {
+        let cfg = aws_config::from_env()
+            .region(aws_sdk_dynamodb::Region::new(region))
+            .credentials_provider(aws_sdk_dynamodb::Credentials::new(
+                "accesskey",
+                "privatekey",
+                None,
+                None,
+                "dummy",
+            ))
+            .load()
+            .await;
+        let config = aws_sdk_dynamodb::config::Builder::from(&cfg)
+            .retry_config(RetryConfig::disabled())
+            .build();
+
+        aws_sdk_dynamodb::Client::from_conf_conn(config, DynConnector::new(conn.clone()))
+    }
+
+    pub async fn s3_fake_client(conn: &TestConnection<SdkBody>) -> aws_sdk_s3::Client {
+        let cfg = aws_config::from_env()
+            .region(aws_sdk_s3::Region::new("eu-central-1"))
+            .credentials_provider(aws_sdk_s3::Credentials::new(
+                "accesskey",
+                "privatekey",
+                None,
+                None,
+                "dummy",
+            ))
+            .load()
+            .await;
+
+        aws_sdk_s3::Client::from_conf_conn(
+            aws_sdk_s3::Config::new(&cfg),
+            DynConnector::new(conn.clone()),
+        )
+    }
+
+    pub async fn secretsmanager_fake_client(
+        conn: &TestConnection<SdkBody>,
+    ) -> aws_sdk_secretsmanager::Client {
+        let cfg = aws_config::from_env()
+            .region(aws_sdk_secretsmanager::Region::new("eu-central-1"))
+            .credentials_provider(aws_sdk_secretsmanager::Credentials::new(
+                "accesskey",
+                "privatekey",
+                None,
+                None,
+                "dummy",
+            ))
+            .load()
+            .await;
+
+        aws_sdk_secretsmanager::Client::from_conf_conn(
+            aws_sdk_secretsmanager::Config::new(&cfg),
+            DynConnector::new(conn.clone()),
+        )
+    }
 }